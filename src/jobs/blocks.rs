@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use worker::kv::{KvError, KvStore};
+use worker::{console_log, Date};
+
+use crate::jobs::recipes::list_keys;
+use crate::scraper::block::BlockReason;
+use crate::scraper::error::ScrapeError;
+use crate::utils::UtcTime;
+
+//Daily counters are kept for 30 days
+const BLOCK_STATS_TTL: u64 = 60 * 60 * 24 * 30;
+
+//How often a source was blocked on one UTC day, by reason
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BlockStats {
+    pub source: String,
+    pub day: String,
+    pub total: u64,
+    pub reasons: BTreeMap<String, u64>,
+    pub last_blocked_at: u64,
+}
+
+fn stats_prefix(source: &str) -> String {
+    format!("block_stats:{}:", source)
+}
+
+fn stats_key(source: &str, day: &str) -> String {
+    format!("{}{}", stats_prefix(source), day)
+}
+
+async fn record_block(kv: &KvStore, source: &str, reason: BlockReason) -> Result<(), KvError> {
+    let now = Date::now().as_millis();
+    let time = UtcTime::from_millis(now);
+    let day = format!("{:04}-{:02}-{:02}", time.year, time.month, time.day);
+    let key = stats_key(source, &day);
+    let mut stats = kv.get(&key).json::<BlockStats>().await?.unwrap_or_else(|| BlockStats {
+        source: source.to_string(),
+        day,
+        ..BlockStats::default()
+    });
+    stats.total += 1;
+    *stats.reasons.entry(reason.as_str().to_string()).or_insert(0) += 1;
+    stats.last_blocked_at = now;
+    kv.put(&key, serde_json::to_string(&stats)?)?.expiration_ttl(BLOCK_STATS_TTL).execute().await
+}
+
+//Counts the failure when it was a block. Monitoring must not fail the scrape, so storage errors are only logged
+pub async fn record_if_blocked(kv: &KvStore, source: &str, error: &ScrapeError) {
+    if let ScrapeError::Blocked(_, reason) = error {
+        if let Err(e) = record_block(kv, source, *reason).await {
+            console_log!("Unable to record block for {}: {}", source, e);
+        }
+    }
+}
+
+//Daily stats, oldest first, for one source or every source
+pub async fn list_block_stats(kv: &KvStore, source: Option<&str>) -> Result<Vec<BlockStats>, KvError> {
+    let prefix = match source {
+        Some(source) => stats_prefix(source),
+        None => "block_stats:".to_string(),
+    };
+    let mut stats = vec![];
+    for name in list_keys(kv, prefix).await? {
+        if let Some(day) = kv.get(&name).json::<BlockStats>().await? {
+            stats.push(day);
+        }
+    }
+    Ok(stats)
+}
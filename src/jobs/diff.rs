@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::scraper::recipe::Record;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangedRecord {
+    pub key: String,
+    pub before: Record,
+    pub after: Record,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RecordDiff {
+    pub added: Vec<Record>,
+    pub removed: Vec<Record>,
+    pub changed: Vec<ChangedRecord>,
+}
+
+impl RecordDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+//Records are matched by the value of `key_field` when it is set, otherwise by their whole content,
+//in which case an edited record shows up as one removal and one addition
+fn record_identity(record: &Record, key_field: Option<&str>) -> String {
+    if let Some(field) = key_field {
+        match record.get(field) {
+            Some(Value::String(value)) => return value.clone(),
+            Some(Value::Null) | None => {}
+            Some(value) => return value.to_string(),
+        }
+    }
+    serde_json::to_string(record).unwrap_or_default()
+}
+
+fn index_records(records: &[Record], key_field: Option<&str>) -> BTreeMap<String, Record> {
+    let mut index = BTreeMap::new();
+    for record in records {
+        //Keep the first record when several share an identity
+        index.entry(record_identity(record, key_field)).or_insert_with(|| record.clone());
+    }
+    index
+}
+
+pub fn diff_records(previous: &[Record], current: &[Record], key_field: Option<&str>) -> RecordDiff {
+    let before = index_records(previous, key_field);
+    let after = index_records(current, key_field);
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut diff = RecordDiff::default();
+    for key in keys {
+        match (before.get(key), after.get(key)) {
+            (None, Some(record)) => diff.added.push(record.clone()),
+            (Some(record), None) => diff.removed.push(record.clone()),
+            (Some(old), Some(new)) if old != new => diff.changed.push(ChangedRecord {
+                key: key.clone(),
+                before: old.clone(),
+                after: new.clone(),
+            }),
+            _ => {}
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        match value {
+            Value::Object(record) => record,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_by_key() {
+        let previous = vec![record(json!({"id": "a", "price": 1})), record(json!({"id": "b", "price": 2})), record(json!({"id": 3, "price": 3}))];
+        let current = vec![record(json!({"id": "a", "price": 1})), record(json!({"id": "b", "price": 5})), record(json!({"id": "c", "price": 4}))];
+        let diff = diff_records(&previous, &current, Some("id"));
+        assert_eq!(diff.added, vec![record(json!({"id": "c", "price": 4}))]);
+        assert_eq!(diff.removed, vec![record(json!({"id": 3, "price": 3}))]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].key, "b");
+        assert_eq!(diff.changed[0].before, record(json!({"id": "b", "price": 2})));
+        assert_eq!(diff.changed[0].after, record(json!({"id": "b", "price": 5})));
+        assert!(diff_records(&current, &current, Some("id")).is_empty());
+    }
+
+    #[test]
+    fn without_a_key_edits_are_a_removal_and_an_addition() {
+        let previous = vec![record(json!({"title": "Old"})), record(json!({"title": "Same"}))];
+        let current = vec![record(json!({"title": "Same"})), record(json!({"title": "New"}))];
+        let diff = diff_records(&previous, &current, None);
+        assert_eq!(diff.added, vec![record(json!({"title": "New"}))]);
+        assert_eq!(diff.removed, vec![record(json!({"title": "Old"}))]);
+        assert!(diff.changed.is_empty());
+        //Records missing the key fall back to their content
+        let diff = diff_records(&previous, &current, Some("id"));
+        assert_eq!((diff.added.len(), diff.removed.len()), (1, 1));
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use worker::kv::{KvError, KvStore};
+use worker::{console_log, Date, Env};
+
+use crate::jobs::blocks::record_if_blocked;
+use crate::jobs::profiles::get_profile;
+use crate::jobs::publishers::load_registry;
+use crate::jobs::schedule::CronSchedule;
+use crate::jobs::search_index::index_articles;
+use crate::jobs::taxonomies::load_taxonomy;
+use crate::jobs::tasks::{JobProgress, JobTask};
+use crate::scraper::enrich::{enrich, EnrichOptions, Taxonomy};
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::language::{keep_languages, language_filter};
+use crate::scraper::publishers::PublisherFilter;
+use crate::scraper::query::ResultQuery;
+use crate::scraper::scraper::Article;
+use crate::scraper::sources::{default_limit, run_source};
+use crate::utils::UtcTime;
+
+//KV namespace holding jobs and their snapshots
+pub const SCRAPES_KV: &str = "SCRAPES";
+
+//Snapshots are kept for 30 days
+const RUN_TTL: u64 = 60 * 60 * 24 * 30;
+
+//Run keys store an inverted timestamp so that listing returns the newest run first
+const MAX_TIMESTAMP: u64 = 9_999_999_999_999;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub owner: String,
+    pub source: String,
+    #[serde(default)]
+    pub params: HashMap<String, String>,
+    //Empty for one-shot jobs
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub schedule: String,
+    //One of the owner's request profiles to fetch with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    //Work of a one-shot job, which runs once in chunks instead of on a schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<JobTask>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<JobProgress>,
+    pub created_at: u64,
+    #[serde(default)]
+    pub last_run: Option<u64>,
+}
+
+impl Job {
+    fn summary(&self) -> JobSummary {
+        JobSummary {
+            owner: self.owner.clone(),
+            schedule: self.schedule.clone(),
+            active_task: self.task.is_some() && self.progress.as_ref().is_some_and(|progress| progress.is_active()),
+        }
+    }
+}
+
+//Kept as the metadata of a job's key, so the cron and listings can pick the jobs they need from the key listing
+//instead of reading every job
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobSummary {
+    pub owner: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub schedule: String,
+    //A one-shot job that is queued or running
+    #[serde(default)]
+    pub active_task: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JobRun {
+    pub job_id: String,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub articles: Vec<Article>,
+}
+
+pub fn job_key(id: &str) -> String {
+    format!("job:{}", id)
+}
+
+fn run_prefix(job_id: &str) -> String {
+    format!("run:{}:", job_id)
+}
+
+fn run_key(job_id: &str, timestamp: u64) -> String {
+    format!("{}{:013}", run_prefix(job_id), MAX_TIMESTAMP.saturating_sub(timestamp))
+}
+
+pub async fn get_job(kv: &KvStore, id: &str) -> Result<Option<Job>, KvError> {
+    kv.get(&job_key(id)).json::<Job>().await
+}
+
+pub async fn save_job(kv: &KvStore, job: &Job) -> Result<(), KvError> {
+    kv.put(&job_key(&job.id), serde_json::to_string(job)?)?.metadata(job.summary())?.execute().await
+}
+
+//Deletes the job definition, its snapshots expire on their own
+pub async fn delete_job(kv: &KvStore, id: &str) -> Result<(), KvError> {
+    kv.delete(&job_key(id)).await
+}
+
+//Reads the jobs whose summary passes `wanted`, following the key listing until it is exhausted. Jobs saved before
+//summaries were kept are read in full and saved again, so they have one next time
+pub async fn find_jobs(kv: &KvStore, wanted: impl Fn(&JobSummary) -> bool) -> Result<Vec<Job>, KvError> {
+    let mut jobs = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut list = kv.list().prefix("job:".to_string());
+        if let Some(cursor) = cursor {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        for key in page.keys {
+            let summary = key.metadata.and_then(|metadata| serde_json::from_value::<JobSummary>(metadata).ok());
+            if summary.as_ref().is_some_and(|summary| !wanted(summary)) {
+                continue;
+            }
+            let job = match kv.get(&key.name).json::<Job>().await? {
+                Some(job) => job,
+                None => continue,
+            };
+            if summary.is_none() {
+                save_job(kv, &job).await?;
+                if !wanted(&job.summary()) {
+                    continue;
+                }
+            }
+            jobs.push(job);
+        }
+        cursor = page.cursor;
+        if page.list_complete || cursor.is_none() {
+            break;
+        }
+    }
+    Ok(jobs)
+}
+
+//Returns a page of runs for a job, newest first, along with the cursor for the next page
+pub async fn list_runs(kv: &KvStore, job_id: &str, cursor: Option<String>, limit: u64) -> Result<(Vec<JobRun>, Option<String>), KvError> {
+    let mut list = kv.list().prefix(run_prefix(job_id)).limit(limit);
+    if let Some(cursor) = cursor {
+        list = list.cursor(cursor);
+    }
+    let page = list.execute().await?;
+    let mut runs = vec![];
+    for key in page.keys {
+        if let Some(run) = kv.get(&key.name).json::<JobRun>().await? {
+            runs.push(run);
+        }
+    }
+    let next = if page.list_complete { None } else { page.cursor };
+    Ok((runs, next))
+}
+
+//Called from the cron trigger, runs every job whose schedule matches the trigger time
+pub async fn run_due_jobs(env: &Env, scheduled_time: u64) {
+    let kv = match env.kv(SCRAPES_KV) {
+        Ok(kv) => kv,
+        Err(e) => {
+            console_log!("Unable to open {} namespace: {}", SCRAPES_KV, e);
+            return;
+        }
+    };
+    let now = UtcTime::from_millis(scheduled_time);
+    //Invalid schedules are read so they can be reported
+    let due = |summary: &JobSummary| {
+        !summary.schedule.is_empty() && CronSchedule::parse(&summary.schedule).map_or(true, |schedule| schedule.matches(&now))
+    };
+    let jobs = match find_jobs(&kv, due).await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            console_log!("Unable to list jobs: {}", e);
+            return;
+        }
+    };
+    let fetcher = Fetcher::from_env(env);
+    for job in jobs {
+        if job.task.is_some() {
+            continue;
+        }
+        let schedule = match CronSchedule::parse(&job.schedule) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                console_log!("Skipping job {} with invalid schedule: {}", job.id, e);
+                continue;
+            }
+        };
+        if !schedule.matches(&now) {
+            continue;
+        }
+        if let Err(e) = run_job(&kv, &fetcher, job).await {
+            console_log!("Unable to store job run: {}", e);
+        }
+    }
+}
+
+//The fetcher with the job's request profile applied
+pub async fn job_fetcher(kv: &KvStore, fetcher: &Fetcher, job: &Job) -> Result<Fetcher, ScrapeError> {
+    let name = match &job.profile {
+        Some(name) => name,
+        None => return Ok(fetcher.clone()),
+    };
+    match get_profile(kv, &job.owner, name).await {
+        Ok(Some(stored)) => Ok(fetcher.clone().with_profile(stored.profile)),
+        Ok(None) => Err(ScrapeError::InvalidInput(format!("Profile '{}' not found", name))),
+        Err(e) => Err(ScrapeError::Fetch(format!("Unable to load profile '{}': {}", name, e))),
+    }
+}
+
+//Runs a single job and persists the result as a timestamped snapshot
+pub async fn run_job(kv: &KvStore, fetcher: &Fetcher, mut job: Job) -> Result<JobRun, KvError> {
+    let started_at = Date::now().as_millis();
+    //Enrichments named in the params, checked when the job was created
+    let mut options = EnrichOptions::from_params(&job.params).unwrap_or_default();
+    let languages = language_filter(&job.params);
+    options.language |= languages.is_some();
+    let query = ResultQuery::from_params(&job.params, default_limit(&job.source)).unwrap_or_default();
+    options.sentiment |= query.needs_sentiment();
+    let publisher_filter = PublisherFilter::from_params(&job.params).unwrap_or_default();
+    let mut source_params = job.params.clone();
+    let narrowed = languages.is_some() || !publisher_filter.is_empty();
+    if let Some(limit) = query.source_limit(narrowed) {
+        source_params.insert("limit".to_string(), limit.to_string());
+    }
+    let taxonomy = match options.topic {
+        true => load_taxonomy(kv, &job.owner).await?,
+        false => Taxonomy::default(),
+    };
+    let registry = load_registry(kv, &job.owner).await?;
+    let result = match job_fetcher(kv, fetcher, &job).await {
+        Ok(fetcher) => match run_source(&fetcher, &job.source, &source_params).await {
+            Ok(mut articles) => {
+                registry.apply(&mut articles);
+                publisher_filter.apply(&mut articles, &registry);
+                enrich(&fetcher, &mut articles, &options, &taxonomy).await;
+                if let Some(languages) = &languages {
+                    keep_languages(&mut articles, languages);
+                }
+                Ok(query.select_articles(articles).0)
+            }
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let finished_at = Date::now().as_millis();
+    let run = match result {
+        Ok(articles) => JobRun {
+            job_id: job.id.clone(),
+            started_at,
+            finished_at,
+            status: "ok".to_string(),
+            error: None,
+            articles,
+        },
+        Err(e) => {
+            record_if_blocked(kv, &job.source, &e).await;
+            let status = match e {
+                ScrapeError::Blocked(_, _) => "blocked",
+                _ => "error",
+            };
+            JobRun {
+                job_id: job.id.clone(),
+                started_at,
+                finished_at,
+                status: status.to_string(),
+                error: Some(e.to_string()),
+                articles: vec![],
+            }
+        }
+    };
+    kv.put(&run_key(&job.id, started_at), serde_json::to_string(&run)?)?
+        .expiration_ttl(RUN_TTL)
+        .execute()
+        .await?;
+    //A failed index update should not fail the run, the snapshot is already stored
+    if !run.articles.is_empty() {
+        if let Err(e) = index_articles(kv, &job.owner, &job.source, &run.articles, started_at).await {
+            console_log!("Unable to index run of job {}: {}", job.id, e);
+        }
+    }
+    job.last_run = Some(started_at);
+    save_job(kv, &job).await?;
+    Ok(run)
+}
@@ -0,0 +1,12 @@
+pub mod job;
+pub mod schedule;
+pub mod diff;
+pub mod webhook;
+pub mod watches;
+pub mod recipes;
+pub mod profiles;
+pub mod blocks;
+pub mod tasks;
+pub mod taxonomies;
+pub mod search_index;
+pub mod publishers;
\ No newline at end of file
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use worker::kv::{KvError, KvStore};
+
+use crate::jobs::recipes::list_keys;
+use crate::scraper::profile::RequestProfile;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredProfile {
+    pub name: String,
+    pub owner: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub profile: RequestProfile,
+}
+
+fn profile_prefix(owner: &str) -> String {
+    format!("profile:{}:", owner)
+}
+
+fn profile_key(owner: &str, name: &str) -> String {
+    format!("{}{}", profile_prefix(owner), name)
+}
+
+pub async fn get_profile(kv: &KvStore, owner: &str, name: &str) -> Result<Option<StoredProfile>, KvError> {
+    kv.get(&profile_key(owner, name)).json::<StoredProfile>().await
+}
+
+pub async fn save_profile(kv: &KvStore, profile: &StoredProfile) -> Result<(), KvError> {
+    kv.put(&profile_key(&profile.owner, &profile.name), serde_json::to_string(profile)?)?.execute().await
+}
+
+pub async fn delete_profile(kv: &KvStore, owner: &str, name: &str) -> Result<(), KvError> {
+    kv.delete(&profile_key(owner, name)).await
+}
+
+//Profiles belonging to one user
+pub async fn list_profiles(kv: &KvStore, owner: &str) -> Result<Vec<StoredProfile>, KvError> {
+    let mut profiles = vec![];
+    for name in list_keys(kv, profile_prefix(owner)).await? {
+        if let Some(profile) = kv.get(&name).json::<StoredProfile>().await? {
+            profiles.push(profile);
+        }
+    }
+    Ok(profiles)
+}
@@ -0,0 +1,24 @@
+use worker::kv::{KvError, KvStore};
+
+use crate::scraper::publishers::{Publisher, PublisherRegistry};
+
+fn publishers_key(owner: &str) -> String {
+    format!("publishers:{}", owner)
+}
+
+pub async fn get_publishers(kv: &KvStore, owner: &str) -> Result<Vec<Publisher>, KvError> {
+    Ok(kv.get(&publishers_key(owner)).json::<Vec<Publisher>>().await?.unwrap_or_default())
+}
+
+//The built-in registry with the user's own publishers on top
+pub async fn load_registry(kv: &KvStore, owner: &str) -> Result<PublisherRegistry, KvError> {
+    Ok(PublisherRegistry::new(&get_publishers(kv, owner).await?))
+}
+
+pub async fn save_publishers(kv: &KvStore, owner: &str, publishers: &[Publisher]) -> Result<(), KvError> {
+    kv.put(&publishers_key(owner), serde_json::to_string(publishers)?)?.execute().await
+}
+
+pub async fn delete_publishers(kv: &KvStore, owner: &str) -> Result<(), KvError> {
+    kv.delete(&publishers_key(owner)).await
+}
@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, HashMap};
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use worker::kv::{KvError, KvStore};
+
+use crate::scraper::recipe::{Record, Recipe};
+
+const MAX_NAME_LENGTH: usize = 64;
+
+//Type a field must have in a recipe's output schema
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Url,
+}
+
+//The versioned part of a stored recipe
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecipeDefinition {
+    //Page to scrape, `{param}` placeholders are filled from the run's query string
+    pub url_template: String,
+    //Values for placeholders the caller leaves out
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+    pub recipe: Recipe,
+    //When set, records are reduced to these fields and the ones that don't fit are rejected
+    #[serde(default)]
+    pub schema: BTreeMap<String, FieldType>,
+    //One of the owner's request profiles, used when the run does not pick its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredRecipe {
+    pub name: String,
+    pub owner: String,
+    pub version: u32,
+    //GitHub logins allowed to read and run the recipe
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub definition: RecipeDefinition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecipeVersion {
+    pub version: u32,
+    pub created_at: u64,
+    pub definition: RecipeDefinition,
+}
+
+impl StoredRecipe {
+    pub fn can_read(&self, user: &str) -> bool {
+        self.owner == user || self.shared_with.iter().any(|shared| shared == user)
+    }
+}
+
+//Names of stored recipes and profiles, `kind` is used in the error message
+pub fn validate_name(kind: &str, name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(format!("{} names must be 1 to {} characters", kind, MAX_NAME_LENGTH));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("{} names may only contain letters, digits, '-' and '_'", kind));
+    }
+    Ok(())
+}
+
+//Percent encodes everything but unreserved characters so a param cannot change the URL's structure
+fn encode_param(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+//Splits a template into literal text and placeholder names
+fn template_parts(template: &str) -> Result<Vec<(bool, String)>, String> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        parts.push((false, rest[..start].to_string()));
+        let end = rest[start..].find('}').ok_or_else(|| "Unclosed '{' in url_template".to_string())? + start;
+        let name = rest[start + 1..end].trim();
+        if name.is_empty() {
+            return Err("Empty placeholder in url_template".to_string());
+        }
+        parts.push((true, name.to_string()));
+        rest = &rest[end + 1..];
+    }
+    parts.push((false, rest.to_string()));
+    Ok(parts)
+}
+
+impl RecipeDefinition {
+    pub fn validate(&self) -> Result<(), String> {
+        self.recipe.validate()?;
+        if let Some(profile) = &self.profile {
+            validate_name("Profile", profile)?;
+        }
+        //Every placeholder gets a stand-in so the rest of the template can be checked as a URL
+        let placeholders: HashMap<String, String> = template_parts(&self.url_template)?
+            .into_iter()
+            .filter(|(placeholder, _)| *placeholder)
+            .map(|(_, name)| (name, "x".to_string()))
+            .collect();
+        self.render_url(&placeholders).map(|_| ())
+    }
+
+    //Origin every rendered URL shares, when the scheme, host and port all come before the first placeholder. None
+    //when a placeholder can pick the host
+    pub fn fixed_origin(&self) -> Option<String> {
+        let parts = template_parts(&self.url_template).ok()?;
+        let prefix = &parts.first()?.1;
+        let (_, authority) = prefix.split_once("://")?;
+        if parts.len() > 1 && !authority.contains(['/', '?', '#']) {
+            return None;
+        }
+        Url::parse(prefix).ok().map(|url| url.origin().ascii_serialization())
+    }
+
+    pub fn render_url(&self, params: &HashMap<String, String>) -> Result<String, String> {
+        let mut url = String::new();
+        for (placeholder, text) in template_parts(&self.url_template)? {
+            if !placeholder {
+                url.push_str(&text);
+                continue;
+            }
+            match params.get(&text).or_else(|| self.defaults.get(&text)) {
+                Some(value) => url.push_str(&encode_param(value)),
+                None => return Err(format!("Missing param '{}'", text)),
+            }
+        }
+        let parsed = Url::parse(&url).map_err(|e| format!("Invalid url '{}': {}", url, e))?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err(format!("Unsupported url scheme '{}'", parsed.scheme()));
+        }
+        Ok(url)
+    }
+}
+
+fn coerce(value: &Value, field_type: FieldType) -> Option<Value> {
+    let text = match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Null => return Some(Value::Null),
+        other => other.to_string(),
+    };
+    if text.is_empty() {
+        return Some(Value::Null);
+    }
+    match field_type {
+        FieldType::String => Some(Value::String(text)),
+        FieldType::Number => text.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number),
+        FieldType::Integer => text.parse::<i64>().ok().map(Value::from),
+        FieldType::Boolean => match text.to_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(Value::Bool(true)),
+            "false" | "no" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        FieldType::Url => Url::parse(&text).ok().map(|url| Value::String(url.to_string())),
+    }
+}
+
+//Shapes records to the schema, returning the ones that fit and how many were rejected
+pub fn apply_schema(records: Vec<Record>, schema: &BTreeMap<String, FieldType>) -> (Vec<Record>, usize) {
+    if schema.is_empty() {
+        return (records, 0);
+    }
+    let total = records.len();
+    let shaped: Vec<Record> = records
+        .into_iter()
+        .filter_map(|record| {
+            let mut shaped = Record::new();
+            for (name, field_type) in schema {
+                let value = coerce(record.get(name).unwrap_or(&Value::Null), *field_type)?;
+                shaped.insert(name.clone(), value);
+            }
+            Some(shaped)
+        })
+        .collect();
+    let rejected = total - shaped.len();
+    (shaped, rejected)
+}
+
+fn recipe_key(owner: &str, name: &str) -> String {
+    format!("recipe:{}:{}", owner, name)
+}
+
+fn version_prefix(owner: &str, name: &str) -> String {
+    format!("recipe_version:{}:{}:", owner, name)
+}
+
+fn version_key(owner: &str, name: &str, version: u32) -> String {
+    format!("{}{:06}", version_prefix(owner, name), version)
+}
+
+pub async fn get_recipe(kv: &KvStore, owner: &str, name: &str) -> Result<Option<StoredRecipe>, KvError> {
+    kv.get(&recipe_key(owner, name)).json::<StoredRecipe>().await
+}
+
+pub async fn get_recipe_version(kv: &KvStore, owner: &str, name: &str, version: u32) -> Result<Option<RecipeVersion>, KvError> {
+    kv.get(&version_key(owner, name, version)).json::<RecipeVersion>().await
+}
+
+//Stores the recipe and keeps a copy of its current definition as a version
+pub async fn save_recipe(kv: &KvStore, recipe: &StoredRecipe) -> Result<(), KvError> {
+    let version = RecipeVersion {
+        version: recipe.version,
+        created_at: recipe.updated_at,
+        definition: recipe.definition.clone(),
+    };
+    kv.put(&version_key(&recipe.owner, &recipe.name, recipe.version), serde_json::to_string(&version)?)?.execute().await?;
+    kv.put(&recipe_key(&recipe.owner, &recipe.name), serde_json::to_string(recipe)?)?.execute().await
+}
+
+//Updates sharing without creating a new version
+pub async fn save_recipe_sharing(kv: &KvStore, recipe: &StoredRecipe) -> Result<(), KvError> {
+    kv.put(&recipe_key(&recipe.owner, &recipe.name), serde_json::to_string(recipe)?)?.execute().await
+}
+
+pub async fn list_keys(kv: &KvStore, prefix: String) -> Result<Vec<String>, KvError> {
+    let mut names = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut list = kv.list().prefix(prefix.clone());
+        if let Some(cursor) = cursor {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        names.extend(page.keys.into_iter().map(|entry| entry.name));
+        cursor = page.cursor;
+        if page.list_complete || cursor.is_none() {
+            break;
+        }
+    }
+    Ok(names)
+}
+
+//Deletes the recipe along with every stored version
+pub async fn delete_recipe(kv: &KvStore, owner: &str, name: &str) -> Result<(), KvError> {
+    for version in list_keys(kv, version_prefix(owner, name)).await? {
+        kv.delete(&version).await?;
+    }
+    kv.delete(&recipe_key(owner, name)).await
+}
+
+pub async fn list_recipes(kv: &KvStore) -> Result<Vec<StoredRecipe>, KvError> {
+    let mut recipes = vec![];
+    for name in list_keys(kv, "recipe:".to_string()).await? {
+        if let Some(recipe) = kv.get(&name).json::<StoredRecipe>().await? {
+            recipes.push(recipe);
+        }
+    }
+    Ok(recipes)
+}
+
+//Every version of a recipe, oldest first
+pub async fn list_recipe_versions(kv: &KvStore, owner: &str, name: &str) -> Result<Vec<RecipeVersion>, KvError> {
+    let mut versions = vec![];
+    for key_name in list_keys(kv, version_prefix(owner, name)).await? {
+        if let Some(version) = kv.get(&key_name).json::<RecipeVersion>().await? {
+            versions.push(version);
+        }
+    }
+    Ok(versions)
+}
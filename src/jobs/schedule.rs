@@ -0,0 +1,158 @@
+use crate::utils::UtcTime;
+
+//A parsed five field cron expression (minute hour day-of-month month day-of-week)
+//Each field is stored as a bitmask of the values it matches
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<CronSchedule, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields, found {}", fields.len()));
+        }
+        //Sunday can be written as either 0 or 7
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    pub fn matches(&self, time: &UtcTime) -> bool {
+        let day_matches = self.days & (1 << time.day) != 0;
+        let weekday_matches = self.weekdays & (1 << time.weekday) != 0;
+        //When both day fields are restricted, cron runs if either of them matches
+        let day_ok = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day_matches,
+            (true, false) => weekday_matches,
+            (false, false) => day_matches || weekday_matches,
+        };
+        self.minutes & (1 << time.minute) != 0
+            && self.hours & (1 << time.hour) != 0
+            && self.months & (1 << time.month) != 0
+            && day_ok
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<u32>().map_err(|_| format!("Invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("Invalid step in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, min, max)?, parse_value(end, min, max)?)
+        } else {
+            let value = parse_value(range, min, max)?;
+            //"5/10" means every 10 starting at 5
+            if step > 1 { (value, max) } else { (value, value) }
+        };
+        if start > end {
+            return Err(format!("Invalid range '{}'", range));
+        }
+        let mut value = start;
+        while value <= end {
+            mask |= 1 << value;
+            value += step;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(value) if value >= min && value <= max => Ok(value),
+        _ => Err(format!("Value '{}' must be between {} and {}", value, min, max)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //2024-01-01 was a Monday
+    fn time(day: u32, hour: u32, minute: u32) -> UtcTime {
+        UtcTime::from_millis(1_704_067_200_000 + ((day as u64 - 1) * 86400 + hour as u64 * 3600 + minute as u64 * 60) * 1000)
+    }
+
+    #[test]
+    fn parses_fields_steps_and_ranges() {
+        let schedule = CronSchedule::parse("*/15 9-17 * * *").unwrap();
+        assert!(schedule.matches(&time(1, 9, 0)));
+        assert!(schedule.matches(&time(1, 17, 45)));
+        assert!(!schedule.matches(&time(1, 9, 10)));
+        assert!(!schedule.matches(&time(1, 18, 0)));
+
+        let schedule = CronSchedule::parse("5/20 0 * * *").unwrap();
+        assert!(schedule.matches(&time(1, 0, 5)));
+        assert!(schedule.matches(&time(1, 0, 45)));
+        assert!(!schedule.matches(&time(1, 0, 0)));
+    }
+
+    #[test]
+    fn expands_aliases() {
+        assert_eq!(CronSchedule::parse("@daily"), CronSchedule::parse("0 0 * * *"));
+        assert_eq!(CronSchedule::parse("@weekly"), CronSchedule::parse("0 0 * * 0"));
+        assert!(CronSchedule::parse("@hourly").unwrap().matches(&time(3, 7, 0)));
+    }
+
+    #[test]
+    fn treats_seven_as_sunday() {
+        let schedule = CronSchedule::parse("0 0 * * 7").unwrap();
+        assert!(schedule.matches(&time(7, 0, 0)));
+        assert!(!schedule.matches(&time(1, 0, 0)));
+    }
+
+    #[test]
+    fn runs_when_either_day_field_matches() {
+        //The 15th, or any Monday
+        let schedule = CronSchedule::parse("0 12 15 * 1").unwrap();
+        assert!(schedule.matches(&time(15, 12, 0)));
+        assert!(schedule.matches(&time(8, 12, 0)));
+        assert!(!schedule.matches(&time(9, 12, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("10-5 * * * *").is_err());
+        assert!(CronSchedule::parse("0 0 0 * *").is_err());
+    }
+}
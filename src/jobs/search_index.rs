@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use worker::kv::{KvError, KvStore};
+
+use crate::scraper::dedup::{fingerprint, fnv1a};
+use crate::scraper::scraper::Article;
+use crate::scraper::search::{bm25, document_terms, published_millis, snippet, SearchQuery};
+
+//Indexed articles are searchable for as long as job snapshots are kept
+const INDEX_TTL: u64 = 60 * 60 * 24 * 30;
+//Oldest articles are dropped from the index beyond this
+pub const MAX_INDEXED: usize = 5000;
+//Postings are spread over this many KV values by the hash of their term
+const SHARDS: u64 = 32;
+
+//Every live document of an owner's index. Postings of documents missing here are stale and skipped
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct IndexManifest {
+    docs: HashMap<String, IndexedDoc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IndexedDoc {
+    //Milliseconds when it was indexed, also the key of the batch holding the article
+    batch: u64,
+    source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    published: Option<u64>,
+    length: u32,
+}
+
+impl IndexedDoc {
+    //`from` and `to` apply to the publication time, or to when the article was scraped if it has none
+    fn time(&self) -> u64 {
+        self.published.unwrap_or(self.batch)
+    }
+}
+
+//Term to (document, weighted frequency)
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct IndexShard {
+    postings: HashMap<String, Vec<(String, u32)>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    pub score: f64,
+    pub source: String,
+    pub indexed_at: u64,
+    //HTML escaped text around the matches, which are wrapped in <mark>
+    pub snippet: String,
+    pub article: Article,
+}
+
+fn manifest_key(owner: &str) -> String {
+    format!("search:{}:manifest", owner)
+}
+
+fn shard_key(owner: &str, shard: u64) -> String {
+    format!("search:{}:shard:{:02}", owner, shard)
+}
+
+fn batch_key(owner: &str, batch: u64) -> String {
+    format!("search:{}:batch:{}", owner, batch)
+}
+
+fn shard_of(term: &str) -> u64 {
+    fnv1a(term) % SHARDS
+}
+
+async fn load_manifest(kv: &KvStore, owner: &str) -> Result<IndexManifest, KvError> {
+    Ok(kv.get(&manifest_key(owner)).json::<IndexManifest>().await?.unwrap_or_default())
+}
+
+async fn load_shard(kv: &KvStore, owner: &str, shard: u64) -> Result<IndexShard, KvError> {
+    Ok(kv.get(&shard_key(owner, shard)).json::<IndexShard>().await?.unwrap_or_default())
+}
+
+//Adds the articles of a snapshot to the owner's index and returns how many were new. Articles are identified by
+//their canonical link, so one seen in an earlier snapshot keeps its first entry. Expired and overflowing documents
+//are dropped from the manifest, their postings go when their shard is next written
+//The manifest and shards are read, changed and written back without any locking, as KV has no transactions. Two runs
+//indexing for the same owner at once can overwrite each other, losing the articles of one of them from the manifest
+//or from a shard. Job runs are indexed one after another within a cron invocation, so this only happens when two
+//invocations overlap, and lost articles stay readable in their runs
+pub async fn index_articles(kv: &KvStore, owner: &str, source: &str, articles: &[Article], now: u64) -> Result<usize, KvError> {
+    let mut manifest = load_manifest(kv, owner).await?;
+    let before = manifest.docs.len();
+    manifest.docs.retain(|_, doc| doc.batch + INDEX_TTL * 1000 > now);
+    let expired = before != manifest.docs.len();
+    let mut batch: HashMap<String, Article> = HashMap::new();
+    let mut additions: BTreeMap<u64, Vec<(String, String, u32)>> = BTreeMap::new();
+    for article in articles {
+        let id = fingerprint(article).url;
+        if manifest.docs.contains_key(&id) || batch.contains_key(&id) {
+            continue;
+        }
+        let (frequencies, length) = document_terms(article);
+        if length == 0 {
+            continue;
+        }
+        for (term, frequency) in frequencies {
+            additions.entry(shard_of(&term)).or_default().push((term, id.clone(), frequency));
+        }
+        manifest.docs.insert(id.clone(), IndexedDoc { batch: now, source: source.to_string(), published: published_millis(article), length });
+        batch.insert(id, article.clone());
+    }
+    if batch.is_empty() {
+        if expired {
+            kv.put(&manifest_key(owner), serde_json::to_string(&manifest)?)?.execute().await?;
+        }
+        return Ok(0);
+    }
+    if manifest.docs.len() > MAX_INDEXED {
+        let mut oldest: Vec<(u64, String)> = manifest.docs.iter().map(|(id, doc)| (doc.batch, id.clone())).collect();
+        oldest.sort();
+        for (_, id) in oldest.into_iter().take(manifest.docs.len() - MAX_INDEXED) {
+            manifest.docs.remove(&id);
+        }
+    }
+    //The manifest goes last so a search never sees a document whose postings or article are not written yet
+    kv.put(&batch_key(owner, now), serde_json::to_string(&batch)?)?.expiration_ttl(INDEX_TTL).execute().await?;
+    for (shard, postings) in additions {
+        let mut stored = load_shard(kv, owner, shard).await?;
+        for list in stored.postings.values_mut() {
+            list.retain(|(doc, _)| manifest.docs.contains_key(doc));
+        }
+        stored.postings.retain(|_, list| !list.is_empty());
+        for (term, doc, frequency) in postings {
+            if manifest.docs.contains_key(&doc) {
+                stored.postings.entry(term).or_default().push((doc, frequency));
+            }
+        }
+        kv.put(&shard_key(owner, shard), serde_json::to_string(&stored)?)?.execute().await?;
+    }
+    kv.put(&manifest_key(owner), serde_json::to_string(&manifest)?)?.execute().await?;
+    Ok(batch.len())
+}
+
+//Ranks the owner's indexed articles against the query with BM25 and returns the requested page of hits and how many
+//articles matched in total
+pub async fn search_articles(kv: &KvStore, owner: &str, query: &SearchQuery) -> Result<(Vec<SearchHit>, usize), KvError> {
+    let manifest = load_manifest(kv, owner).await?;
+    if manifest.docs.is_empty() {
+        return Ok((vec![], 0));
+    }
+    let average_length = manifest.docs.values().map(|doc| doc.length as f64).sum::<f64>() / manifest.docs.len() as f64;
+    let shards: Vec<u64> = query.terms.iter().map(|term| shard_of(term)).collect::<HashSet<_>>().into_iter().collect();
+    let loaded = join_all(shards.iter().map(|shard| load_shard(kv, owner, *shard))).await;
+    let mut postings: HashMap<u64, IndexShard> = HashMap::new();
+    for (shard, loaded) in shards.into_iter().zip(loaded) {
+        postings.insert(shard, loaded?);
+    }
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+    for term in &query.terms {
+        let list = match postings.get(&shard_of(term)).and_then(|shard| shard.postings.get(term)) {
+            Some(list) => list,
+            None => continue,
+        };
+        let live: Vec<(&str, u32, &IndexedDoc)> = list
+            .iter()
+            .filter_map(|(id, frequency)| manifest.docs.get(id).map(|doc| (id.as_str(), *frequency, doc)))
+            .collect();
+        //Document frequency counts the whole index so filters do not change the ranking
+        for (id, frequency, doc) in &live {
+            if query.keeps(&doc.source, doc.time()) {
+                let weight = bm25(*frequency as f64, live.len(), manifest.docs.len(), doc.length as f64, average_length);
+                *scores.entry(id).or_insert(0.0) += weight;
+            }
+        }
+    }
+    let mut ranked: Vec<(&str, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(b.0)));
+    let total = ranked.len();
+    let page: Vec<(&str, f64)> = ranked.into_iter().skip(query.offset).take(query.limit).collect();
+    let batches: Vec<u64> = page.iter().map(|(id, _)| manifest.docs[*id].batch).collect::<HashSet<_>>().into_iter().collect();
+    let loaded = join_all(batches.iter().map(|batch| kv.get(&batch_key(owner, *batch)).json::<HashMap<String, Article>>())).await;
+    let mut articles: HashMap<String, Article> = HashMap::new();
+    for batch in loaded {
+        articles.extend(batch?.unwrap_or_default());
+    }
+    let terms: HashSet<String> = query.terms.iter().cloned().collect();
+    let hits = page
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let doc = &manifest.docs[id];
+            let article = articles.remove(id)?;
+            let text = article.body.as_deref().and_then(|body| snippet(body, &terms));
+            let snippet = text.or_else(|| snippet(&article.title, &terms)).unwrap_or_default();
+            Some(SearchHit {
+                score: (score * 1000.0).round() / 1000.0,
+                source: doc.source.clone(),
+                indexed_at: doc.batch,
+                snippet,
+                article,
+            })
+        })
+        .collect();
+    Ok((hits, total))
+}
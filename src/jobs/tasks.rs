@@ -0,0 +1,408 @@
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use worker::kv::{KvError, KvStore};
+use worker::{console_log, Date, Env};
+
+use crate::jobs::job::{find_jobs, get_job, job_fetcher, save_job, Job, SCRAPES_KV};
+use crate::scraper::block::BlockReason;
+use crate::scraper::crawl::{crawl_pages, CrawlOptions, CrawlPlan, CrawlResult, CrawlState};
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::recipe::{Record, Recipe};
+use crate::scraper::scraper::scrape_many;
+
+//Results are kept for 30 days, like the runs of scheduled jobs
+const TASK_TTL: u64 = 60 * 60 * 24 * 30;
+//Most URLs a batch task may list and pages a crawl task may visit
+pub const MAX_TASK_URLS: usize = 1000;
+pub const MAX_TASK_PAGES: usize = 1000;
+//Pages handled per chunk, each chunk's results are stored under one key
+const BATCH_CHUNK: usize = 10;
+const BATCH_CONCURRENCY: usize = 5;
+const CRAWL_CHUNK: usize = 20;
+//Time each scheduled invocation spends on one-shot jobs, a chunk that has started is always finished
+const TASK_BUDGET_MS: u64 = 20_000;
+//How long an invocation holds a task without renewing its lease. It is renewed after every chunk, and a chunk that
+//has started is always finished, so this leaves room for one chunk past the budget
+const LEASE_MS: u64 = 60_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobTask {
+    //Applies the recipe to every URL
+    Batch { urls: Vec<String>, recipe: Recipe },
+    //A crawl as for /api/crawl, without its time budget and with up to MAX_TASK_PAGES pages
+    Crawl(CrawlOptions),
+}
+
+impl JobTask {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobTask::Batch { .. } => "batch",
+            JobTask::Crawl(_) => "crawl",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            JobTask::Batch { urls, recipe } => {
+                if urls.is_empty() || urls.len() > MAX_TASK_URLS {
+                    return Err(format!("A batch task needs 1 to {} urls", MAX_TASK_URLS));
+                }
+                recipe.validate()
+            }
+            JobTask::Crawl(options) => CrawlPlan::new(options).map(|_| ()).map_err(|e| e.to_string()),
+        }
+    }
+
+    //Pages known up front, a crawl discovers more as it goes
+    fn initial_total(&self) -> usize {
+        match self {
+            JobTask::Batch { urls, .. } => urls.len(),
+            JobTask::Crawl(_) => 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobProgress {
+    pub status: TaskStatus,
+    //Pages known so far, exact for batches and growing as a crawl finds links
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub records: usize,
+    //Result chunks stored so far
+    pub chunks: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+impl JobProgress {
+    pub fn queued(task: &JobTask) -> Self {
+        JobProgress {
+            status: TaskStatus::Queued,
+            total: task.initial_total(),
+            completed: 0,
+            failed: 0,
+            records: 0,
+            chunks: 0,
+            error: None,
+            finished_at: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status == TaskStatus::Queued || self.status == TaskStatus::Running
+    }
+}
+
+//What a one-shot job got from one page
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PageResult {
+    pub url: String,
+    //ok, error or blocked
+    pub status: String,
+    pub records: Vec<Record>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<BlockReason>,
+}
+
+impl PageResult {
+    fn new(url: String, result: Result<Vec<Record>, ScrapeError>) -> Self {
+        match result {
+            Ok(records) => PageResult {
+                url,
+                status: "ok".to_string(),
+                records,
+                error: None,
+                reason: None,
+            },
+            Err(e) => {
+                let (status, reason) = match &e {
+                    ScrapeError::Blocked(_, reason) => ("blocked", Some(*reason)),
+                    _ => ("error", None),
+                };
+                PageResult {
+                    url,
+                    status: status.to_string(),
+                    records: vec![],
+                    error: Some(e.to_string()),
+                    reason,
+                }
+            }
+        }
+    }
+}
+
+//Where a task has got to between invocations
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct TaskState {
+    //Index of the next URL of a batch
+    #[serde(default)]
+    next: usize,
+    #[serde(default)]
+    crawl: Option<CrawlState>,
+    //The invocation advancing the task, others leave it alone until the lease runs out
+    #[serde(default)]
+    lease: Option<TaskLease>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct TaskLease {
+    //Random id of the cron invocation
+    holder: String,
+    expires_at: u64,
+}
+
+impl TaskState {
+    fn is_leased_to_other(&self, holder: &str, now: u64) -> bool {
+        self.lease.as_ref().is_some_and(|lease| lease.holder != holder && lease.expires_at > now)
+    }
+
+    fn lease_to(&mut self, holder: &str) {
+        self.lease = Some(TaskLease { holder: holder.to_string(), expires_at: Date::now().as_millis() + LEASE_MS });
+    }
+}
+
+struct Chunk {
+    pages: Vec<PageResult>,
+    total: usize,
+    finished: bool,
+}
+
+fn state_key(job_id: &str) -> String {
+    format!("task_state:{}", job_id)
+}
+
+fn result_prefix(job_id: &str) -> String {
+    format!("task_result:{}:", job_id)
+}
+
+fn result_key(job_id: &str, chunk: u32) -> String {
+    format!("{}{:06}", result_prefix(job_id), chunk)
+}
+
+//Pages through a one-shot job's results in the order they were produced, `limit` chunks at a time
+pub async fn list_results(kv: &KvStore, job_id: &str, cursor: Option<String>, limit: u64) -> Result<(Vec<PageResult>, Option<String>), KvError> {
+    let mut list = kv.list().prefix(result_prefix(job_id)).limit(limit);
+    if let Some(cursor) = cursor {
+        list = list.cursor(cursor);
+    }
+    let page = list.execute().await?;
+    let mut results = vec![];
+    for key in page.keys {
+        if let Some(chunk) = kv.get(&key.name).json::<Vec<PageResult>>().await? {
+            results.extend(chunk);
+        }
+    }
+    let next = if page.list_complete { None } else { page.cursor };
+    Ok((results, next))
+}
+
+//Forgets where the task had got to, and its results unless `keep_results`
+pub async fn delete_task_data(kv: &KvStore, job_id: &str, keep_results: bool) -> Result<(), KvError> {
+    kv.delete(&state_key(job_id)).await?;
+    if keep_results {
+        return Ok(());
+    }
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut list = kv.list().prefix(result_prefix(job_id));
+        if let Some(cursor) = cursor {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        for key in page.keys {
+            kv.delete(&key.name).await?;
+        }
+        cursor = page.cursor;
+        if page.list_complete || cursor.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn save_state(kv: &KvStore, job_id: &str, state: &TaskState) -> Result<(), KvError> {
+    kv.put(&state_key(job_id), serde_json::to_string(state)?)?.expiration_ttl(TASK_TTL).execute().await
+}
+
+//Whether the stored state is still leased to `holder`, so its progress and results may be written
+async fn holds_lease(kv: &KvStore, job_id: &str, holder: &str) -> Result<bool, KvError> {
+    let stored = kv.get(&state_key(job_id)).json::<TaskState>().await?;
+    Ok(stored.and_then(|stored| stored.lease).is_some_and(|lease| lease.holder == holder))
+}
+
+async fn run_chunk(fetcher: &Fetcher, task: &JobTask, state: &mut TaskState, deadline: u64) -> Result<Chunk, ScrapeError> {
+    match task {
+        JobTask::Batch { urls, recipe } => {
+            let end = (state.next + BATCH_CHUNK).min(urls.len());
+            let chunk_urls = &urls[state.next..end];
+            let results = scrape_many(fetcher, chunk_urls, recipe, BATCH_CONCURRENCY).await;
+            state.next = end;
+            Ok(Chunk {
+                pages: chunk_urls.iter().cloned().zip(results).map(|(url, result)| PageResult::new(url, result)).collect(),
+                total: urls.len(),
+                finished: end >= urls.len(),
+            })
+        }
+        JobTask::Crawl(options) => {
+            let plan = CrawlPlan::new(options)?;
+            let crawl = match &mut state.crawl {
+                Some(crawl) => crawl,
+                None => state.crawl.insert(CrawlState::new(&plan)),
+            };
+            let max_pages = (options.max_pages as usize).clamp(1, MAX_TASK_PAGES);
+            let chunk_end = (crawl.pages + CRAWL_CHUNK).min(max_pages);
+            let CrawlResult { pages, mut records, .. } = crawl_pages(fetcher, &plan, crawl, chunk_end, deadline).await?;
+            //Records carry the index of the page they came from
+            let pages = pages
+                .into_iter()
+                .map(|page| {
+                    let (page_records, rest): (Vec<Record>, Vec<Record>) = std::mem::take(&mut records)
+                        .into_iter()
+                        .partition(|record| record.get("page_index") == Some(&Value::from(page.page_index)));
+                    records = rest;
+                    match page.error {
+                        Some(e) => PageResult::new(page.url, Err(ScrapeError::Fetch(e))),
+                        None => PageResult::new(page.url, Ok(page_records)),
+                    }
+                })
+                .collect();
+            Ok(Chunk {
+                pages,
+                total: (crawl.pages + crawl.pending()).min(max_pages),
+                finished: crawl.is_finished() || crawl.pages >= max_pages,
+            })
+        }
+    }
+}
+
+//Advances a one-shot job chunk by chunk until it finishes or the deadline passes. The job is read again after
+//every chunk so a cancel or delete that came in meanwhile is honoured.
+//Overlapping cron invocations are kept apart by a lease in the task state, taken before the first chunk and checked
+//before every write. KV has no compare and swap, so two invocations taking the lease at the same moment are told
+//apart by reading it back, which is best effort: KV is eventually consistent across locations
+async fn advance_task(kv: &KvStore, fetcher: &Fetcher, job: Job, holder: &str, deadline: u64) -> Result<(), KvError> {
+    let task = match &job.task {
+        Some(task) => task.clone(),
+        None => return Ok(()),
+    };
+    let mut state = kv.get(&state_key(&job.id)).json::<TaskState>().await?.unwrap_or_default();
+    if state.is_leased_to_other(holder, Date::now().as_millis()) {
+        return Ok(());
+    }
+    state.lease_to(holder);
+    save_state(kv, &job.id, &state).await?;
+    if !holds_lease(kv, &job.id, holder).await? {
+        return Ok(());
+    }
+    let fetcher = job_fetcher(kv, fetcher, &job).await;
+    loop {
+        let chunk = match &fetcher {
+            Ok(fetcher) => run_chunk(fetcher, &task, &mut state, deadline).await,
+            Err(e) => Err(e.clone()),
+        };
+        //Another invocation took the task over after the lease ran out, its progress wins
+        if !holds_lease(kv, &job.id, holder).await? {
+            return Ok(());
+        }
+        let mut current = match get_job(kv, &job.id).await? {
+            Some(current) if current.progress.as_ref().is_some_and(|progress| progress.is_active()) => current,
+            _ => return kv.delete(&state_key(&job.id)).await,
+        };
+        let now = Date::now().as_millis();
+        let progress = current.progress.get_or_insert_with(|| JobProgress::queued(&task));
+        match chunk {
+            Ok(chunk) => {
+                if !chunk.pages.is_empty() {
+                    progress.completed += chunk.pages.iter().filter(|page| page.status == "ok").count();
+                    progress.failed += chunk.pages.iter().filter(|page| page.status != "ok").count();
+                    progress.records += chunk.pages.iter().map(|page| page.records.len()).sum::<usize>();
+                    kv.put(&result_key(&job.id, progress.chunks), serde_json::to_string(&chunk.pages)?)?
+                        .expiration_ttl(TASK_TTL)
+                        .execute()
+                        .await?;
+                    progress.chunks += 1;
+                }
+                progress.total = chunk.total;
+                progress.status = match chunk.finished {
+                    true => TaskStatus::Done,
+                    false => TaskStatus::Running,
+                };
+            }
+            Err(e) => {
+                progress.status = TaskStatus::Failed;
+                progress.error = Some(e.to_string());
+            }
+        }
+        let finished = !progress.is_active();
+        if finished {
+            progress.finished_at = Some(now);
+            kv.delete(&state_key(&job.id)).await?;
+        } else {
+            state.lease_to(holder);
+            save_state(kv, &job.id, &state).await?;
+        }
+        current.last_run = Some(now);
+        save_job(kv, &current).await?;
+        if finished {
+            return Ok(());
+        }
+        //The lease is given up once the job is saved, so the next invocation starts from its progress
+        if Date::now().as_millis() >= deadline {
+            state.lease = None;
+            return save_state(kv, &job.id, &state).await;
+        }
+    }
+}
+
+//Called from the cron trigger, advances queued and running one-shot jobs, oldest first, until the budget is spent
+pub async fn run_pending_tasks(env: &Env) {
+    let kv = match env.kv(SCRAPES_KV) {
+        Ok(kv) => kv,
+        Err(e) => {
+            console_log!("Unable to open {} namespace: {}", SCRAPES_KV, e);
+            return;
+        }
+    };
+    let mut jobs: Vec<Job> = match find_jobs(&kv, |summary| summary.active_task).await {
+        Ok(jobs) => jobs
+            .into_iter()
+            .filter(|job| job.task.is_some() && job.progress.as_ref().is_some_and(|progress| progress.is_active()))
+            .collect(),
+        Err(e) => {
+            console_log!("Unable to list jobs: {}", e);
+            return;
+        }
+    };
+    jobs.sort_by_key(|job| job.created_at);
+    let fetcher = Fetcher::from_env(env);
+    let holder = format!("{:016x}", thread_rng().gen::<u64>());
+    let deadline = Date::now().as_millis() + TASK_BUDGET_MS;
+    for job in jobs {
+        if Date::now().as_millis() >= deadline {
+            break;
+        }
+        let id = job.id.clone();
+        if let Err(e) = advance_task(&kv, &fetcher, job, &holder, deadline).await {
+            console_log!("Unable to advance job {}: {}", id, e);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use worker::kv::{KvError, KvStore};
+
+use crate::scraper::enrich::Taxonomy;
+
+fn taxonomy_key(owner: &str) -> String {
+    format!("taxonomy:{}", owner)
+}
+
+pub async fn get_taxonomy(kv: &KvStore, owner: &str) -> Result<Option<Taxonomy>, KvError> {
+    kv.get(&taxonomy_key(owner)).json::<Taxonomy>().await
+}
+
+//The user's taxonomy, or the built-in one when they have not saved their own
+pub async fn load_taxonomy(kv: &KvStore, owner: &str) -> Result<Taxonomy, KvError> {
+    Ok(get_taxonomy(kv, owner).await?.unwrap_or_default())
+}
+
+pub async fn save_taxonomy(kv: &KvStore, owner: &str, taxonomy: &Taxonomy) -> Result<(), KvError> {
+    kv.put(&taxonomy_key(owner), serde_json::to_string(taxonomy)?)?.execute().await
+}
+
+pub async fn delete_taxonomy(kv: &KvStore, owner: &str) -> Result<(), KvError> {
+    kv.delete(&taxonomy_key(owner)).await
+}
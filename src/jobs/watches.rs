@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use worker::kv::{KvError, KvStore};
+use worker::{console_log, Date, Env};
+
+use crate::jobs::diff::{diff_records, RecordDiff};
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::schedule::CronSchedule;
+use crate::jobs::webhook::{attempt_delivery, retry_deliveries, Delivery};
+use crate::routes::utils::generate_random_string;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::recipe::{Record, Recipe};
+use crate::scraper::scraper::scrape_website;
+use crate::utils::UtcTime;
+
+//A page monitored for changes, either through a plain selector or a full recipe
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Watch {
+    pub id: String,
+    pub owner: String,
+    pub url: String,
+    #[serde(default)]
+    pub selector: Option<String>,
+    #[serde(default)]
+    pub recipe: Option<Recipe>,
+    //Field used to match records between snapshots so edits are reported as changes
+    #[serde(default)]
+    pub key: Option<String>,
+    pub schedule: String,
+    pub webhook_url: String,
+    pub secret: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub last_checked: Option<u64>,
+    #[serde(default)]
+    pub last_changed: Option<u64>,
+}
+
+impl Watch {
+    pub fn recipe(&self) -> Option<Recipe> {
+        match (&self.recipe, &self.selector) {
+            (Some(recipe), _) => Some(recipe.clone()),
+            (None, Some(selector)) => Some(Recipe::from_selector(selector)),
+            (None, None) => None,
+        }
+    }
+
+    //Copy that is safe to return from the API, the secret is only shown on creation
+    pub fn redacted(&self) -> Watch {
+        let mut watch = self.clone();
+        watch.secret = String::new();
+        watch
+    }
+
+    fn summary(&self) -> WatchSummary {
+        WatchSummary {
+            owner: self.owner.clone(),
+            schedule: self.schedule.clone(),
+        }
+    }
+}
+
+//Metadata of a watch's key, see `JobSummary`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchSummary {
+    pub owner: String,
+    pub schedule: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WatchSnapshot {
+    taken_at: u64,
+    records: Vec<Record>,
+}
+
+pub fn watch_key(id: &str) -> String {
+    format!("watch:{}", id)
+}
+
+fn snapshot_key(id: &str) -> String {
+    format!("watch_snapshot:{}", id)
+}
+
+pub async fn get_watch(kv: &KvStore, id: &str) -> Result<Option<Watch>, KvError> {
+    kv.get(&watch_key(id)).json::<Watch>().await
+}
+
+pub async fn save_watch(kv: &KvStore, watch: &Watch) -> Result<(), KvError> {
+    kv.put(&watch_key(&watch.id), serde_json::to_string(watch)?)?.metadata(watch.summary())?.execute().await
+}
+
+pub async fn delete_watch(kv: &KvStore, id: &str) -> Result<(), KvError> {
+    kv.delete(&watch_key(id)).await?;
+    kv.delete(&snapshot_key(id)).await
+}
+
+//Same as `find_jobs`, for watches
+pub async fn find_watches(kv: &KvStore, wanted: impl Fn(&WatchSummary) -> bool) -> Result<Vec<Watch>, KvError> {
+    let mut watches = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut list = kv.list().prefix("watch:".to_string());
+        if let Some(cursor) = cursor {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        for key in page.keys {
+            let summary = key.metadata.and_then(|metadata| serde_json::from_value::<WatchSummary>(metadata).ok());
+            if summary.as_ref().is_some_and(|summary| !wanted(summary)) {
+                continue;
+            }
+            let watch = match kv.get(&key.name).json::<Watch>().await? {
+                Some(watch) => watch,
+                None => continue,
+            };
+            if summary.is_none() {
+                save_watch(kv, &watch).await?;
+                if !wanted(&watch.summary()) {
+                    continue;
+                }
+            }
+            watches.push(watch);
+        }
+        cursor = page.cursor;
+        if page.list_complete || cursor.is_none() {
+            break;
+        }
+    }
+    Ok(watches)
+}
+
+//Called from the cron trigger, evaluates due watches then retries failed webhook deliveries
+pub async fn run_due_watches(env: &Env, scheduled_time: u64) {
+    let kv = match env.kv(SCRAPES_KV) {
+        Ok(kv) => kv,
+        Err(e) => {
+            console_log!("Unable to open {} namespace: {}", SCRAPES_KV, e);
+            return;
+        }
+    };
+    let now = UtcTime::from_millis(scheduled_time);
+    //Invalid schedules are read too, so they can be reported below
+    let due = |summary: &WatchSummary| CronSchedule::parse(&summary.schedule).map_or(true, |schedule| schedule.matches(&now));
+    let watches = match find_watches(&kv, due).await {
+        Ok(watches) => watches,
+        Err(e) => {
+            console_log!("Unable to list watches: {}", e);
+            return;
+        }
+    };
+    let fetcher = Fetcher::from_env(env);
+    for watch in watches {
+        match CronSchedule::parse(&watch.schedule) {
+            Ok(schedule) if schedule.matches(&now) => {}
+            Ok(_) => continue,
+            Err(e) => {
+                console_log!("Skipping watch {} with invalid schedule: {}", watch.id, e);
+                continue;
+            }
+        }
+        let id = watch.id.clone();
+        if let Err(e) = check_watch(&kv, &fetcher, watch).await {
+            console_log!("Unable to check watch {}: {}", id, e);
+        }
+    }
+    if let Err(e) = retry_deliveries(&kv, Date::now().as_millis()).await {
+        console_log!("Unable to retry webhook deliveries: {}", e);
+    }
+}
+
+//Scrapes the watched page, diffs it against the previous snapshot and sends a webhook when it changed
+pub async fn check_watch(kv: &KvStore, fetcher: &Fetcher, mut watch: Watch) -> Result<RecordDiff, String> {
+    let recipe = match watch.recipe() {
+        Some(recipe) => recipe,
+        None => return Err("Watch has neither a selector nor a recipe".to_string()),
+    };
+    let records = scrape_website(fetcher, &watch.url, &recipe).await.map_err(|e| e.to_string())?;
+    let now = Date::now().as_millis();
+    let previous = kv.get(&snapshot_key(&watch.id)).json::<WatchSnapshot>().await.map_err(|e| e.to_string())?;
+    let diff = match &previous {
+        Some(previous) => diff_records(&previous.records, &records, watch.key.as_deref()),
+        //The first check only records a baseline
+        None => RecordDiff::default(),
+    };
+    if previous.is_none() || !diff.is_empty() {
+        let snapshot = WatchSnapshot { taken_at: now, records };
+        kv.put(&snapshot_key(&watch.id), serde_json::to_string(&snapshot).map_err(|e| e.to_string())?)
+            .map_err(|e| e.to_string())?
+            .execute()
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    if !diff.is_empty() {
+        let body = json!({
+            "watch_id": watch.id,
+            "url": watch.url,
+            "checked_at": now,
+            "diff": diff,
+        });
+        let delivery = Delivery {
+            id: generate_random_string(16),
+            watch_id: watch.id.clone(),
+            url: watch.webhook_url.clone(),
+            secret: watch.secret.clone(),
+            body: body.to_string(),
+            attempts: 0,
+            next_attempt_at: now,
+            status: "pending".to_string(),
+            last_error: None,
+        };
+        attempt_delivery(kv, delivery, now).await.map_err(|e| e.to_string())?;
+        watch.last_changed = Some(now);
+    }
+    watch.last_checked = Some(now);
+    save_watch(kv, &watch).await.map_err(|e| e.to_string())?;
+    Ok(diff)
+}
@@ -0,0 +1,126 @@
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use worker::console_log;
+use worker::kv::{KvError, KvStore};
+
+//Deliveries are retried with exponential backoff, 1, 2, 4, 8, 16 then 32 minutes after each failure
+const MAX_ATTEMPTS: u32 = 7;
+const BASE_BACKOFF_MS: u64 = 60 * 1000;
+//Failed deliveries are kept for a week so they can be inspected
+const DELIVERY_TTL: u64 = 60 * 60 * 24 * 7;
+
+pub const SIGNATURE_HEADER: &str = "X-Scraper-Signature";
+pub const DELIVERY_HEADER: &str = "X-Scraper-Delivery";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Delivery {
+    pub id: String,
+    pub watch_id: String,
+    pub url: String,
+    pub secret: String,
+    pub body: String,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub status: String,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+fn delivery_key(id: &str) -> String {
+    format!("delivery:{}", id)
+}
+
+//Hex encoded HMAC-SHA256 of the body, sent as `sha256=<hex>`
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn send(delivery: &Delivery) -> Result<(), String> {
+    let client = Client::new();
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "web-scraper-webhooks")
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, &delivery.body))
+        .header(DELIVERY_HEADER, delivery.id.as_str())
+        .body(delivery.body.clone())
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(format!("Webhook responded with {}", response.status())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+//Makes one delivery attempt, failures are stored and picked up again by `retry_deliveries`
+pub async fn attempt_delivery(kv: &KvStore, mut delivery: Delivery, now: u64) -> Result<Delivery, KvError> {
+    delivery.attempts += 1;
+    match send(&delivery).await {
+        Ok(_) => {
+            delivery.status = "delivered".to_string();
+            delivery.last_error = None;
+            if delivery.attempts > 1 {
+                kv.delete(&delivery_key(&delivery.id)).await?;
+            }
+            return Ok(delivery);
+        }
+        Err(e) => {
+            console_log!("Webhook delivery {} failed: {}", delivery.id, e);
+            delivery.last_error = Some(e);
+        }
+    }
+    if delivery.attempts >= MAX_ATTEMPTS {
+        delivery.status = "failed".to_string();
+    } else {
+        delivery.status = "pending".to_string();
+        delivery.next_attempt_at = now + BASE_BACKOFF_MS * 2u64.pow(delivery.attempts - 1);
+    }
+    kv.put(&delivery_key(&delivery.id), serde_json::to_string(&delivery)?)?
+        .expiration_ttl(DELIVERY_TTL)
+        .execute()
+        .await?;
+    Ok(delivery)
+}
+
+//Retries every pending delivery whose backoff has elapsed
+pub async fn retry_deliveries(kv: &KvStore, now: u64) -> Result<(), KvError> {
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut list = kv.list().prefix("delivery:".to_string());
+        if let Some(cursor) = cursor {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        for key in page.keys {
+            let delivery = match kv.get(&key.name).json::<Delivery>().await? {
+                Some(delivery) => delivery,
+                None => continue,
+            };
+            if delivery.status == "pending" && delivery.next_attempt_at <= now {
+                attempt_delivery(kv, delivery, now).await?;
+            }
+        }
+        cursor = page.cursor;
+        if page.list_complete || cursor.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_bodies_with_hmac_sha256() {
+        //RFC 4231 test case 2
+        assert_eq!(sign("Jefe", "what do ya want for nothing?"), "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_ne!(sign("other", "what do ya want for nothing?"), sign("Jefe", "what do ya want for nothing?"));
+    }
+}
@@ -3,7 +3,6 @@ use std::borrow::Borrow;
 use oauth::github_oauth::{AuthResponse, AuthToken};
 use pages::pages::{home_page, oauth_home_page};
 use routes::utils::{generate_client_id_and_secrets, ClientCredentials};
-use scraper::scraper::google_news_scraper;
 use serde_json::json;
 use worker::*;
 
@@ -12,6 +11,7 @@ mod routes;
 mod utils;
 mod pages;
 mod oauth;
+mod jobs;
 
 fn log_request(req: &Request) {
     console_log!(
@@ -33,22 +33,13 @@ pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
     let router = Router::new();
     router
         .get("/", |_, _| Response::from_html(home_page()))
-        .get_async("/api/scrape", |req, ctx| async move {
-            match crate::routes::utils::token_middleware(req, ctx).await {
-                Ok(_) => {
-                    let result = google_news_scraper().await;
-                    match result {
-                        Ok(articles) => {
-                            Response::from_json(&json!({ "Result": articles }))
-                        }
-                        Err(e) => {
-                            Response::error(&format!("Error: {}", e), 500)
-                        }
-                    }
-                },
-                Err(e) => Ok(e)
-            }
-        })
+        .get_async("/api/scrape", crate::routes::scrape::scrape)
+        .post_async("/api/scrape/batch", crate::routes::batch::scrape_batch)
+        .post_async("/api/crawl", crate::routes::crawl::crawl_website)
+        .get_async("/api/tables", crate::routes::tables::get_tables)
+        .get_async("/api/links", crate::routes::links::get_links)
+        .get_async("/api/blocks", crate::routes::scrape::get_block_stats)
+        .get_async("/api/search", crate::routes::search::search)
         .get("/oauth", |_, _| Response::from_html(oauth_home_page()))
         .get_async("/oauth/github", |req, ctx| async move {
             let client_id = ctx.secret("github_client_id").unwrap().to_string();
@@ -174,6 +165,42 @@ pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
             Response::from_json(&json!({ "Result": result }))
         })
         .post_async("/authorize", crate::routes::utils::authorize) 
+        .get_async("/api/jobs", crate::routes::jobs::get_jobs)
+        .post_async("/api/jobs", crate::routes::jobs::create_job)
+        .get_async("/api/jobs/:id", crate::routes::jobs::get_job_by_id)
+        .delete_async("/api/jobs/:id", crate::routes::jobs::remove_job)
+        .get_async("/api/jobs/:id/runs", crate::routes::jobs::get_job_runs)
+        .get_async("/api/jobs/:id/results", crate::routes::jobs::get_job_results)
+        .get_async("/api/watches", crate::routes::watches::get_watches)
+        .post_async("/api/watches", crate::routes::watches::create_watch)
+        .get_async("/api/watches/:id", crate::routes::watches::get_watch_by_id)
+        .delete_async("/api/watches/:id", crate::routes::watches::remove_watch)
+        .post_async("/api/watches/:id/check", crate::routes::watches::check_watch_now)
+        .get_async("/api/recipes", crate::routes::recipes::get_recipes)
+        .post_async("/api/recipes", crate::routes::recipes::save_user_recipe)
+        .get_async("/api/recipes/:name", crate::routes::recipes::get_recipe_by_name)
+        .delete_async("/api/recipes/:name", crate::routes::recipes::remove_recipe)
+        .get_async("/api/recipes/:name/versions", crate::routes::recipes::get_recipe_versions)
+        .put_async("/api/recipes/:name/share", crate::routes::recipes::share_recipe)
+        .get_async("/api/recipes/:name/run", crate::routes::recipes::run_recipe)
+        .get_async("/api/profiles", crate::routes::profiles::get_profiles)
+        .post_async("/api/profiles", crate::routes::profiles::save_user_profile)
+        .get_async("/api/profiles/:name", crate::routes::profiles::get_profile_by_name)
+        .delete_async("/api/profiles/:name", crate::routes::profiles::remove_profile)
+        .get_async("/api/taxonomy", crate::routes::taxonomy::get_user_taxonomy)
+        .put_async("/api/taxonomy", crate::routes::taxonomy::put_user_taxonomy)
+        .delete_async("/api/taxonomy", crate::routes::taxonomy::remove_user_taxonomy)
+        .get_async("/api/publishers", crate::routes::publishers::get_user_publishers)
+        .put_async("/api/publishers", crate::routes::publishers::put_user_publishers)
+        .delete_async("/api/publishers", crate::routes::publishers::remove_user_publishers)
         .run(req, env)
         .await
 }
+
+#[event(scheduled)]
+pub async fn scheduled(event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    utils::set_panic_hook();
+    crate::jobs::job::run_due_jobs(&env, event.schedule() as u64).await;
+    crate::jobs::watches::run_due_watches(&env, event.schedule() as u64).await;
+    crate::jobs::tasks::run_pending_tasks(&env).await;
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashMap};
+
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use worker::kv::KvStore;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::jobs::blocks::record_if_blocked;
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::recipes::{apply_schema, RecipeDefinition};
+use crate::routes::output::{stream_response, StreamFormat};
+use crate::routes::recipes::{load_definition, load_readable_recipe};
+use crate::routes::utils::{authenticate, load_profile, request_fetcher};
+use crate::scraper::block::BlockReason;
+use crate::scraper::error::ScrapeError;
+use crate::scraper::recipe::{Record, Recipe};
+use crate::scraper::scraper::scrape_stream;
+
+pub const MAX_BATCH_URLS: usize = 100;
+const DEFAULT_CONCURRENCY: usize = 5;
+const MAX_CONCURRENCY: usize = 10;
+
+#[derive(Deserialize, Debug)]
+struct BatchPayload {
+    //Pages to scrape, or leave empty and give a template with one parameter set per page
+    #[serde(default)]
+    urls: Vec<String>,
+    #[serde(default)]
+    url_template: Option<String>,
+    #[serde(default)]
+    params: Vec<HashMap<String, String>>,
+    #[serde(default)]
+    recipe: Option<Recipe>,
+    //A saved recipe instead of an inline one, its template is used when neither urls nor url_template are given
+    #[serde(default)]
+    recipe_name: Option<String>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    version: Option<u32>,
+    #[serde(default)]
+    concurrency: Option<usize>,
+}
+
+//A page of the batch, the URL is an error when the template could not be rendered with its params
+struct BatchPage {
+    params: Option<HashMap<String, String>>,
+    url: Result<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+struct BatchItem {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<HashMap<String, String>>,
+    //ok, error or blocked
+    status: &'static str,
+    records: Vec<Record>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejected: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<BlockReason>,
+}
+
+impl BatchItem {
+    fn failed(url: String, params: Option<HashMap<String, String>>, error: &ScrapeError) -> Self {
+        let (status, reason) = match error {
+            ScrapeError::Blocked(_, reason) => ("blocked", Some(*reason)),
+            _ => ("error", None),
+        };
+        BatchItem {
+            url,
+            params,
+            status,
+            records: vec![],
+            rejected: None,
+            error: Some(error.to_string()),
+            reason,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+struct BatchSummary {
+    total: usize,
+    ok: usize,
+    error: usize,
+    blocked: usize,
+}
+
+impl BatchSummary {
+    fn add(&mut self, item: &BatchItem) {
+        self.total += 1;
+        match item.status {
+            "ok" => self.ok += 1,
+            "blocked" => self.blocked += 1,
+            _ => self.error += 1,
+        }
+    }
+}
+
+//POST /api/scrape/batch applies one recipe to many pages and reports every page separately, `?stream=ndjson|sse`
+//sends each page as an `item` event as soon as it is done
+pub async fn scrape_batch(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let payload = match req.json::<BatchPayload>().await {
+        Ok(payload) => payload,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    //The definition supplies the recipe, template defaults, schema and profile
+    let (definition, source) = match (payload.recipe, &payload.recipe_name) {
+        (Some(recipe), None) => {
+            if let Err(e) = recipe.validate() {
+                return Response::error(&format!("Error: {}", e), 400);
+            }
+            let definition = RecipeDefinition {
+                url_template: payload.url_template.clone().unwrap_or_default(),
+                defaults: BTreeMap::new(),
+                recipe,
+                schema: BTreeMap::new(),
+                profile: None,
+            };
+            (definition, "batch".to_string())
+        }
+        (None, Some(name)) => {
+            let owner = payload.owner.clone().unwrap_or_else(|| claims.sub.clone());
+            let recipe = match load_readable_recipe(&kv, &owner, name, &claims.sub).await {
+                Ok(recipe) => recipe,
+                Err(e) => return Ok(e)
+            };
+            let mut definition = match load_definition(&kv, &recipe, payload.version).await {
+                Ok((_, definition)) => definition,
+                Err(e) => return Ok(e)
+            };
+            if let Some(url_template) = &payload.url_template {
+                definition.url_template = url_template.clone();
+            }
+            (definition, format!("recipe/{}/{}", recipe.owner, recipe.name))
+        }
+        _ => return Response::error("Error: Give either recipe or recipe_name", 400)
+    };
+
+    let from_template = payload.urls.is_empty() && payload.url_template.is_none();
+    let pages: Vec<BatchPage> = match payload.urls.is_empty() {
+        false => payload.urls.into_iter().map(|url| BatchPage { params: None, url: Ok(url) }).collect(),
+        true if definition.url_template.is_empty() => return Response::error("Error: Give urls or a url_template", 400),
+        true => {
+            let params = match payload.params.is_empty() {
+                true => vec![HashMap::new()],
+                false => payload.params,
+            };
+            params.into_iter().map(|params| BatchPage { url: definition.render_url(&params), params: Some(params) }).collect()
+        }
+    };
+    if pages.len() > MAX_BATCH_URLS {
+        return Response::error(&format!("Error: A batch may hold at most {} pages", MAX_BATCH_URLS), 400);
+    }
+    let concurrency = payload.concurrency.unwrap_or(DEFAULT_CONCURRENCY).clamp(1, MAX_CONCURRENCY);
+
+    let mut fetcher = match request_fetcher(&req, &ctx, &claims).await {
+        Ok(fetcher) => fetcher,
+        Err(e) => return Ok(e)
+    };
+    let query_profile = req.url()?.query_pairs().any(|(name, _)| name == "profile");
+    //The profile saved with a recipe belongs to its owner. Someone the recipe is shared with only gets it for the
+    //pages of the stored template, so its headers and cookies cannot be sent to a host they pick
+    let owner = payload.owner.clone().unwrap_or_else(|| claims.sub.clone());
+    let owner_urls = owner == claims.sub || (from_template && definition.fixed_origin().is_some());
+    if let (false, true, Some(profile)) = (query_profile, owner_urls, &definition.profile) {
+        fetcher = match load_profile(&ctx, &owner, profile).await {
+            Ok(profile) => fetcher.with_profile(profile),
+            Err(e) => return Ok(e)
+        };
+    }
+
+    let urls: Vec<String> = pages.iter().filter_map(|page| page.url.clone().ok()).collect();
+    if let Some(format) = StreamFormat::from_request(&req)? {
+        return stream_response(format, move |events| async move {
+            let mut scraped = Box::pin(scrape_stream(&fetcher, &urls, &definition.recipe, concurrency));
+            let mut summary = BatchSummary::default();
+            let mut errors = vec![];
+            for page in pages {
+                if let Some(item) = next_item(page, &mut scraped, &definition, &kv, &source).await {
+                    summary.add(&item);
+                    if let Some(error) = &item.error {
+                        errors.push(json!({ "url": item.url, "error": error }));
+                    }
+                    if !events.send("item", &json!(item)).await {
+                        return;
+                    }
+                }
+            }
+            events.send("summary", &json!({ "summary": summary, "errors": errors })).await;
+            events.close().await;
+        });
+    }
+    let mut scraped = Box::pin(scrape_stream(&fetcher, &urls, &definition.recipe, concurrency));
+    let mut summary = BatchSummary::default();
+    let mut items = vec![];
+    for page in pages {
+        if let Some(item) = next_item(page, &mut scraped, &definition, &kv, &source).await {
+            summary.add(&item);
+            items.push(item);
+        }
+    }
+    Response::from_json(&json!({ "Result": items, "Summary": summary }))
+}
+
+//The item for the next page. `scraped` yields a result for every page whose URL could be rendered, in page order
+async fn next_item<S>(page: BatchPage, scraped: &mut S, definition: &RecipeDefinition, kv: &KvStore, source: &str) -> Option<BatchItem>
+where
+    S: Stream<Item = Result<Vec<Record>, ScrapeError>> + Unpin,
+{
+    let BatchPage { params, url } = page;
+    let url = match url {
+        Ok(url) => url,
+        Err(e) => return Some(BatchItem::failed(definition.url_template.clone(), params, &ScrapeError::InvalidInput(e))),
+    };
+    match scraped.next().await? {
+        Ok(records) => {
+            let (records, rejected) = apply_schema(records, &definition.schema);
+            Some(BatchItem {
+                url,
+                params,
+                status: "ok",
+                records,
+                rejected: (!definition.schema.is_empty()).then_some(rejected),
+                error: None,
+                reason: None,
+            })
+        }
+        Err(e) => {
+            record_if_blocked(kv, source, &e).await;
+            Some(BatchItem::failed(url, params, &e))
+        }
+    }
+}
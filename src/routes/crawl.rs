@@ -0,0 +1,82 @@
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::routes::output::{stream_response, EventWriter, StreamFormat};
+use crate::routes::utils::{authenticate, request_fetcher};
+use crate::scraper::crawl::{crawl, crawl_budget, crawl_pages, CrawlOptions, CrawlPlan, CrawlState};
+use crate::scraper::fetch::Fetcher;
+
+//POST /api/crawl with a JSON body of CrawlOptions
+pub async fn crawl_website(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let fetcher = match request_fetcher(&req, &ctx, &claims).await {
+        Ok(fetcher) => fetcher,
+        Err(e) => return Ok(e)
+    };
+    let options = match req.json::<CrawlOptions>().await {
+        Ok(options) => options,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    if let Some(format) = StreamFormat::from_request(&req)? {
+        //Bad options are still reported with a plain error before the stream starts
+        let plan = match CrawlPlan::new(&options) {
+            Ok(plan) => plan,
+            Err(e) => return Ok(e.response())
+        };
+        return stream_response(format, move |events| stream_crawl(fetcher, options, plan, events));
+    }
+    match crawl(&fetcher, &options).await {
+        Ok(result) => Response::from_json(&json!({ "Result": result.records, "Pages": result.pages, "Stopped": result.stopped })),
+        Err(e) => Ok(e.response())
+    }
+}
+
+//Crawls one page at a time and sends a `page` event with each page's records, then a `summary` event
+async fn stream_crawl(fetcher: Fetcher, options: CrawlOptions, plan: CrawlPlan, events: EventWriter) {
+    let mut state = CrawlState::new(&plan);
+    let (max_pages, deadline) = crawl_budget(&options);
+    let (mut records, mut errors) = (0, vec![]);
+    let mut stopped = None;
+    let mut failure = None;
+    while !state.is_finished() {
+        if state.pages >= max_pages {
+            stopped = Some("max_pages".to_string());
+            break;
+        }
+        let next_page = state.pages + 1;
+        let result = match crawl_pages(&fetcher, &plan, &mut state, next_page, deadline).await {
+            Ok(result) => result,
+            Err(e) => {
+                failure = Some(e.to_string());
+                break;
+            }
+        };
+        if result.stopped.as_deref() == Some("time_budget") {
+            stopped = result.stopped;
+            break;
+        }
+        //Every record comes from the one page crawled
+        if let Some(page) = result.pages.first() {
+            records += result.records.len();
+            if let Some(error) = &page.error {
+                errors.push(json!({ "url": page.url, "error": error }));
+            }
+            if !events.send("page", &json!({ "page": page, "records": result.records })).await {
+                return;
+            }
+        }
+    }
+    let summary = json!({
+        "pages": state.pages,
+        "records": records,
+        "failed": errors.len(),
+        "errors": errors,
+        "stopped": stopped,
+        "error": failure,
+    });
+    events.send("summary", &summary).await;
+    events.close().await;
+}
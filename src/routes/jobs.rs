@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::json;
+use worker::kv::KvStore;
+use worker::{Date, Error, Request, Response, RouteContext};
+
+use crate::jobs::job::{delete_job, find_jobs, get_job, list_runs, save_job, Job, SCRAPES_KV};
+use crate::jobs::schedule::CronSchedule;
+use crate::jobs::tasks::{delete_task_data, list_results, JobProgress, JobTask, TaskStatus};
+use crate::routes::utils::{authenticate, generate_random_string, load_profile};
+use crate::scraper::enrich::EnrichOptions;
+use crate::scraper::publishers::PublisherFilter;
+use crate::scraper::query::ResultQuery;
+use crate::scraper::sources::{is_known_source, SOURCES};
+
+const DEFAULT_RUNS_LIMIT: u64 = 10;
+const MAX_RUNS_LIMIT: u64 = 50;
+//Result chunks per page, a chunk holds up to 10 pages of a batch or 20 of a crawl
+const DEFAULT_RESULTS_LIMIT: u64 = 5;
+const MAX_RESULTS_LIMIT: u64 = 20;
+
+//A scheduled job gives a source and schedule, a one-shot job gives a task instead
+#[derive(Deserialize, Debug)]
+struct JobPayload {
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    params: HashMap<String, String>,
+    #[serde(default)]
+    schedule: String,
+    #[serde(default)]
+    task: Option<JobTask>,
+    #[serde(default)]
+    profile: Option<String>,
+}
+
+//Loads a job and checks that it belongs to the caller
+async fn load_owned_job(kv: &KvStore, id: &str, owner: &str) -> Result<Job, Response> {
+    match get_job(kv, id).await {
+        Ok(Some(job)) if job.owner == owner => Ok(job),
+        Ok(_) => Err(Response::error("Job not found", 404).unwrap()),
+        Err(e) => Err(Response::error(&format!("Error: {}", e), 500).unwrap())
+    }
+}
+
+pub async fn create_job(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let payload = match req.json::<JobPayload>().await {
+        Ok(payload) => payload,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    match &payload.task {
+        Some(_) if !payload.schedule.is_empty() => return Response::error("Error: A job has either a schedule or a task", 400),
+        Some(task) => {
+            if let Err(e) = task.validate() {
+                return Response::error(&format!("Error: Invalid task: {}", e), 400);
+            }
+        }
+        None => {
+            if !is_known_source(&payload.source) {
+                return Response::error(&format!("Error: Unknown source, expected one of {:?}", SOURCES), 400);
+            }
+            if let Err(e) = CronSchedule::parse(&payload.schedule) {
+                return Response::error(&format!("Error: Invalid schedule: {}", e), 400);
+            }
+            if let Err(e) = EnrichOptions::from_params(&payload.params) {
+                return Ok(e.response());
+            }
+            if let Err(e) = ResultQuery::from_params(&payload.params, None) {
+                return Ok(e.response());
+            }
+            if let Err(e) = PublisherFilter::from_params(&payload.params) {
+                return Ok(e.response());
+            }
+        }
+    }
+    if let Some(profile) = &payload.profile {
+        if let Err(e) = load_profile(&ctx, &claims.sub, profile).await {
+            return Ok(e);
+        }
+    }
+    let job = Job {
+        id: generate_random_string(16),
+        owner: claims.sub,
+        source: match &payload.task {
+            Some(task) => task.kind().to_string(),
+            None => payload.source,
+        },
+        params: payload.params,
+        schedule: payload.schedule,
+        profile: payload.profile,
+        progress: payload.task.as_ref().map(JobProgress::queued),
+        task: payload.task,
+        created_at: Date::now().as_millis(),
+        last_run: None,
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match save_job(&kv, &job).await {
+        Ok(_) => Response::from_json(&json!({ "Result": job })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+pub async fn get_jobs(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match find_jobs(&kv, |summary| summary.owner == claims.sub).await {
+        Ok(jobs) => {
+            let jobs: Vec<Job> = jobs.into_iter().filter(|job| job.owner == claims.sub).collect();
+            Response::from_json(&json!({ "Result": jobs }))
+        }
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+pub async fn get_job_by_id(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match load_owned_job(&kv, &id, &claims.sub).await {
+        Ok(job) => Response::from_json(&json!({ "Result": job })),
+        Err(e) => Ok(e)
+    }
+}
+
+pub async fn remove_job(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let mut job = match load_owned_job(&kv, &id, &claims.sub).await {
+        Ok(job) => job,
+        Err(e) => return Ok(e)
+    };
+    //An unfinished one-shot job is cancelled and keeps the results it already has, deleting it again removes it
+    if let Some(progress) = job.progress.as_mut().filter(|progress| progress.is_active()) {
+        progress.status = TaskStatus::Cancelled;
+        progress.finished_at = Some(Date::now().as_millis());
+        if let Err(e) = save_job(&kv, &job).await {
+            return Response::error(&format!("Error: {}", e), 500);
+        }
+        return match delete_task_data(&kv, &job.id, true).await {
+            Ok(_) => Response::from_json(&json!({ "Result": job })),
+            Err(e) => Response::error(&format!("Error: {}", e), 500)
+        };
+    }
+    if job.task.is_some() {
+        if let Err(e) = delete_task_data(&kv, &job.id, false).await {
+            return Response::error(&format!("Error: {}", e), 500);
+        }
+    }
+    match delete_job(&kv, &job.id).await {
+        Ok(_) => Response::from_json(&json!({ "Result": job.id })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/jobs/:id/runs?cursor=&limit=
+pub async fn get_job_runs(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let mut cursor = None;
+    let mut limit = DEFAULT_RUNS_LIMIT;
+    for (key, value) in req.url()?.query_pairs() {
+        match key.as_ref() {
+            "cursor" => cursor = Some(value.to_string()),
+            "limit" => limit = value.parse::<u64>().unwrap_or(DEFAULT_RUNS_LIMIT).clamp(1, MAX_RUNS_LIMIT),
+            _ => {}
+        }
+    }
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let job = match load_owned_job(&kv, &id, &claims.sub).await {
+        Ok(job) => job,
+        Err(e) => return Ok(e)
+    };
+    match list_runs(&kv, &job.id, cursor, limit).await {
+        Ok((runs, next)) => Response::from_json(&json!({ "Result": runs, "Cursor": next })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/jobs/:id/results?cursor=&limit= pages through the results of a one-shot job, `limit` chunks at a time
+pub async fn get_job_results(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let mut cursor = None;
+    let mut limit = DEFAULT_RESULTS_LIMIT;
+    for (key, value) in req.url()?.query_pairs() {
+        match key.as_ref() {
+            "cursor" => cursor = Some(value.to_string()),
+            "limit" => limit = value.parse::<u64>().unwrap_or(DEFAULT_RESULTS_LIMIT).clamp(1, MAX_RESULTS_LIMIT),
+            _ => {}
+        }
+    }
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let job = match load_owned_job(&kv, &id, &claims.sub).await {
+        Ok(job) => job,
+        Err(e) => return Ok(e)
+    };
+    if job.task.is_none() {
+        return Response::error("Error: Scheduled jobs have runs instead of results", 400);
+    }
+    match list_results(&kv, &job.id, cursor, limit).await {
+        Ok((results, next)) => Response::from_json(&json!({ "Result": results, "Cursor": next, "Progress": job.progress })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
@@ -0,0 +1,51 @@
+use reqwest::Url;
+use scraper::Html;
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::routes::utils::{authenticate, request_fetcher};
+use crate::scraper::links::{check_links, collect_links};
+
+//GET /api/links?url=&check=
+pub async fn get_links(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let mut url = None;
+    let mut check = false;
+    for (name, value) in req.url()?.query_pairs() {
+        match name.as_ref() {
+            "url" => url = Some(value.to_string()),
+            "check" => check = value == "true",
+            _ => {}
+        }
+    }
+    let page_url = match url.as_deref().map(Url::parse) {
+        Some(Ok(page_url)) => page_url,
+        Some(Err(e)) => return Response::error(&format!("Error: Invalid url: {}", e), 400),
+        None => return Response::error("Error: url is required", 400)
+    };
+    let fetcher = match request_fetcher(&req, &ctx, &claims).await {
+        Ok(fetcher) => fetcher,
+        Err(e) => return Ok(e)
+    };
+    let body = match fetcher.fetch_html(page_url.as_str()).await {
+        Ok(body) => body,
+        Err(e) => return Ok(e.response())
+    };
+    let mut links = collect_links(&Html::parse_document(&body), &page_url);
+    let unchecked = match check {
+        true => check_links(&fetcher, &mut links).await,
+        false => 0,
+    };
+    let internal = links.iter().filter(|link| link.internal).count();
+    let summary = json!({
+        "total": links.len(),
+        "internal": internal,
+        "external": links.len() - internal,
+        "broken": links.iter().filter(|link| link.is_broken()).count(),
+        "unchecked": unchecked,
+    });
+    Response::from_json(&json!({ "Result": links, "Summary": summary }))
+}
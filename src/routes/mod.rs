@@ -1,2 +1,15 @@
 pub mod routes;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod jobs;
+pub mod watches;
+pub mod scrape;
+pub mod crawl;
+pub mod recipes;
+pub mod output;
+pub mod tables;
+pub mod links;
+pub mod profiles;
+pub mod batch;
+pub mod taxonomy;
+pub mod search;
+pub mod publishers;
\ No newline at end of file
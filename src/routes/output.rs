@@ -0,0 +1,211 @@
+use std::future::Future;
+
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use serde_json::Value;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use worker::worker_sys::Response as EdgeResponse;
+use worker::{Error, Request, Response, ResponseBody};
+
+use crate::scraper::recipe::Record;
+
+//Response format picked with `?format=`, JSON unless `csv` is asked for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn from_request(req: &Request) -> Result<Self, Error> {
+        let format = req.url()?.query_pairs().find(|(name, _)| name == "format").map(|(_, value)| value.to_string());
+        Ok(match format.as_deref() {
+            Some("csv") => OutputFormat::Csv,
+            _ => OutputFormat::Json,
+        })
+    }
+}
+
+//Every field that appears in the records, in the order first seen
+pub fn record_columns(records: &[Record]) -> Vec<String> {
+    let mut columns: Vec<String> = vec![];
+    for record in records {
+        for name in record.keys() {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+    }
+    columns
+}
+
+//Quotes a field when it holds a separator, quote or line break (RFC 4180)
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn csv_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => csv_field(text),
+        //Nested values are written as JSON
+        Some(other) => csv_field(&other.to_string()),
+    }
+}
+
+pub fn records_to_csv(columns: &[String], records: &[Record]) -> String {
+    let mut csv = columns.iter().map(|column| csv_field(column)).collect::<Vec<_>>().join(",");
+    csv.push_str("\r\n");
+    for record in records {
+        let row: Vec<String> = columns.iter().map(|column| csv_value(record.get(column))).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+pub fn csv_response(columns: &[String], records: &[Record]) -> Result<Response, Error> {
+    let mut response = Response::ok(records_to_csv(columns, records))?;
+    response.headers_mut().set("Content-Type", "text/csv; charset=utf-8")?;
+    Ok(response)
+}
+
+//Streamed response format picked with `?stream=`, results are sent as they are produced instead of all at the end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamFormat {
+    Ndjson,
+    Sse,
+}
+
+impl StreamFormat {
+    pub fn from_request(req: &Request) -> Result<Option<Self>, Error> {
+        let format = req.url()?.query_pairs().find(|(name, _)| name == "stream").map(|(_, value)| value.to_string());
+        Ok(match format.as_deref() {
+            Some("ndjson") => Some(StreamFormat::Ndjson),
+            Some("sse") => Some(StreamFormat::Sse),
+            _ => None,
+        })
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Ndjson => "application/x-ndjson",
+            StreamFormat::Sse => "text/event-stream",
+        }
+    }
+
+    //NDJSON carries the event name in a `type` field, which wins over a `type` in the data, SSE in the `event:` line
+    fn encode(&self, event: &str, data: &Value) -> String {
+        match self {
+            StreamFormat::Ndjson => {
+                let mut line = serde_json::Map::new();
+                match data {
+                    Value::Object(fields) => line.extend(fields.clone()),
+                    other => {
+                        line.insert("data".to_string(), other.clone());
+                    }
+                }
+                line.insert("type".to_string(), Value::String(event.to_string()));
+                format!("{}\n", Value::Object(line))
+            }
+            StreamFormat::Sse => format!("event: {}\ndata: {}\n\n", event, data),
+        }
+    }
+}
+
+fn js_method(target: &JsValue, name: &str) -> Result<Function, JsValue> {
+    Reflect::get(target, &JsValue::from_str(name))?.dyn_into::<Function>()
+}
+
+//Writable end of a streamed response
+pub struct EventWriter {
+    writer: JsValue,
+    format: StreamFormat,
+}
+
+impl EventWriter {
+    //Writes one event, false once the client has gone away and nothing more should be produced
+    pub async fn send(&self, event: &str, data: &Value) -> bool {
+        let bytes = Uint8Array::from(self.format.encode(event, data).as_bytes());
+        let written = js_method(&self.writer, "write").and_then(|write| write.call1(&self.writer, &bytes));
+        match written {
+            Ok(promise) => JsFuture::from(Promise::from(promise)).await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub async fn close(self) {
+        if let Ok(promise) = js_method(&self.writer, "close").and_then(|close| close.call0(&self.writer)) {
+            let _ = JsFuture::from(Promise::from(promise)).await;
+        }
+    }
+}
+
+//Returns a response right away and runs `produce` in the background, its events go out as it writes them.
+//The runtime keeps the request alive while the body is still being read
+pub fn stream_response<F, Fut>(format: StreamFormat, produce: F) -> Result<Response, Error>
+where
+    F: FnOnce(EventWriter) -> Fut,
+    Fut: Future<Output = ()> + 'static,
+{
+    let global = js_sys::global();
+    let transform = Reflect::construct(&js_method(&global, "TransformStream")?, &Array::new())?;
+    let readable = Reflect::get(&transform, &JsValue::from_str("readable"))?;
+    let writable = Reflect::get(&transform, &JsValue::from_str("writable"))?;
+    let writer = js_method(&writable, "getWriter")?.call0(&writable)?;
+    let edge = Reflect::construct(&js_method(&global, "Response")?, &Array::of1(&readable))?;
+    spawn_local(produce(EventWriter { writer, format }));
+
+    let mut response = Response::from_body(ResponseBody::Stream(edge.unchecked_into::<EdgeResponse>()))?;
+    response.headers_mut().set("Content-Type", format.content_type())?;
+    response.headers_mut().set("Cache-Control", "no-cache")?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        match value {
+            Value::Object(record) => record,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn quotes_csv_fields_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn writes_records_as_csv() {
+        let records = vec![record(json!({"title": "One", "tags": ["a", "b"]})), record(json!({"title": "Two, three", "price": 4, "note": null}))];
+        let columns = record_columns(&records);
+        assert_eq!(columns, vec!["tags", "title", "note", "price"]);
+        assert_eq!(records_to_csv(&columns, &records), "tags,title,note,price\r\n\"[\"\"a\"\",\"\"b\"\"]\",One,,\r\n,\"Two, three\",,4\r\n");
+    }
+
+    #[test]
+    fn encodes_ndjson_lines_with_the_event_type() {
+        let line = StreamFormat::Ndjson.encode("page", &json!({"records": 2, "type": "listing"}));
+        assert!(line.ends_with('\n') && !line.trim_end().contains('\n'));
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap(), json!({"type": "page", "records": 2}));
+        let line = StreamFormat::Ndjson.encode("error", &json!("timed out"));
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap(), json!({"type": "error", "data": "timed out"}));
+    }
+
+    #[test]
+    fn frames_server_sent_events() {
+        assert_eq!(StreamFormat::Sse.encode("summary", &json!({"pages": 3})), "event: summary\ndata: {\"pages\":3}\n\n");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use serde_json::json;
+use worker::{Date, Error, Request, Response, RouteContext};
+
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::profiles::{delete_profile, get_profile, list_profiles, save_profile, StoredProfile};
+use crate::jobs::recipes::validate_name;
+use crate::routes::utils::authenticate;
+use crate::scraper::profile::RequestProfile;
+
+#[derive(Deserialize, Debug)]
+struct ProfilePayload {
+    name: String,
+    #[serde(flatten)]
+    profile: RequestProfile,
+}
+
+//POST /api/profiles creates a request profile, or replaces the caller's profile with that name
+pub async fn save_user_profile(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let payload = match req.json::<ProfilePayload>().await {
+        Ok(payload) => payload,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    if let Err(e) = validate_name("Profile", &payload.name).and_then(|_| payload.profile.validate()) {
+        return Response::error(&format!("Error: {}", e), 400);
+    }
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let now = Date::now().as_millis();
+    let created_at = match get_profile(&kv, &claims.sub, &payload.name).await {
+        Ok(Some(existing)) => existing.created_at,
+        Ok(None) => now,
+        Err(e) => return Response::error(&format!("Error: {}", e), 500)
+    };
+    let profile = StoredProfile {
+        name: payload.name,
+        owner: claims.sub,
+        created_at,
+        updated_at: now,
+        profile: payload.profile,
+    };
+    match save_profile(&kv, &profile).await {
+        Ok(_) => Response::from_json(&json!({ "Result": profile })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/profiles lists the caller's profiles
+pub async fn get_profiles(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match list_profiles(&kv, &claims.sub).await {
+        Ok(profiles) => Response::from_json(&json!({ "Result": profiles })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/profiles/:name
+pub async fn get_profile_by_name(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match get_profile(&kv, &claims.sub, &name).await {
+        Ok(Some(profile)) => Response::from_json(&json!({ "Result": profile })),
+        Ok(None) => Response::error("Profile not found", 404),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//DELETE /api/profiles/:name, recipes and jobs that still name the profile fail until it is recreated
+pub async fn remove_profile(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match get_profile(&kv, &claims.sub, &name).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Response::error("Profile not found", 404),
+        Err(e) => return Response::error(&format!("Error: {}", e), 500)
+    }
+    match delete_profile(&kv, &claims.sub, &name).await {
+        Ok(_) => Response::from_json(&json!({ "Result": name })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
@@ -0,0 +1,75 @@
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::publishers::{delete_publishers, get_publishers, save_publishers};
+use crate::routes::utils::authenticate;
+use crate::scraper::publishers::{built_in_publishers, normalize_domain, validate_publishers, Publisher, PublisherRegistry};
+
+//GET /api/publishers?domain=&name= lists the built-in publishers with the caller's own on top, or looks one up by
+//domain or name
+pub async fn get_user_publishers(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let (mut domain, mut name) = (None, None);
+    for (key, value) in req.url()?.query_pairs() {
+        match key.as_ref() {
+            "domain" => domain = Some(value.to_string()),
+            "name" => name = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let custom = match get_publishers(&kv, &claims.sub).await {
+        Ok(custom) => custom,
+        Err(e) => return Response::error(&format!("Error: {}", e), 500)
+    };
+    let registry = PublisherRegistry::new(&custom);
+    let found = match (domain, name) {
+        (Some(domain), _) => match normalize_domain(&domain) {
+            Some(domain) => registry.by_domain(&domain),
+            None => return Response::error(&format!("Error: Invalid domain: '{}'", domain), 400),
+        },
+        (None, Some(name)) => registry.by_name(&name),
+        (None, None) => return Response::from_json(&json!({ "Result": registry.publishers(), "Custom": custom.len() })),
+    };
+    match found {
+        Some(publisher) => Response::from_json(&json!({ "Result": publisher })),
+        None => Response::error("Publisher not found", 404)
+    }
+}
+
+//PUT /api/publishers replaces the caller's own publishers, which take precedence over built-in ones sharing a domain
+pub async fn put_user_publishers(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let publishers = match req.json::<Vec<Publisher>>().await {
+        Ok(publishers) => publishers,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    if let Err(e) = validate_publishers(&publishers) {
+        return Response::error(&format!("Error: {}", e), 400);
+    }
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match save_publishers(&kv, &claims.sub, &publishers).await {
+        Ok(_) => Response::from_json(&json!({ "Result": publishers })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//DELETE /api/publishers goes back to the built-in publishers
+pub async fn remove_user_publishers(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match delete_publishers(&kv, &claims.sub).await {
+        Ok(_) => Response::from_json(&json!({ "Result": built_in_publishers() })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
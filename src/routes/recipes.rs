@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+use serde_json::json;
+use worker::kv::KvStore;
+use worker::{Date, Error, Request, Response, RouteContext};
+
+use crate::jobs::blocks::record_if_blocked;
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::recipes::{
+    apply_schema, delete_recipe, get_recipe, get_recipe_version, list_recipe_versions, list_recipes, save_recipe, save_recipe_sharing,
+    validate_name, FieldType, RecipeDefinition, StoredRecipe,
+};
+use crate::routes::output::{csv_response, record_columns, OutputFormat};
+use crate::routes::utils::{authenticate, load_profile, request_fetcher};
+use crate::scraper::recipe::Recipe;
+use crate::scraper::scraper::scrape_website;
+
+#[derive(Deserialize, Debug)]
+struct RecipePayload {
+    name: String,
+    url_template: String,
+    #[serde(default)]
+    defaults: BTreeMap<String, String>,
+    recipe: Recipe,
+    #[serde(default)]
+    schema: BTreeMap<String, FieldType>,
+    #[serde(default)]
+    profile: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SharePayload {
+    shared_with: Vec<String>,
+}
+
+//Query parameters that select the recipe rather than fill its template
+struct RecipeQuery {
+    owner: Option<String>,
+    version: Option<u32>,
+    profile: Option<String>,
+    params: HashMap<String, String>,
+}
+
+impl RecipeQuery {
+    fn from_request(req: &Request) -> Result<Self, Error> {
+        let mut query = RecipeQuery {
+            owner: None,
+            version: None,
+            profile: None,
+            params: HashMap::new(),
+        };
+        for (name, value) in req.url()?.query_pairs() {
+            match name.as_ref() {
+                "owner" => query.owner = Some(value.to_string()),
+                "version" => query.version = value.parse().ok(),
+                "profile" => query.profile = Some(value.to_string()),
+                "ignore_robots" | "format" => {}
+                _ => {
+                    query.params.insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+//Loads a recipe the caller owns or that has been shared with them, `owner` defaults to the caller
+pub async fn load_readable_recipe(kv: &KvStore, owner: &str, name: &str, user: &str) -> Result<StoredRecipe, Response> {
+    match get_recipe(kv, owner, name).await {
+        Ok(Some(recipe)) if recipe.can_read(user) => Ok(recipe),
+        Ok(_) => Err(Response::error("Recipe not found", 404).unwrap()),
+        Err(e) => Err(Response::error(&format!("Error: {}", e), 500).unwrap())
+    }
+}
+
+//Resolves the requested version of a recipe, the latest when none is given
+pub async fn load_definition(kv: &KvStore, recipe: &StoredRecipe, version: Option<u32>) -> Result<(u32, RecipeDefinition), Response> {
+    match version {
+        None => Ok((recipe.version, recipe.definition.clone())),
+        Some(version) if version == recipe.version => Ok((recipe.version, recipe.definition.clone())),
+        Some(version) => match get_recipe_version(kv, &recipe.owner, &recipe.name, version).await {
+            Ok(Some(stored)) => Ok((stored.version, stored.definition)),
+            Ok(None) => Err(Response::error("Recipe version not found", 404).unwrap()),
+            Err(e) => Err(Response::error(&format!("Error: {}", e), 500).unwrap())
+        },
+    }
+}
+
+//POST /api/recipes creates a recipe, or a new version when the caller already has one with that name
+pub async fn save_user_recipe(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let payload = match req.json::<RecipePayload>().await {
+        Ok(payload) => payload,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    let definition = RecipeDefinition {
+        url_template: payload.url_template,
+        defaults: payload.defaults,
+        recipe: payload.recipe,
+        schema: payload.schema,
+        profile: payload.profile,
+    };
+    if let Err(e) = validate_name("Recipe", &payload.name).and_then(|_| definition.validate()) {
+        return Response::error(&format!("Error: {}", e), 400);
+    }
+    if let Some(profile) = &definition.profile {
+        if let Err(e) = load_profile(&ctx, &claims.sub, profile).await {
+            return Ok(e);
+        }
+    }
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let now = Date::now().as_millis();
+    let recipe = match get_recipe(&kv, &claims.sub, &payload.name).await {
+        Ok(Some(existing)) => StoredRecipe {
+            version: existing.version + 1,
+            updated_at: now,
+            definition,
+            ..existing
+        },
+        Ok(None) => StoredRecipe {
+            name: payload.name,
+            owner: claims.sub,
+            version: 1,
+            shared_with: vec![],
+            created_at: now,
+            updated_at: now,
+            definition,
+        },
+        Err(e) => return Response::error(&format!("Error: {}", e), 500)
+    };
+    match save_recipe(&kv, &recipe).await {
+        Ok(_) => Response::from_json(&json!({ "Result": recipe })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/recipes lists the caller's recipes and the ones shared with them
+pub async fn get_recipes(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match list_recipes(&kv).await {
+        Ok(recipes) => {
+            let recipes: Vec<StoredRecipe> = recipes.into_iter().filter(|recipe| recipe.can_read(&claims.sub)).collect();
+            Response::from_json(&json!({ "Result": recipes }))
+        }
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/recipes/:name?owner=&version=
+pub async fn get_recipe_by_name(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let query = RecipeQuery::from_request(&req)?;
+    let owner = query.owner.clone().unwrap_or_else(|| claims.sub.clone());
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let recipe = match load_readable_recipe(&kv, &owner, &name, &claims.sub).await {
+        Ok(recipe) => recipe,
+        Err(e) => return Ok(e)
+    };
+    match load_definition(&kv, &recipe, query.version).await {
+        Ok((version, definition)) => Response::from_json(&json!({ "Result": StoredRecipe { version, definition, ..recipe } })),
+        Err(e) => Ok(e)
+    }
+}
+
+//GET /api/recipes/:name/versions?owner=
+pub async fn get_recipe_versions(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let query = RecipeQuery::from_request(&req)?;
+    let owner = query.owner.unwrap_or_else(|| claims.sub.clone());
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let recipe = match load_readable_recipe(&kv, &owner, &name, &claims.sub).await {
+        Ok(recipe) => recipe,
+        Err(e) => return Ok(e)
+    };
+    match list_recipe_versions(&kv, &recipe.owner, &recipe.name).await {
+        Ok(versions) => Response::from_json(&json!({ "Result": versions })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//DELETE /api/recipes/:name removes one of the caller's recipes with all of its versions
+pub async fn remove_recipe(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let recipe = match load_readable_recipe(&kv, &claims.sub, &name, &claims.sub).await {
+        Ok(recipe) => recipe,
+        Err(e) => return Ok(e)
+    };
+    match delete_recipe(&kv, &recipe.owner, &recipe.name).await {
+        Ok(_) => Response::from_json(&json!({ "Result": recipe.name })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//PUT /api/recipes/:name/share replaces the list of users the recipe is shared with, read only
+pub async fn share_recipe(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let payload = match req.json::<SharePayload>().await {
+        Ok(payload) => payload,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let mut recipe = match load_readable_recipe(&kv, &claims.sub, &name, &claims.sub).await {
+        Ok(recipe) => recipe,
+        Err(e) => return Ok(e)
+    };
+    let mut shared_with: Vec<String> = payload.shared_with.into_iter().filter(|user| !user.is_empty() && *user != claims.sub).collect();
+    shared_with.sort();
+    shared_with.dedup();
+    recipe.shared_with = shared_with;
+    match save_recipe_sharing(&kv, &recipe).await {
+        Ok(_) => Response::from_json(&json!({ "Result": recipe })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//GET /api/recipes/:name/run?owner=&version=&profile=&format=&<template params>
+pub async fn run_recipe(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let query = RecipeQuery::from_request(&req)?;
+    let owner = query.owner.clone().unwrap_or_else(|| claims.sub.clone());
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let recipe = match load_readable_recipe(&kv, &owner, &name, &claims.sub).await {
+        Ok(recipe) => recipe,
+        Err(e) => return Ok(e)
+    };
+    let (version, definition) = match load_definition(&kv, &recipe, query.version).await {
+        Ok(loaded) => loaded,
+        Err(e) => return Ok(e)
+    };
+    let url = match definition.render_url(&query.params) {
+        Ok(url) => url,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    let mut fetcher = match request_fetcher(&req, &ctx, &claims).await {
+        Ok(fetcher) => fetcher,
+        Err(e) => return Ok(e)
+    };
+    //A profile picked by the caller wins over the one saved with the recipe, which belongs to its owner. Someone the
+    //recipe is shared with only gets it when the template fixes the host, so it cannot be sent to a host they pick
+    let owner_host = recipe.owner == claims.sub || definition.fixed_origin().is_some();
+    if let (None, true, Some(profile)) = (&query.profile, owner_host, &definition.profile) {
+        fetcher = match load_profile(&ctx, &recipe.owner, profile).await {
+            Ok(profile) => fetcher.with_profile(profile),
+            Err(e) => return Ok(e)
+        };
+    }
+    let records = match scrape_website(&fetcher, &url, &definition.recipe).await {
+        Ok(records) => records,
+        Err(e) => {
+            record_if_blocked(&kv, &format!("recipe/{}/{}", recipe.owner, recipe.name), &e).await;
+            return Ok(e.response());
+        }
+    };
+    let (records, rejected) = apply_schema(records, &definition.schema);
+    if OutputFormat::from_request(&req)? == OutputFormat::Csv {
+        return csv_response(&record_columns(&records), &records);
+    }
+    Response::from_json(&json!({ "Result": records, "Version": version, "Url": url, "Rejected": rejected }))
+}
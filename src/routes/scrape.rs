@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::jobs::blocks::{list_block_stats, record_if_blocked};
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::publishers::load_registry;
+use crate::jobs::taxonomies::load_taxonomy;
+use crate::routes::output::{csv_response, record_columns, OutputFormat};
+use crate::routes::utils::{authenticate, request_fetcher, require_admin};
+use crate::scraper::dedup::{dedupe, fingerprint, group_stories, SeenStore};
+use crate::scraper::enrich::{enrich, EnrichOptions, Taxonomy};
+use crate::scraper::language::{keep_languages, language_filter};
+use crate::scraper::publishers::PublisherFilter;
+use crate::scraper::query::{articles_to_records, ResultQuery};
+use crate::scraper::sources::{default_limit, is_known_source, run_source, SOURCES};
+
+//Seen fingerprints are forgotten after 30 days without a request on the cursor
+const SEEN_TTL: u64 = 60 * 60 * 24 * 30;
+
+struct ScrapeOptions {
+    source: String,
+    params: HashMap<String, String>,
+    dedupe: bool,
+    only_new: bool,
+    cursor: String,
+    group: bool,
+}
+
+impl ScrapeOptions {
+    //Query parameters that are not options are handed to the source
+    fn from_request(req: &Request) -> Result<Self, Error> {
+        let mut options = ScrapeOptions {
+            source: "google_news".to_string(),
+            params: HashMap::new(),
+            dedupe: false,
+            only_new: false,
+            cursor: "default".to_string(),
+            group: false,
+        };
+        for (key, value) in req.url()?.query_pairs() {
+            match key.as_ref() {
+                "source" => options.source = value.to_string(),
+                "dedupe" => options.dedupe = value == "true",
+                "only_new" => options.only_new = value == "true",
+                "cursor" => options.cursor = value.to_string(),
+                "group" => options.group = value == "true",
+                "ignore_robots" | "format" | "profile" => {}
+                _ => {
+                    options.params.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn seen_key(user: &str, cursor: &str) -> String {
+    format!("seen:{}:{}", user, cursor)
+}
+
+//GET /api/scrape?source=&dedupe=&only_new=&cursor=&group=&enrich=&expand=&summary_sentences=&lang=&include_publishers=
+//&exclude_publishers=&ignore_robots=&profile=&format=, and the ResultQuery parameters
+pub async fn scrape(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let options = ScrapeOptions::from_request(&req)?;
+    if !is_known_source(&options.source) {
+        return Response::error(&format!("Error: Unknown source, expected one of {:?}", SOURCES), 400);
+    }
+    let mut enrichments = match EnrichOptions::from_params(&options.params) {
+        Ok(enrichments) => enrichments,
+        Err(e) => return Ok(e.response())
+    };
+    let query = match ResultQuery::from_params(&options.params, default_limit(&options.source)) {
+        Ok(query) => query,
+        Err(e) => return Ok(e.response())
+    };
+    let publisher_filter = match PublisherFilter::from_params(&options.params) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(e.response())
+    };
+    let format = OutputFormat::from_request(&req)?;
+    if options.group && (format == OutputFormat::Csv || !query.fields.is_empty()) {
+        return Response::error("Error: CSV output and fields are not available for grouped stories", 400);
+    }
+    let languages = language_filter(&options.params);
+    //Filtering or sorting by sentiment or language needs them worked out before the page is picked, otherwise only
+    //the articles on the page are enriched
+    enrichments.sentiment |= query.needs_sentiment();
+    enrichments.language |= languages.is_some();
+    let enrich_first = query.needs_sentiment() || languages.is_some();
+    let fetcher = match request_fetcher(&req, &ctx, &claims).await {
+        Ok(fetcher) => fetcher,
+        Err(e) => return Ok(e)
+    };
+    let mut source_params = options.params.clone();
+    let narrowed = options.dedupe || options.only_new || languages.is_some() || !publisher_filter.is_empty();
+    if let Some(limit) = query.source_limit(narrowed) {
+        source_params.insert("limit".to_string(), limit.to_string());
+    }
+    let mut articles = match run_source(&fetcher, &options.source, &source_params).await {
+        Ok(articles) => articles,
+        Err(e) => {
+            record_if_blocked(&ctx.kv(SCRAPES_KV)?, &options.source, &e).await;
+            return Ok(e.response());
+        }
+    };
+    let registry = match load_registry(&ctx.kv(SCRAPES_KV)?, &claims.sub).await {
+        Ok(registry) => registry,
+        Err(e) => return Response::error(&format!("Error: {}", e), 500)
+    };
+    registry.apply(&mut articles);
+    publisher_filter.apply(&mut articles, &registry);
+    if options.dedupe {
+        articles = dedupe(articles);
+    }
+    if options.only_new {
+        //Each cursor remembers what it has already returned to the caller
+        let kv = ctx.kv(SCRAPES_KV)?;
+        let key = seen_key(&claims.sub, &options.cursor);
+        let mut seen = match kv.get(&key).json::<SeenStore>().await {
+            Ok(Some(seen)) => seen,
+            Ok(None) => SeenStore::default(),
+            Err(e) => return Response::error(&format!("Error: {}", e), 500)
+        };
+        let prints: Vec<_> = articles.iter().map(fingerprint).collect();
+        let mut fresh = vec![];
+        for (article, print) in articles.into_iter().zip(prints.iter()) {
+            if !seen.has_seen(print) {
+                fresh.push(article);
+            }
+        }
+        for print in prints {
+            seen.remember(print);
+        }
+        kv.put(&key, serde_json::to_string(&seen)?)?.expiration_ttl(SEEN_TTL).execute().await?;
+        articles = fresh;
+    }
+    let taxonomy = match enrichments.topic {
+        true => match load_taxonomy(&ctx.kv(SCRAPES_KV)?, &claims.sub).await {
+            Ok(taxonomy) => taxonomy,
+            Err(e) => return Response::error(&format!("Error: {}", e), 500)
+        },
+        false => Taxonomy::default(),
+    };
+    let total;
+    if enrich_first {
+        enrich(&fetcher, &mut articles, &enrichments, &taxonomy).await;
+        if let Some(languages) = &languages {
+            keep_languages(&mut articles, languages);
+        }
+        (articles, total) = query.select_articles(articles);
+    } else {
+        (articles, total) = query.select_articles(articles);
+        if !enrichments.is_empty() {
+            enrich(&fetcher, &mut articles, &enrichments, &taxonomy).await;
+        }
+    }
+    if options.group {
+        return Response::from_json(&json!({ "Result": group_stories(articles) }));
+    }
+    let records = query.project(articles_to_records(&articles));
+    if format == OutputFormat::Csv {
+        let columns = match query.fields.is_empty() {
+            true => record_columns(&records),
+            false => query.fields.clone(),
+        };
+        return csv_response(&columns, &records);
+    }
+    Response::from_json(&json!({ "Result": records, "Total": total }))
+}
+
+//GET /api/blocks?source= reports how often each source was blocked per day, for admins
+pub async fn get_block_stats(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    if let Err(e) = require_admin(&claims) {
+        return Ok(e);
+    }
+    let source = req.url()?.query_pairs().find(|(name, _)| name == "source").map(|(_, value)| value.to_string());
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match list_block_stats(&kv, source.as_deref()).await {
+        Ok(stats) => Response::from_json(&json!({ "Result": stats })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
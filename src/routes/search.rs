@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::search_index::search_articles;
+use crate::routes::utils::authenticate;
+use crate::scraper::search::SearchQuery;
+
+//GET /api/search?q=&from=&to=&source=&offset=&limit= searches the articles stored by the caller's jobs
+pub async fn search(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let params: HashMap<String, String> = req.url()?.query_pairs().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    let query = match SearchQuery::from_params(&params) {
+        Ok(query) => query,
+        Err(e) => return Ok(e.response())
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match search_articles(&kv, &claims.sub, &query).await {
+        Ok((hits, total)) => Response::from_json(&json!({ "Result": hits, "Total": total })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
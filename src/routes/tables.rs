@@ -0,0 +1,59 @@
+use scraper::Html;
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::routes::output::{csv_response, OutputFormat};
+use crate::routes::utils::{authenticate, request_fetcher};
+use crate::scraper::recipe::NodeSelector;
+use crate::scraper::table::extract_tables;
+
+//GET /api/tables?url=&selector=&table=&format=
+pub async fn get_tables(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let mut url = None;
+    let mut selector = None;
+    let mut table = None;
+    for (name, value) in req.url()?.query_pairs() {
+        match name.as_ref() {
+            "url" => url = Some(value.to_string()),
+            "selector" => selector = Some(value.to_string()),
+            "table" => table = value.parse::<usize>().ok(),
+            _ => {}
+        }
+    }
+    let url = match url {
+        Some(url) => url,
+        None => return Response::error("Error: url is required", 400)
+    };
+    let selector = match selector.as_deref().map(NodeSelector::parse).transpose() {
+        Ok(selector) => selector,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    let fetcher = match request_fetcher(&req, &ctx, &claims).await {
+        Ok(fetcher) => fetcher,
+        Err(e) => return Ok(e)
+    };
+    let body = match fetcher.fetch_html(&url).await {
+        Ok(body) => body,
+        Err(e) => return Ok(e.response())
+    };
+    let document = Html::parse_document(&body);
+    let mut tables = match extract_tables(&document, selector.as_ref()) {
+        Ok(tables) => tables,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    if let Some(index) = table {
+        tables.retain(|found| found.index == index);
+    }
+    match OutputFormat::from_request(&req)? {
+        //CSV holds a single table, the one picked with `table` or the first
+        OutputFormat::Csv => match tables.first() {
+            Some(first) => csv_response(&first.headers, &first.rows),
+            None => Response::error("Error: No table found", 404)
+        },
+        OutputFormat::Json => Response::from_json(&json!({ "Result": tables })),
+    }
+}
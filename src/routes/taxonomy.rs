@@ -0,0 +1,54 @@
+use serde_json::json;
+use worker::{Error, Request, Response, RouteContext};
+
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::taxonomies::{delete_taxonomy, get_taxonomy, save_taxonomy};
+use crate::routes::utils::authenticate;
+use crate::scraper::enrich::Taxonomy;
+
+//GET /api/taxonomy returns the topics used for `enrich=topic`, the built-in ones until the caller saves their own
+pub async fn get_user_taxonomy(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match get_taxonomy(&kv, &claims.sub).await {
+        Ok(Some(taxonomy)) => Response::from_json(&json!({ "Result": taxonomy, "Default": false })),
+        Ok(None) => Response::from_json(&json!({ "Result": Taxonomy::default(), "Default": true })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//PUT /api/taxonomy replaces the caller's taxonomy
+pub async fn put_user_taxonomy(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let taxonomy = match req.json::<Taxonomy>().await {
+        Ok(taxonomy) => taxonomy,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    if let Err(e) = taxonomy.validate() {
+        return Response::error(&format!("Error: {}", e), 400);
+    }
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match save_taxonomy(&kv, &claims.sub, &taxonomy).await {
+        Ok(_) => Response::from_json(&json!({ "Result": taxonomy })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//DELETE /api/taxonomy goes back to the built-in topics
+pub async fn remove_user_taxonomy(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match delete_taxonomy(&kv, &claims.sub).await {
+        Ok(_) => Response::from_json(&json!({ "Result": Taxonomy::default() })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
@@ -9,11 +9,15 @@ use worker::Error;
 use worker::RouteContext;
 use worker::Request;
 use worker::Response;
-use once_cell::sync::Lazy;
+use worker::Env;
 
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use crate::oauth::github_oauth::AuthResponse;
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::profiles::get_profile;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::profile::RequestProfile;
 
 enum AuthError {
     InvalidToken,
@@ -22,6 +26,8 @@ enum AuthError {
     TokenCreation,
     ClientIDOrSecret,
     MissingUserID,
+    InsufficientScope,
+    MissingSigningKey,
 }
 
 impl AuthError {
@@ -33,6 +39,8 @@ impl AuthError {
             AuthError::TokenCreation => "Token Creation Error".to_string(),
             AuthError::ClientIDOrSecret => "Invalid Client ID or Secret".to_string(),
             AuthError::MissingUserID => "Missing User ID".to_string(),
+            AuthError::InsufficientScope => "Insufficient Scope".to_string(),
+            AuthError::MissingSigningKey => "Missing Signing Key".to_string(),
         }
     }
     fn status(&self) -> u16 {
@@ -43,6 +51,8 @@ impl AuthError {
             AuthError::TokenCreation => 500,
             AuthError::ClientIDOrSecret => 400,
             AuthError::MissingUserID => 400,
+            AuthError::InsufficientScope => 403,
+            AuthError::MissingSigningKey => 500,
         }
     }
     fn response(&self) -> Response {
@@ -52,10 +62,19 @@ impl AuthError {
 
 
 #[derive(Serialize, Deserialize, Debug)]
-struct Claims {
-    sub: String,
-    company: String,
-    exp: usize,
+pub struct Claims {
+    pub sub: String,
+    pub company: String,
+    pub exp: usize,
+    //"admin" for logins listed in the ADMIN_USERS var, tokens issued before scopes existed have none
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl Claims {
+    pub fn is_admin(&self) -> bool {
+        self.scope == "admin"
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -94,10 +113,13 @@ impl Keys {
     }
 }
 
-
-//Must be obtained from the environment in PROD
-const key: &str = "secret";
-static KEYS: Lazy<Keys> = Lazy::new(|| Keys::new(key.as_bytes()));
+//Tokens are signed with the JWT_SECRET worker secret, set with `wrangler secret put JWT_SECRET`
+fn keys(env: &Env) -> Result<Keys, AuthError> {
+    match env.secret("JWT_SECRET") {
+        Ok(secret) if !secret.to_string().is_empty() => Ok(Keys::new(secret.to_string().as_bytes())),
+        _ => Err(AuthError::MissingSigningKey)
+    }
+}
 
 pub async fn authorize(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
     let client_id = match req.headers().get("client_id"){
@@ -116,6 +138,17 @@ pub async fn authorize(mut req: Request, ctx: RouteContext<()>) -> Result<Respon
         Err(_) => return Response::error(AuthError::MissingUserID.to_string(), AuthError::MissingUserID.status())
     };
 
+    //Comma separated GitHub logins that are issued admin scoped tokens
+    let admin_users = match ctx.var("ADMIN_USERS") {
+        Ok(admin_users) => admin_users.to_string(),
+        Err(_) => String::new()
+    };
+
+    let keys = match keys(&ctx.env) {
+        Ok(keys) => keys,
+        Err(e) => return Response::error(e.to_string(), e.status())
+    };
+
     //Create AuthPayload
     let auth_payload = AuthPayload {
         client_id: client_id.to_string(),
@@ -136,14 +169,19 @@ pub async fn authorize(mut req: Request, ctx: RouteContext<()>) -> Result<Respon
 
     //Cast AuthPayload to Header
     let header = Header::default();
+    let scope = if admin_users.split(',').any(|login| login.trim() == auth_reponse.login) {
+        "admin".to_string()
+    } else {
+        "user".to_string()
+    };
     let claims = Claims {
         sub: auth_reponse.login,
         company: auth_reponse.name,
         exp: get_current_timestamp() as usize + 3600,
-
+        scope,
     };
     //Create a new token
-    match encode(&header, &claims, &KEYS.encoding).map_err(|_| AuthError::TokenCreation) {
+    match encode(&header, &claims, &keys.encoding).map_err(|_| AuthError::TokenCreation) {
         Ok(token) => Response::from_json(&AuthBody::new_token(token)),
         Err(e) => Response::error(e.to_string(), 500)
     }
@@ -177,26 +215,78 @@ async fn check_credentials(auth_payload: AuthPayload, ctx: RouteContext<()>) ->
     }
 }
 
-pub async fn token_middleware(req: Request, ctx: RouteContext<()>) -> Result<Request, Response> {
+//Validates the bearer token and returns its claims, for routes that need to know the caller
+pub fn authenticate(req: &Request, ctx: &RouteContext<()>) -> Result<Claims, Response> {
     let token = match req.headers().get("Authorization") {
         Ok(Some(token)) => token,
         Ok(None) => return Err(AuthError::MissingToken.response()),
         Err(_) => return Err(AuthError::MissingToken.response())
     };
-    match validate_token(&token) {
-        Ok(_) => Ok(req),
+    let keys = match keys(&ctx.env) {
+        Ok(keys) => keys,
+        Err(e) => return Err(e.response())
+    };
+    match validate_token(&token, &keys) {
+        Ok(claims) => Ok(claims),
         Err(e) => Err(e.response())
     }
 }
 
-fn validate_token(token: &str) -> Result<&str, AuthError> {
+//For overrides that only admin scoped tokens may use
+pub fn require_admin(claims: &Claims) -> Result<(), Response> {
+    if claims.is_admin() {
+        Ok(())
+    } else {
+        Err(AuthError::InsufficientScope.response())
+    }
+}
+
+//Loads one of `owner`'s request profiles
+pub async fn load_profile(ctx: &RouteContext<()>, owner: &str, name: &str) -> Result<RequestProfile, Response> {
+    let kv = match ctx.kv(SCRAPES_KV) {
+        Ok(kv) => kv,
+        Err(e) => return Err(Response::error(&format!("Error: {}", e), 500).unwrap())
+    };
+    match get_profile(&kv, owner, name).await {
+        Ok(Some(stored)) => Ok(stored.profile),
+        Ok(None) => Err(Response::error(&format!("Error: Profile '{}' not found", name), 404).unwrap()),
+        Err(e) => Err(Response::error(&format!("Error: {}", e), 500).unwrap())
+    }
+}
+
+//Fetcher for a scraping route. `ignore_robots=true` in the query skips robots.txt for admins and
+//`profile=` sends the requests with one of the caller's request profiles
+pub async fn request_fetcher(req: &Request, ctx: &RouteContext<()>, claims: &Claims) -> Result<Fetcher, Response> {
+    let mut fetcher = Fetcher::from_env(&ctx.env);
+    let mut ignore_robots = false;
+    let mut profile = None;
+    if let Ok(url) = req.url() {
+        for (name, value) in url.query_pairs() {
+            match name.as_ref() {
+                "ignore_robots" => ignore_robots = value == "true",
+                "profile" => profile = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    if let Some(name) = profile {
+        fetcher = fetcher.with_profile(load_profile(ctx, &claims.sub, &name).await?);
+    }
+    if !ignore_robots {
+        return Ok(fetcher);
+    }
+    require_admin(claims)?;
+    Ok(fetcher.ignore_robots())
+}
+
+fn validate_token(token: &str, keys: &Keys) -> Result<Claims, AuthError> {
     if !token.starts_with("Bearer ") {
         return Err(AuthError::InvalidToken);
     }
 
     let token_data = decode::<Claims>(
         &token[7..],
-        &KEYS.decoding,
+        &keys.decoding,
         &Validation::default(),
     )
     .map_err(|e| {
@@ -208,7 +298,7 @@ fn validate_token(token: &str) -> Result<&str, AuthError> {
         return Err(AuthError::ExpiredToken);
     }
 
-    Ok(&token[7..])
+    Ok(token_data.claims)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -231,7 +321,7 @@ pub async fn generate_client_id_and_secrets(mut resp: AuthResponse) -> ClientCre
     }
 }
 
-fn generate_random_string(length: usize) -> String {
+pub fn generate_random_string(length: usize) -> String {
     let mut rng = thread_rng();
     let random_string: String = rng
         .sample_iter(&Alphanumeric)
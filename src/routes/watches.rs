@@ -0,0 +1,140 @@
+use serde::Deserialize;
+use serde_json::json;
+use worker::kv::KvStore;
+use worker::{Date, Error, Request, Response, RouteContext, Url};
+
+use crate::jobs::job::SCRAPES_KV;
+use crate::jobs::schedule::CronSchedule;
+use crate::jobs::watches::{check_watch, delete_watch, find_watches, get_watch, save_watch, Watch};
+use crate::routes::utils::{authenticate, generate_random_string};
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::recipe::Recipe;
+
+#[derive(Deserialize, Debug)]
+struct WatchPayload {
+    url: String,
+    #[serde(default)]
+    selector: Option<String>,
+    #[serde(default)]
+    recipe: Option<Recipe>,
+    #[serde(default)]
+    key: Option<String>,
+    schedule: String,
+    webhook_url: String,
+}
+
+async fn load_owned_watch(kv: &KvStore, id: &str, owner: &str) -> Result<Watch, Response> {
+    match get_watch(kv, id).await {
+        Ok(Some(watch)) if watch.owner == owner => Ok(watch),
+        Ok(_) => Err(Response::error("Watch not found", 404).unwrap()),
+        Err(e) => Err(Response::error(&format!("Error: {}", e), 500).unwrap())
+    }
+}
+
+fn validate_payload(payload: &WatchPayload) -> Result<(), String> {
+    Url::parse(&payload.url).map_err(|e| format!("Invalid url: {}", e))?;
+    Url::parse(&payload.webhook_url).map_err(|e| format!("Invalid webhook_url: {}", e))?;
+    CronSchedule::parse(&payload.schedule).map_err(|e| format!("Invalid schedule: {}", e))?;
+    match (&payload.recipe, &payload.selector) {
+        (Some(recipe), _) => recipe.validate(),
+        (None, Some(selector)) => Recipe::from_selector(selector).validate(),
+        (None, None) => Err("Either selector or recipe is required".to_string())
+    }
+}
+
+pub async fn create_watch(mut req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let payload = match req.json::<WatchPayload>().await {
+        Ok(payload) => payload,
+        Err(e) => return Response::error(&format!("Error: {}", e), 400)
+    };
+    if let Err(e) = validate_payload(&payload) {
+        return Response::error(&format!("Error: {}", e), 400);
+    }
+    let watch = Watch {
+        id: generate_random_string(16),
+        owner: claims.sub,
+        url: payload.url,
+        selector: payload.selector,
+        recipe: payload.recipe,
+        key: payload.key,
+        schedule: payload.schedule,
+        webhook_url: payload.webhook_url,
+        secret: generate_random_string(32),
+        created_at: Date::now().as_millis(),
+        last_checked: None,
+        last_changed: None,
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match save_watch(&kv, &watch).await {
+        //The signing secret is only returned here
+        Ok(_) => Response::from_json(&json!({ "Result": watch })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+pub async fn get_watches(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match find_watches(&kv, |summary| summary.owner == claims.sub).await {
+        Ok(watches) => {
+            let watches: Vec<Watch> = watches.iter().map(|watch| watch.redacted()).collect();
+            Response::from_json(&json!({ "Result": watches }))
+        }
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+pub async fn get_watch_by_id(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    match load_owned_watch(&kv, &id, &claims.sub).await {
+        Ok(watch) => Response::from_json(&json!({ "Result": watch.redacted() })),
+        Err(e) => Ok(e)
+    }
+}
+
+pub async fn remove_watch(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let watch = match load_owned_watch(&kv, &id, &claims.sub).await {
+        Ok(watch) => watch,
+        Err(e) => return Ok(e)
+    };
+    match delete_watch(&kv, &watch.id).await {
+        Ok(_) => Response::from_json(&json!({ "Result": watch.id })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
+
+//POST /api/watches/:id/check evaluates a watch immediately instead of waiting for its schedule
+pub async fn check_watch_now(req: Request, ctx: RouteContext<()>) -> Result<Response, Error> {
+    let claims = match authenticate(&req, &ctx) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e)
+    };
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let kv = ctx.kv(SCRAPES_KV)?;
+    let watch = match load_owned_watch(&kv, &id, &claims.sub).await {
+        Ok(watch) => watch,
+        Err(e) => return Ok(e)
+    };
+    match check_watch(&kv, &Fetcher::from_env(&ctx.env), watch).await {
+        Ok(diff) => Response::from_json(&json!({ "Result": diff })),
+        Err(e) => Response::error(&format!("Error: {}", e), 500)
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+//Challenge pages are small. Cloudflare adds its challenge script to ordinary pages it serves and widgets on larger
+//pages are more likely a contact form, so neither counts on a larger successful page
+const CHALLENGE_PAGE_BYTES: usize = 30_000;
+//A page with fewer elements and less text than this is an error or interstitial, not the listing that was expected
+const TINY_PAGE_ELEMENTS: usize = 25;
+const TINY_PAGE_TEXT: usize = 300;
+
+//Titles, script sources and form targets that only show up on bot challenges
+const CHALLENGE_MARKERS: [&str; 8] = [
+    "attention required! | cloudflare",
+    "just a moment...",
+    "access to this page has been denied",
+    "/sorry/index",
+    "__cf_chl_",
+    "/cdn-cgi/challenge-platform/",
+    "captcha-delivery.com",
+    "captcha.px-cdn.net",
+];
+//Scripts of captcha widgets, also embedded in ordinary forms
+const CAPTCHA_WIDGETS: [&str; 3] = ["recaptcha/api.js", "hcaptcha.com/1/api.js", "challenges.cloudflare.com/turnstile"];
+//Cookie walls that replace the page until consent is given
+const CONSENT_MARKERS: [&str; 3] = ["before you continue to google", "consent.google.com/save", "consent.youtube.com/save"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockReason {
+    Captcha,
+    Consent,
+    RateLimited,
+    Forbidden,
+    TinyPage,
+}
+
+impl BlockReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockReason::Captcha => "captcha",
+            BlockReason::Consent => "consent",
+            BlockReason::RateLimited => "rate_limited",
+            BlockReason::Forbidden => "forbidden",
+            BlockReason::TinyPage => "tiny_page",
+        }
+    }
+}
+
+impl fmt::Display for BlockReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+//The title, script and frame sources and form targets of a page, lowercased. The text of the page is left out as
+//articles quote challenge wording
+fn challenge_signals(body: &str) -> String {
+    let document = Html::parse_document(body);
+    let selector = Selector::parse("title, script[src], iframe[src], form[action]").unwrap();
+    document
+        .select(&selector)
+        .map(|element| match element.value().name() {
+            "title" => element.text().collect::<String>(),
+            "form" => element.value().attr("action").unwrap_or("").to_string(),
+            _ => element.value().attr("src").unwrap_or("").to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+        .to_lowercase()
+}
+
+fn has_captcha(status: u16, body: &str) -> bool {
+    let small = body.len() <= CHALLENGE_PAGE_BYTES;
+    if (200..300).contains(&status) && !small {
+        return false;
+    }
+    let signals = challenge_signals(body);
+    CHALLENGE_MARKERS.iter().any(|marker| signals.contains(marker))
+        || (small && CAPTCHA_WIDGETS.iter().any(|marker| signals.contains(marker)))
+}
+
+//Classifies a response as a bot block from its status, the URL it ended up at after redirects and its body
+pub fn detect_block(status: u16, url: &Url, body: &str) -> Option<BlockReason> {
+    let host = url.host_str().unwrap_or("");
+    if url.path().starts_with("/sorry/") || has_captcha(status, body) {
+        return Some(BlockReason::Captcha);
+    }
+    let body = body.to_lowercase();
+    if host.starts_with("consent.") || CONSENT_MARKERS.iter().any(|marker| body.contains(marker)) {
+        return Some(BlockReason::Consent);
+    }
+    match status {
+        429 => Some(BlockReason::RateLimited),
+        401 | 403 => Some(BlockReason::Forbidden),
+        _ => None,
+    }
+}
+
+//Whether a page that matched nothing is too small to be the page that was asked for
+pub fn is_tiny_document(document: &Html) -> bool {
+    let body_selector = Selector::parse("body *").unwrap();
+    let elements = document.select(&body_selector).count();
+    let text: usize = document.root_element().text().map(|text| text.trim().len()).sum();
+    elements < TINY_PAGE_ELEMENTS && text < TINY_PAGE_TEXT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url() -> Url {
+        Url::parse("https://example.com/news").unwrap()
+    }
+
+    #[test]
+    fn detects_challenge_title() {
+        let body = "<html><head><title>Just a moment...</title></head><body></body></html>";
+        assert_eq!(detect_block(200, &url(), body), Some(BlockReason::Captcha));
+    }
+
+    #[test]
+    fn ignores_challenge_wording_in_text() {
+        let body = "<html><head><title>News</title></head><body><p>Please verify you are a human, the site said.</p></body></html>";
+        assert_eq!(detect_block(200, &url(), body), None);
+    }
+
+    #[test]
+    fn ignores_challenge_script_on_large_pages() {
+        let text = "<p>Story</p>".repeat(CHALLENGE_PAGE_BYTES / 10);
+        let body = format!("<html><head><script src=\"/cdn-cgi/challenge-platform/scripts/jsd/main.js\"></script></head><body>{}</body></html>", text);
+        assert_eq!(detect_block(200, &url(), &body), None);
+        assert_eq!(detect_block(403, &url(), &body), Some(BlockReason::Captcha));
+    }
+
+    #[test]
+    fn detects_sorry_redirect_and_statuses() {
+        let sorry = Url::parse("https://www.google.com/sorry/index?continue=x").unwrap();
+        assert_eq!(detect_block(200, &sorry, ""), Some(BlockReason::Captcha));
+        assert_eq!(detect_block(429, &url(), "<html></html>"), Some(BlockReason::RateLimited));
+        assert_eq!(detect_block(403, &url(), "<html></html>"), Some(BlockReason::Forbidden));
+    }
+}
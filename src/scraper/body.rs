@@ -0,0 +1,99 @@
+use std::cmp::Reverse;
+
+use ego_tree::NodeId;
+use futures::stream::{self, StreamExt};
+use scraper::{ElementRef, Html, Selector};
+
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::scraper::Article;
+use crate::scraper::transform::collapse_whitespace;
+
+//Article pages fetched per request and how many at once
+pub const MAX_EXPANDED: usize = 20;
+const EXPAND_CONCURRENCY: usize = 5;
+//Paragraphs shorter than this are captions, bylines or buttons
+const MIN_PARAGRAPH_CHARS: usize = 40;
+const MAX_BODY_CHARS: usize = 20_000;
+
+//Paragraph text inside these is page furniture rather than the article
+fn in_boilerplate(paragraph: &ElementRef) -> bool {
+    paragraph.ancestors().filter_map(ElementRef::wrap).any(|ancestor| {
+        let element = ancestor.value();
+        matches!(element.name(), "nav" | "header" | "footer" | "aside" | "form" | "figcaption")
+            || element.attr("role").is_some_and(|role| role == "navigation" || role == "complementary")
+    })
+}
+
+//Main text of an article page: the paragraphs of the element whose own paragraphs hold the most text
+pub fn extract_body(document: &Html) -> Option<String> {
+    let paragraph_selector = Selector::parse("p").unwrap();
+    let paragraphs: Vec<(Option<NodeId>, String)> = document
+        .select(&paragraph_selector)
+        .filter(|paragraph| !in_boilerplate(paragraph))
+        .map(|paragraph| (paragraph.parent().map(|parent| parent.id()), collapse_whitespace(&paragraph.text().collect::<String>())))
+        .filter(|(_, text)| text.chars().count() >= MIN_PARAGRAPH_CHARS)
+        .collect();
+    //Parents in document order, so a tie goes to the one that comes first
+    let mut scores: Vec<(Option<NodeId>, usize)> = vec![];
+    for (parent, text) in &paragraphs {
+        match scores.iter_mut().find(|(seen, _)| seen == parent) {
+            Some((_, score)) => *score += text.chars().count(),
+            None => scores.push((*parent, text.chars().count())),
+        }
+    }
+    let best = scores.into_iter().min_by_key(|(_, score)| Reverse(*score))?.0;
+    let body = paragraphs
+        .into_iter()
+        .filter(|(parent, _)| *parent == best)
+        .map(|(_, text)| text)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Some(body.chars().take(MAX_BODY_CHARS).collect())
+}
+
+//Fetches the first MAX_EXPANDED articles and fills in their bodies. A page that cannot be fetched or has no
+//paragraphs leaves its body unset
+pub async fn expand_articles(fetcher: &Fetcher, articles: &mut [Article]) {
+    let links: Vec<String> = articles.iter().take(MAX_EXPANDED).map(|article| article.link.clone()).collect();
+    let bodies: Vec<Option<String>> = stream::iter(links)
+        .map(|link| async move {
+            let html = fetcher.fetch_html(&link).await.ok()?;
+            extract_body(&Html::parse_document(&html))
+        })
+        .buffered(EXPAND_CONCURRENCY)
+        .collect()
+        .await;
+    for (article, body) in articles.iter_mut().zip(bodies) {
+        article.body = body;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "The first paragraph of the story is long enough to count as body text.";
+    const SECOND: &str = "The other paragraph of the story is long enough to count as body text.";
+
+    #[test]
+    fn takes_the_paragraphs_of_the_richest_parent() {
+        let html = format!(
+            "<html><body><nav><p>{first}</p><p>{first}</p><p>{first}</p></nav><div><p>Short caption</p></div>\
+             <article><p>{first}</p><p>{second}</p></article><footer><p>{second}</p></footer></body></html>",
+            first = FIRST,
+            second = SECOND
+        );
+        let body = extract_body(&Html::parse_document(&html)).unwrap();
+        assert_eq!(body, format!("{}\n\n{}", FIRST, SECOND));
+        assert_eq!(extract_body(&Html::parse_document("<p>Too short</p>")), None);
+    }
+
+    #[test]
+    fn breaks_ties_by_document_order() {
+        let html = format!("<div id=\"a\"><p>{}</p></div><div id=\"b\"><p>{}</p></div>", FIRST, SECOND);
+        for _ in 0..20 {
+            assert_eq!(extract_body(&Html::parse_document(&html)), Some(FIRST.to_string()));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,360 @@
+use std::collections::{HashSet, VecDeque};
+
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use worker::Date;
+
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::recipe::{extract_records, Match, NodeSelector, Record, Recipe};
+use crate::scraper::xpath::Evaluator;
+
+//Hard limits so a single request stays within the worker's budget
+const MAX_PAGES: u32 = 100;
+const MAX_TIME_BUDGET_MS: u64 = 25_000;
+
+fn default_same_domain() -> bool {
+    true
+}
+
+fn default_max_depth() -> u32 {
+    2
+}
+
+fn default_max_pages() -> u32 {
+    10
+}
+
+fn default_time_budget_ms() -> u64 {
+    15_000
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrawlOptions {
+    pub url: String,
+    pub recipe: Recipe,
+    //Link to the next page of a listing, followed without increasing the depth
+    #[serde(default)]
+    pub next_selector: Option<String>,
+    //Links whose absolute URL matches one of these are followed
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_same_domain")]
+    pub same_domain: bool,
+    #[serde(default = "default_max_depth")]
+    pub max_depth: u32,
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    #[serde(default = "default_time_budget_ms")]
+    pub time_budget_ms: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CrawledPage {
+    pub url: String,
+    pub page_index: usize,
+    pub depth: u32,
+    pub records: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CrawlResult {
+    pub records: Vec<Record>,
+    pub pages: Vec<CrawledPage>,
+    //Why the crawl ended before the frontier was empty, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<String>,
+}
+
+//Compiled form of the options, validated and built once per crawl before any page is fetched
+pub struct CrawlPlan {
+    start: Url,
+    recipe: Recipe,
+    next_selector: Option<NodeSelector>,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    same_domain: bool,
+    max_depth: u32,
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>, ScrapeError> {
+    patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| ScrapeError::InvalidInput(format!("Invalid pattern '{}': {}", pattern, e))))
+        .collect()
+}
+
+impl CrawlPlan {
+    pub fn new(options: &CrawlOptions) -> Result<Self, ScrapeError> {
+        options.recipe.validate().map_err(ScrapeError::InvalidInput)?;
+        let start = Url::parse(&options.url).map_err(|e| ScrapeError::InvalidInput(format!("{}: {}", options.url, e)))?;
+        let next_selector = match &options.next_selector {
+            Some(selector) => Some(NodeSelector::parse(selector).map_err(ScrapeError::InvalidInput)?),
+            None => None,
+        };
+        Ok(CrawlPlan {
+            start,
+            recipe: options.recipe.clone(),
+            next_selector,
+            include: compile_patterns(&options.include)?,
+            exclude: compile_patterns(&options.exclude)?,
+            same_domain: options.same_domain,
+            max_depth: options.max_depth,
+        })
+    }
+
+    fn allows(&self, url: &Url) -> bool {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return false;
+        }
+        if self.same_domain && url.host_str() != self.start.host_str() {
+            return false;
+        }
+        !self.exclude.iter().any(|pattern| pattern.is_match(url.as_str()))
+    }
+}
+
+//Fragments never change the page so they are dropped before comparing URLs
+fn normalize(mut url: Url) -> Url {
+    url.set_fragment(None);
+    url
+}
+
+//Where a crawl has got to, so it can be carried on by a later invocation
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CrawlState {
+    //URLs still to crawl with their depth
+    frontier: VecDeque<(String, u32)>,
+    seen: HashSet<String>,
+    //Pages crawled so far, the next page's index
+    pub pages: usize,
+}
+
+impl CrawlState {
+    pub fn new(plan: &CrawlPlan) -> Self {
+        let start = normalize(plan.start.clone()).to_string();
+        let mut state = CrawlState::default();
+        state.seen.insert(start.clone());
+        state.frontier.push_back((start, 0));
+        state
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frontier.is_empty()
+    }
+
+    //Pages discovered but not crawled yet
+    pub fn pending(&self) -> usize {
+        self.frontier.len()
+    }
+}
+
+//Page limit and deadline for a crawl starting now, the options capped by the hard limits
+pub fn crawl_budget(options: &CrawlOptions) -> (usize, u64) {
+    let deadline = Date::now().as_millis() + options.time_budget_ms.min(MAX_TIME_BUDGET_MS);
+    (page_limit(options), deadline)
+}
+
+fn page_limit(options: &CrawlOptions) -> usize {
+    options.max_pages.clamp(1, MAX_PAGES) as usize
+}
+
+//Crawls from `options.url`, applying the recipe to every page and tagging records with their provenance
+pub async fn crawl(fetcher: &Fetcher, options: &CrawlOptions) -> Result<CrawlResult, ScrapeError> {
+    let plan = CrawlPlan::new(options)?;
+    let mut state = CrawlState::new(&plan);
+    let (max_pages, deadline) = crawl_budget(options);
+    crawl_pages(fetcher, &plan, &mut state, max_pages, deadline).await
+}
+
+//Crawls pages off the state's frontier until it is empty, `max_pages` have been crawled in total or the deadline
+//passes. Pages left on the frontier stay in the state
+pub async fn crawl_pages(
+    fetcher: &Fetcher,
+    plan: &CrawlPlan,
+    state: &mut CrawlState,
+    max_pages: usize,
+    deadline: u64,
+) -> Result<CrawlResult, ScrapeError> {
+    let mut result = CrawlResult::default();
+
+    while !state.frontier.is_empty() {
+        if state.pages >= max_pages {
+            result.stopped = Some("max_pages".to_string());
+            break;
+        }
+        if Date::now().as_millis() >= deadline {
+            result.stopped = Some("time_budget".to_string());
+            break;
+        }
+        let (url, depth) = match state.frontier.pop_front() {
+            Some((url, depth)) => match Url::parse(&url) {
+                Ok(url) => (url, depth),
+                Err(_) => continue,
+            },
+            None => break,
+        };
+        let page_index = state.pages;
+        state.pages += 1;
+        let body = match fetcher.fetch_html(url.as_str()).await {
+            Ok(body) => body,
+            Err(e) => {
+                result.pages.push(CrawledPage {
+                    url: url.to_string(),
+                    page_index,
+                    depth,
+                    records: 0,
+                    error: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+        let records = visit_page(plan, state, &url, depth, page_index, &Html::parse_document(&body))?;
+        result.pages.push(CrawledPage {
+            url: url.to_string(),
+            page_index,
+            depth,
+            records: records.len(),
+            error: None,
+        });
+        result.records.extend(records);
+    }
+    Ok(result)
+}
+
+//Applies the recipe to one crawled page, tagging records with their provenance, and queues the links it leads to
+fn visit_page(
+    plan: &CrawlPlan,
+    state: &mut CrawlState,
+    url: &Url,
+    depth: u32,
+    page_index: usize,
+    document: &Html,
+) -> Result<Vec<Record>, ScrapeError> {
+    let mut records = extract_records(document, &plan.recipe, url).map_err(ScrapeError::InvalidInput)?;
+    for record in records.iter_mut() {
+        record.insert("source_url".to_string(), Value::String(url.to_string()));
+        record.insert("page_index".to_string(), Value::from(page_index));
+    }
+
+    let mut discovered: Vec<(Url, u32)> = vec![];
+    if let Some(next_selector) = &plan.next_selector {
+        //An XPath may select the href itself, e.g. `xpath://a[@rel='next']/@href`
+        let evaluator = Evaluator::new(document);
+        for link in next_selector.select_document(&evaluator, document).map_err(ScrapeError::InvalidInput)? {
+            let href = match &link {
+                Match::Element(element) => element.value().attr("href"),
+                Match::Text(href) => Some(href.as_str()),
+            };
+            if let Some(next) = href.and_then(|href| url.join(href.trim()).ok()) {
+                discovered.push((normalize(next), depth));
+            }
+        }
+    }
+    if !plan.include.is_empty() && depth < plan.max_depth {
+        let link_selector = Selector::parse("a[href]").unwrap();
+        for link in document.select(&link_selector) {
+            if let Some(target) = link.value().attr("href").and_then(|href| url.join(href).ok()) {
+                if plan.include.iter().any(|pattern| pattern.is_match(target.as_str())) {
+                    discovered.push((normalize(target), depth + 1));
+                }
+            }
+        }
+    }
+    for (target, target_depth) in discovered {
+        if plan.allows(&target) && state.seen.insert(target.to_string()) {
+            state.frontier.push_back((target.to_string(), target_depth));
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <h2>First</h2><h2>Second</h2>
+        <a href="/articles/1#comments">One</a>
+        <a href="https://example.com/articles/2">Two</a>
+        <a href="/articles/drafts/3">Draft</a>
+        <a href="/about">About</a>
+        <a href="https://other.org/articles/4">Elsewhere</a>
+        <a href="mailto:news@example.com">Mail</a>
+        <a class="next" href="?page=2">Next</a>
+    </body></html>"#;
+
+    fn options(extra: Value) -> CrawlOptions {
+        let mut options = json!({"url": "https://example.com/list", "recipe": {"item_selector": "h2", "fields": {}}});
+        if let (Value::Object(options), Value::Object(extra)) = (&mut options, extra) {
+            options.extend(extra);
+        }
+        serde_json::from_value(options).unwrap()
+    }
+
+    fn visit(options: &CrawlOptions, depth: u32) -> (Vec<Record>, Vec<(String, u32)>) {
+        let plan = CrawlPlan::new(options).unwrap();
+        let mut state = CrawlState::new(&plan);
+        let (url, _) = state.frontier.pop_front().unwrap();
+        let records = visit_page(&plan, &mut state, &Url::parse(&url).unwrap(), depth, 3, &Html::parse_document(PAGE)).unwrap();
+        (records, state.frontier.into_iter().collect())
+    }
+
+    #[test]
+    fn tags_records_with_their_page() {
+        let (records, _) = visit(&options(json!({})), 0);
+        assert_eq!(records.len(), 2);
+        for record in &records {
+            assert_eq!(record.get("source_url"), Some(&json!("https://example.com/list")));
+            assert_eq!(record.get("page_index"), Some(&json!(3)));
+        }
+    }
+
+    #[test]
+    fn follows_included_links_that_are_not_excluded() {
+        let (_, frontier) = visit(&options(json!({"include": ["/articles/"], "exclude": ["/drafts/"]})), 0);
+        assert_eq!(
+            frontier,
+            vec![("https://example.com/articles/1".to_string(), 1), ("https://example.com/articles/2".to_string(), 1)]
+        );
+        //Without patterns only the next page selector adds links
+        let (_, frontier) = visit(&options(json!({})), 0);
+        assert!(frontier.is_empty());
+    }
+
+    #[test]
+    fn leaves_the_site_only_when_asked() {
+        let (_, frontier) = visit(&options(json!({"include": ["articles/4", "mailto:"], "same_domain": false})), 0);
+        assert_eq!(frontier, vec![("https://other.org/articles/4".to_string(), 1)]);
+        let (_, frontier) = visit(&options(json!({"include": ["articles/4"]})), 0);
+        assert!(frontier.is_empty());
+    }
+
+    #[test]
+    fn stops_following_links_at_max_depth() {
+        let crawl = options(json!({"include": ["/articles/"], "max_depth": 1, "next_selector": "a.next"}));
+        let (_, frontier) = visit(&crawl, 0);
+        assert_eq!(frontier.len(), 4);
+        //The next page keeps its depth and is still followed at the limit
+        let (_, frontier) = visit(&crawl, 1);
+        assert_eq!(frontier, vec![("https://example.com/list?page=2".to_string(), 1)]);
+    }
+
+    #[test]
+    fn checks_options_and_caps_pages() {
+        assert_eq!(page_limit(&options(json!({}))), 10);
+        assert_eq!(page_limit(&options(json!({"max_pages": 0}))), 1);
+        assert_eq!(page_limit(&options(json!({"max_pages": 5000}))), MAX_PAGES as usize);
+        assert!(CrawlPlan::new(&options(json!({"include": ["("]}))).is_err());
+        assert!(CrawlPlan::new(&options(json!({"url": "not a url"}))).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::scraper::scraper::Article;
+
+//Google News links are relative to the site root
+const GOOGLE_NEWS_BASE: &str = "https://news.google.com/";
+
+//Titles whose SimHashes differ in at most this many bits are treated as the same story
+const SIMHASH_DISTANCE: u32 = 3;
+//Jaccard similarity of title shingles above which two headlines are grouped together
+const SHINGLE_SIMILARITY: f64 = 0.5;
+
+//Query parameters that only track the click and never change the page
+const TRACKING_PARAMS: [&str; 9] = ["fbclid", "gclid", "ocid", "cmpid", "ref", "smid", "mc_cid", "mc_eid", "taid"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    pub url: String,
+    pub simhash: u64,
+}
+
+//Stable 64 bit FNV-1a, fingerprints are persisted so they must not depend on the std hasher
+pub fn fnv1a(value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in value.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+//Lower cases the host, drops `www.`, the fragment, tracking parameters and trailing slashes
+pub fn canonical_url(link: &str) -> String {
+    let base = Url::parse(GOOGLE_NEWS_BASE).unwrap();
+    let mut url = match base.join(link) {
+        Ok(url) => url,
+        Err(_) => return link.trim().to_lowercase(),
+    };
+    url.set_fragment(None);
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    params.sort();
+    let host = url.host_str().unwrap_or("").to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let path = url.path().trim_end_matches('/');
+    let mut canonical = format!("{}{}", host, path);
+    if !params.is_empty() {
+        let query: Vec<String> = params.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        canonical.push('?');
+        canonical.push_str(&query.join("&"));
+    }
+    canonical
+}
+
+//Lower cased words with punctuation removed
+pub fn normalize_title(title: &str) -> Vec<String> {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_string())
+        .collect()
+}
+
+//Word bigrams of the normalized title, short titles fall back to single words
+pub fn shingles(title: &str) -> HashSet<String> {
+    let words = normalize_title(title);
+    if words.len() < 3 {
+        return words.into_iter().collect();
+    }
+    words.windows(2).map(|pair| pair.join(" ")).collect()
+}
+
+pub fn simhash(title: &str) -> u64 {
+    let mut weights = [0i32; 64];
+    for shingle in shingles(title) {
+        let hash = fnv1a(&shingle);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+    let mut simhash = 0u64;
+    for (bit, weight) in weights.iter().enumerate() {
+        if *weight > 0 {
+            simhash |= 1 << bit;
+        }
+    }
+    simhash
+}
+
+pub fn fingerprint(article: &Article) -> Fingerprint {
+    Fingerprint {
+        url: format!("{:016x}", fnv1a(&canonical_url(&article.link))),
+        simhash: simhash(&article.title),
+    }
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let intersection = a.intersection(b).count() as f64;
+    let union = a.union(b).count() as f64;
+    intersection / union
+}
+
+pub fn is_near_duplicate(a: &Fingerprint, b: &Fingerprint) -> bool {
+    a.url == b.url || (a.simhash ^ b.simhash).count_ones() <= SIMHASH_DISTANCE
+}
+
+//Fingerprints already returned to a client, oldest first
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SeenStore {
+    pub fingerprints: Vec<Fingerprint>,
+}
+
+impl SeenStore {
+    //Bounded so the KV value stays small
+    const CAPACITY: usize = 5000;
+
+    pub fn has_seen(&self, fingerprint: &Fingerprint) -> bool {
+        self.fingerprints.iter().any(|seen| is_near_duplicate(seen, fingerprint))
+    }
+
+    pub fn remember(&mut self, fingerprint: Fingerprint) {
+        if !self.fingerprints.contains(&fingerprint) {
+            self.fingerprints.push(fingerprint);
+        }
+        if self.fingerprints.len() > Self::CAPACITY {
+            let excess = self.fingerprints.len() - Self::CAPACITY;
+            self.fingerprints.drain(..excess);
+        }
+    }
+}
+
+//Drops articles that share a canonical URL or a near identical title with one kept earlier
+pub fn dedupe(articles: Vec<Article>) -> Vec<Article> {
+    let mut kept: Vec<(Fingerprint, Article)> = vec![];
+    for article in articles {
+        let print = fingerprint(&article);
+        if kept.iter().any(|(seen, _)| is_near_duplicate(seen, &print)) {
+            continue;
+        }
+        kept.push((print, article));
+    }
+    kept.into_iter().map(|(_, article)| article).collect()
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoryGroup {
+    pub representative: Article,
+    pub size: usize,
+    pub articles: Vec<Article>,
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut node = index;
+    while parents[node] != root {
+        let next = parents[node];
+        parents[node] = root;
+        node = next;
+    }
+    root
+}
+
+//Clusters near duplicate headlines, the earliest article of each cluster represents it
+pub fn group_stories(articles: Vec<Article>) -> Vec<StoryGroup> {
+    let prints: Vec<Fingerprint> = articles.iter().map(fingerprint).collect();
+    let shingle_sets: Vec<HashSet<String>> = articles.iter().map(|article| shingles(&article.title)).collect();
+    let mut parents: Vec<usize> = (0..articles.len()).collect();
+    for i in 0..articles.len() {
+        for j in (i + 1)..articles.len() {
+            if is_near_duplicate(&prints[i], &prints[j]) || jaccard(&shingle_sets[i], &shingle_sets[j]) >= SHINGLE_SIMILARITY {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                if a != b {
+                    parents[b] = a;
+                }
+            }
+        }
+    }
+    let mut groups: Vec<(usize, Vec<Article>)> = vec![];
+    for (index, article) in articles.into_iter().enumerate() {
+        let root = find(&mut parents, index);
+        match groups.iter_mut().find(|(group_root, _)| *group_root == root) {
+            Some((_, members)) => members.push(article),
+            None => groups.push((root, vec![article])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, members)| {
+            //Times are RFC 3339 so they order lexicographically, articles without a time sort last
+            let representative = members
+                .iter()
+                .min_by(|a, b| match (a.time.is_empty(), b.time.is_empty()) {
+                    (false, true) => std::cmp::Ordering::Less,
+                    (true, false) => std::cmp::Ordering::Greater,
+                    _ => a.time.cmp(&b.time),
+                })
+                .cloned()
+                .unwrap();
+            StoryGroup {
+                representative,
+                size: members.len(),
+                articles: members,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scraper::enrich::Enrichment;
+
+    use super::*;
+
+    fn article(title: &str, link: &str, time: &str) -> Article {
+        Article {
+            title: title.to_string(),
+            link: link.to_string(),
+            time: time.to_string(),
+            author: String::new(),
+            sourcelink: String::new(),
+            sourcename: String::new(),
+            image_link: String::new(),
+            body: None,
+            publisher: None,
+            enrichment: Enrichment::default(),
+        }
+    }
+
+    #[test]
+    fn canonicalizes_urls() {
+        assert_eq!(canonical_url("https://WWW.Example.com/story/?utm_source=x&b=2&fbclid=y&a=1#top"), "example.com/story?a=1&b=2");
+        assert_eq!(canonical_url("./articles/abc"), "news.google.com/articles/abc");
+        assert_eq!(fnv1a(""), 0xcbf29ce484222325);
+    }
+
+    #[test]
+    fn simhash_ignores_case_and_punctuation() {
+        let title = "Central bank raises interest rates to fight rising inflation across the region";
+        let same = simhash("CENTRAL BANK raises interest rates, to fight rising inflation across the region!");
+        let other = simhash("Local team wins the championship after dramatic overtime finish");
+        assert_eq!(simhash(title), same);
+        assert!((simhash(title) ^ other).count_ones() > SIMHASH_DISTANCE);
+    }
+
+    #[test]
+    fn dedupes_by_url_and_title() {
+        let articles = vec![
+            article("Storm hits the coast overnight", "https://example.com/a?utm_medium=rss", "2024-01-01T10:00:00Z"),
+            article("Something else entirely", "https://www.example.com/a/", "2024-01-01T11:00:00Z"),
+            article("Storm hits the coast overnight.", "https://other.com/b", "2024-01-01T12:00:00Z"),
+            article("Markets close higher on tech rally", "https://other.com/c", "2024-01-01T13:00:00Z"),
+        ];
+        let kept: Vec<String> = dedupe(articles).into_iter().map(|article| article.link).collect();
+        assert_eq!(kept, vec!["https://example.com/a?utm_medium=rss", "https://other.com/c"]);
+    }
+
+    #[test]
+    fn seen_store_is_bounded() {
+        let mut store = SeenStore::default();
+        let print = fingerprint(&article("Storm hits the coast overnight", "https://example.com/a", ""));
+        store.remember(print.clone());
+        store.remember(print.clone());
+        assert_eq!(store.fingerprints.len(), 1);
+        assert!(store.has_seen(&fingerprint(&article("Storm hits the coast overnight", "https://elsewhere.com/x", ""))));
+        for index in 0..SeenStore::CAPACITY + 10 {
+            store.remember(Fingerprint { url: index.to_string(), simhash: index as u64 });
+        }
+        assert_eq!(store.fingerprints.len(), SeenStore::CAPACITY);
+        assert!(!store.fingerprints.contains(&print));
+    }
+
+    #[test]
+    fn groups_stories_under_the_earliest_article() {
+        let articles = vec![
+            article("Election results delayed in three states", "https://a.com/1", "2024-01-02T10:00:00Z"),
+            article("Election results delayed in three states, officials say", "https://b.com/2", "2024-01-01T09:00:00Z"),
+            article("New phone released with bigger screen", "https://c.com/3", ""),
+        ];
+        let groups = group_stories(articles);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].size, 2);
+        assert_eq!(groups[0].representative.link, "https://b.com/2");
+        assert_eq!(groups[1].representative.link, "https://c.com/3");
+    }
+}
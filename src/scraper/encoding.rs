@@ -0,0 +1,87 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+//How far into the document a <meta charset> or XML declaration is looked for
+const PRESCAN_BYTES: usize = 4096;
+
+//Matches `<meta charset="...">`, `<meta http-equiv="Content-Type" content="text/html; charset=...">`
+//and `<?xml version="1.0" encoding="..."?>`
+static DECLARED_CHARSET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i-u)<meta[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)|<\?xml[^>]*?encoding\s*=\s*["']([a-z0-9_:.\-]+)"#).unwrap()
+});
+
+fn from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        match name.trim().eq_ignore_ascii_case("charset") {
+            true => Encoding::for_label(value.trim().trim_matches(|c| c == '"' || c == '\'').as_bytes()),
+            false => None,
+        }
+    })
+}
+
+fn from_declaration(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(PRESCAN_BYTES)];
+    let captures = DECLARED_CHARSET.captures(head)?;
+    let label = captures.get(1).or_else(|| captures.get(2))?;
+    let encoding = Encoding::for_label(label.as_bytes())?;
+    //A document that could be read this far as ASCII cannot be UTF-16, browsers treat that declaration as UTF-8
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        return Some(UTF_8);
+    }
+    Some(encoding)
+}
+
+//Picks the encoding the way browsers do: byte order mark, then the Content-Type charset, then a declaration
+//in the document and finally a statistical guess, which `tld` (e.g. "jp") helps with
+pub fn sniff_encoding(bytes: &[u8], content_type: Option<&str>, tld: Option<&str>) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if let Some(encoding) = content_type.and_then(from_content_type) {
+        return encoding;
+    }
+    if let Some(encoding) = from_declaration(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(tld.map(|tld| tld.as_bytes()), true)
+}
+
+//Decodes a fetched document to text, malformed sequences become U+FFFD
+pub fn decode_document(bytes: &[u8], content_type: Option<&str>, tld: Option<&str>) -> String {
+    let encoding = sniff_encoding(bytes, content_type, tld);
+    let (text, _) = encoding.decode_with_bom_removal(bytes);
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    use super::*;
+
+    #[test]
+    fn follows_the_browser_order() {
+        let declared = b"<html><head><meta charset=\"shift_jis\"></head></html>";
+        assert_eq!(sniff_encoding(b"\xef\xbb\xbfabc", Some("text/html; charset=windows-1252"), None), UTF_8);
+        assert_eq!(sniff_encoding(declared, Some("text/html; charset=\"windows-1252\""), None), WINDOWS_1252);
+        assert_eq!(sniff_encoding(declared, Some("text/html"), None), SHIFT_JIS);
+        assert_eq!(sniff_encoding(b"<?xml version=\"1.0\" encoding=\"UTF-16\"?><urlset/>", None, None), UTF_8);
+        assert_eq!(sniff_encoding("plain café".as_bytes(), None, None), UTF_8);
+    }
+
+    #[test]
+    fn decodes_legacy_encodings() {
+        let (bytes, _, _) = SHIFT_JIS.encode("<p>東京のニュース</p>");
+        assert_eq!(decode_document(&bytes, Some("text/html; charset=Shift_JIS"), None), "<p>東京のニュース</p>");
+        assert_eq!(decode_document(b"caf\xe9", Some("text/html; charset=iso-8859-1"), None), "café");
+        assert_eq!(decode_document(b"\xef\xbb\xbfhello", None, None), "hello");
+    }
+}
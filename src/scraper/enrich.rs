@@ -0,0 +1,552 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scraper::body::expand_articles;
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::language::detect_language;
+use crate::scraper::scraper::Article;
+use crate::scraper::sentiment::{score, Sentiment};
+use crate::scraper::summary::{summarize, DEFAULT_SUMMARY_SENTENCES, MAX_SUMMARY_SENTENCES};
+use crate::scraper::text::{is_stopword, tokenize, Token};
+
+const MAX_KEYWORDS: usize = 5;
+const MAX_ENTITIES: usize = 10;
+//Longest keyword phrase and entity name, in words
+const MAX_PHRASE_WORDS: usize = 3;
+const MAX_ENTITY_WORDS: usize = 4;
+//Limits on a user's taxonomy
+const MAX_TOPICS: usize = 50;
+const MAX_TOPIC_TERMS: usize = 200;
+//Lower case words allowed inside a name, e.g. "Bank of England"
+const NAME_CONNECTORS: [&str; 8] = ["of", "de", "del", "da", "la", "van", "von", "for"];
+
+//Names worth recognising even without capitals, with the name they are reported under and their type
+const GAZETTEER: [(&str, &str, &str); 58] = [
+    ("united states", "United States", "place"),
+    ("u.s", "United States", "place"),
+    ("us", "United States", "place"),
+    ("usa", "United States", "place"),
+    ("america", "United States", "place"),
+    ("united kingdom", "United Kingdom", "place"),
+    ("uk", "United Kingdom", "place"),
+    ("britain", "United Kingdom", "place"),
+    ("china", "China", "place"),
+    ("russia", "Russia", "place"),
+    ("ukraine", "Ukraine", "place"),
+    ("india", "India", "place"),
+    ("japan", "Japan", "place"),
+    ("germany", "Germany", "place"),
+    ("france", "France", "place"),
+    ("italy", "Italy", "place"),
+    ("spain", "Spain", "place"),
+    ("canada", "Canada", "place"),
+    ("mexico", "Mexico", "place"),
+    ("brazil", "Brazil", "place"),
+    ("australia", "Australia", "place"),
+    ("israel", "Israel", "place"),
+    ("iran", "Iran", "place"),
+    ("gaza", "Gaza", "place"),
+    ("taiwan", "Taiwan", "place"),
+    ("north korea", "North Korea", "place"),
+    ("south korea", "South Korea", "place"),
+    ("europe", "Europe", "place"),
+    ("africa", "Africa", "place"),
+    ("asia", "Asia", "place"),
+    ("new york", "New York", "place"),
+    ("london", "London", "place"),
+    ("washington", "Washington", "place"),
+    ("beijing", "Beijing", "place"),
+    ("paris", "Paris", "place"),
+    ("tokyo", "Tokyo", "place"),
+    ("california", "California", "place"),
+    ("texas", "Texas", "place"),
+    ("united nations", "United Nations", "organization"),
+    ("un", "United Nations", "organization"),
+    ("european union", "European Union", "organization"),
+    ("eu", "European Union", "organization"),
+    ("nato", "NATO", "organization"),
+    ("white house", "White House", "organization"),
+    ("congress", "Congress", "organization"),
+    ("senate", "Senate", "organization"),
+    ("supreme court", "Supreme Court", "organization"),
+    ("federal reserve", "Federal Reserve", "organization"),
+    ("fed", "Federal Reserve", "organization"),
+    ("pentagon", "Pentagon", "organization"),
+    ("kremlin", "Kremlin", "organization"),
+    ("apple", "Apple", "organization"),
+    ("google", "Google", "organization"),
+    ("microsoft", "Microsoft", "organization"),
+    ("amazon", "Amazon", "organization"),
+    ("tesla", "Tesla", "organization"),
+    ("nvidia", "Nvidia", "organization"),
+    ("openai", "OpenAI", "organization"),
+];
+
+//Terms that place an article under a topic, used when a user has not saved their own taxonomy
+const DEFAULT_TAXONOMY: [(&str, &[&str]); 8] = [
+    ("business", &["economy", "market", "markets", "stocks", "shares", "inflation", "earnings", "bank", "trade", "tariffs", "investors", "interest rates", "profit", "company", "deal"]),
+    ("entertainment", &["film", "movie", "music", "album", "actor", "actress", "celebrity", "box office", "netflix", "concert", "series", "festival"]),
+    ("health", &["health", "hospital", "vaccine", "virus", "covid", "disease", "cancer", "doctors", "patients", "medical", "outbreak", "drug"]),
+    ("politics", &["election", "president", "senate", "congress", "parliament", "minister", "vote", "campaign", "government", "lawmakers", "policy", "democrats", "republicans"]),
+    ("science", &["science", "scientists", "study", "research", "space", "nasa", "climate", "species", "planet", "researchers", "telescope"]),
+    ("sports", &["game", "match", "season", "league", "coach", "championship", "tournament", "cup", "score", "nba", "nfl", "football", "soccer", "olympics"]),
+    ("technology", &["technology", "tech", "ai", "artificial intelligence", "software", "app", "apple", "google", "microsoft", "chip", "chips", "startup", "iphone", "cyber"]),
+    ("world", &["war", "military", "troops", "ceasefire", "embassy", "sanctions", "refugees", "border", "strike", "attack", "diplomats"]),
+];
+
+//Which enrichments to compute, picked with `enrich=keywords,entities,topic,summary,sentiment,language` or `all`, plus `expand=true`
+//to return article bodies and `summary_sentences=`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnrichOptions {
+    pub keywords: bool,
+    pub entities: bool,
+    pub topic: bool,
+    pub summary: bool,
+    pub sentiment: bool,
+    pub language: bool,
+    pub expand: bool,
+    pub summary_sentences: usize,
+}
+
+impl Default for EnrichOptions {
+    fn default() -> Self {
+        EnrichOptions {
+            keywords: false,
+            entities: false,
+            topic: false,
+            summary: false,
+            sentiment: false,
+            language: false,
+            expand: false,
+            summary_sentences: DEFAULT_SUMMARY_SENTENCES,
+        }
+    }
+}
+
+impl EnrichOptions {
+    //Reads the options from request or job params, other params are left for the source
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, ScrapeError> {
+        let mut options = EnrichOptions {
+            expand: params.get("expand").is_some_and(|expand| expand == "true"),
+            ..EnrichOptions::default()
+        };
+        let names = params.get("enrich").map(String::as_str).unwrap_or("");
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "keywords" => options.keywords = true,
+                "entities" => options.entities = true,
+                "topic" => options.topic = true,
+                "summary" => options.summary = true,
+                "sentiment" => options.sentiment = true,
+                "language" => options.language = true,
+                "all" => {
+                    options.keywords = true;
+                    options.entities = true;
+                    options.topic = true;
+                    options.summary = true;
+                    options.sentiment = true;
+                    options.language = true;
+                }
+                _ => return Err(ScrapeError::InvalidInput(format!("Unknown enrichment: {}", name))),
+            }
+        }
+        if let Some(sentences) = params.get("summary_sentences") {
+            options.summary_sentences = match sentences.parse::<usize>() {
+                Ok(sentences) if (1..=MAX_SUMMARY_SENTENCES).contains(&sentences) => sentences,
+                _ => return Err(ScrapeError::InvalidInput(format!("summary_sentences must be between 1 and {}", MAX_SUMMARY_SENTENCES))),
+            };
+        }
+        Ok(options)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.keywords || self.entities || self.topic || self.sentiment || self.language || self.needs_body())
+    }
+
+    //Summaries are made from the article page, so it has to be fetched
+    pub fn needs_body(&self) -> bool {
+        self.expand || self.summary
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub text: String,
+    //place or organization for names from the gazetteer, unset for other capitalised names
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+//Fields computed from an article's text, only present when asked for
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Enrichment {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entities: Option<Vec<Entity>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    //Extractive summary of the body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentiment: Option<Sentiment>,
+    //ISO 639-1 code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+//Topic names with the words and phrases that point to them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Taxonomy {
+    pub topics: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Taxonomy {
+    fn default() -> Self {
+        let topics = DEFAULT_TAXONOMY
+            .iter()
+            .map(|(topic, terms)| (topic.to_string(), terms.iter().map(|term| term.to_string()).collect()))
+            .collect();
+        Taxonomy { topics }
+    }
+}
+
+impl Taxonomy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.topics.is_empty() || self.topics.len() > MAX_TOPICS {
+            return Err(format!("A taxonomy needs between 1 and {} topics", MAX_TOPICS));
+        }
+        for (topic, terms) in &self.topics {
+            if topic.trim().is_empty() || topic.len() > 64 {
+                return Err(format!("Invalid topic name: '{}'", topic));
+            }
+            if terms.is_empty() || terms.len() > MAX_TOPIC_TERMS {
+                return Err(format!("Topic '{}' needs between 1 and {} terms", topic, MAX_TOPIC_TERMS));
+            }
+            if terms.iter().any(|term| term.trim().is_empty()) {
+                return Err(format!("Topic '{}' has an empty term", topic));
+            }
+        }
+        Ok(())
+    }
+
+    //The topic whose terms occur most often in the text, the first in name order on a tie
+    pub fn classify(&self, words: &[String]) -> Option<String> {
+        let mut best: Option<(&String, usize)> = None;
+        for (topic, terms) in &self.topics {
+            let hits: usize = terms.iter().map(|term| count_phrase(words, term)).sum();
+            if hits > 0 && best.is_none_or(|(_, most)| hits > most) {
+                best = Some((topic, hits));
+            }
+        }
+        best.map(|(topic, _)| topic.clone())
+    }
+}
+
+//Occurrences of a term, which may be several words, in lower case words
+fn count_phrase(words: &[String], term: &str) -> usize {
+    let term: Vec<String> = tokenize(term).iter().map(Token::lower).collect();
+    if term.is_empty() || term.len() > words.len() {
+        return 0;
+    }
+    words.windows(term.len()).filter(|window| *window == term.as_slice()).count()
+}
+
+//Words the enrichments are computed from, the title then the body when it was fetched
+fn article_tokens(article: &Article) -> Vec<Token> {
+    let mut tokens = tokenize(&article.title);
+    if let Some(body) = &article.body {
+        tokens.extend(tokenize(body));
+    }
+    tokens
+}
+
+fn is_content_word(word: &str) -> bool {
+    word.chars().count() > 1 && !is_stopword(word) && !word.chars().all(|c| c.is_numeric())
+}
+
+//RAKE candidates: runs of content words broken by stopwords and punctuation, long runs are cut into pieces
+fn candidate_phrases(tokens: &[Token]) -> Vec<Vec<String>> {
+    let mut phrases: Vec<Vec<String>> = vec![];
+    let mut current: Vec<String> = vec![];
+    for token in tokens {
+        let word = token.lower();
+        let breaks = token.phrase_start || !is_content_word(&word) || current.len() == MAX_PHRASE_WORDS;
+        if breaks && !current.is_empty() {
+            phrases.push(std::mem::take(&mut current));
+        }
+        if is_content_word(&word) {
+            current.push(word);
+        }
+    }
+    if !current.is_empty() {
+        phrases.push(current);
+    }
+    phrases
+}
+
+//Keywords of one text: phrases ranked by RAKE (word degree over frequency) weighted by how rare their words are
+//across all the articles being enriched
+fn keywords(tokens: &[Token], idf: &dyn Fn(&str) -> f64) -> Vec<String> {
+    let phrases = candidate_phrases(tokens);
+    let mut frequency: HashMap<&str, f64> = HashMap::new();
+    let mut degree: HashMap<&str, f64> = HashMap::new();
+    for phrase in &phrases {
+        for word in phrase {
+            *frequency.entry(word).or_insert(0.0) += 1.0;
+            *degree.entry(word).or_insert(0.0) += phrase.len() as f64;
+        }
+    }
+    let mut scored: Vec<(String, f64)> = vec![];
+    for phrase in &phrases {
+        let rake: f64 = phrase.iter().map(|word| degree[word.as_str()] / frequency[word.as_str()]).sum();
+        let rarity = phrase.iter().map(|word| idf(word)).sum::<f64>() / phrase.len() as f64;
+        let text = phrase.join(" ");
+        if !scored.iter().any(|(seen, _)| *seen == text) {
+            scored.push((text, rake * rarity));
+        }
+    }
+    //Stable, so equal scores keep the order they appear in
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.into_iter().take(MAX_KEYWORDS).map(|(text, _)| text).collect()
+}
+
+fn gazetteer_entry(words: &[String]) -> Option<(&'static str, &'static str)> {
+    let name = words.join(" ");
+    GAZETTEER.iter().find(|(alias, _, _)| *alias == name).map(|(_, canonical, kind)| (*canonical, *kind))
+}
+
+//Most words of a headline written in title case are capitalised, which makes capitals useless for spotting names
+fn is_title_case(tokens: &[Token]) -> bool {
+    let content: Vec<&Token> = tokens.iter().filter(|token| !is_stopword(&token.lower())).collect();
+    let capitalized = content.iter().filter(|token| token.is_capitalized()).count();
+    content.len() >= 4 && capitalized * 10 >= content.len() * 7
+}
+
+//Named entity candidates: gazetteer names, then runs of capitalised words. Title case text only yields gazetteer
+//names and acronyms
+fn entities(tokens: &[Token]) -> Vec<Entity> {
+    let mut found: Vec<Entity> = vec![];
+    let mut add = |entity: Entity| {
+        if !found.iter().any(|seen| seen.text.eq_ignore_ascii_case(&entity.text)) {
+            found.push(entity);
+        }
+    };
+    let title_case = is_title_case(tokens);
+    let lower: Vec<String> = tokens.iter().map(Token::lower).collect();
+    let mut index = 0;
+    while index < tokens.len() {
+        //Gazetteer names must still be capitalised so "us" and "fed" in running text are not taken for names
+        let known = (1..=MAX_ENTITY_WORDS.min(tokens.len() - index)).rev().find_map(|length| {
+            let words = &tokens[index..index + length];
+            let joined = words.iter().skip(1).all(|token| !token.phrase_start);
+            let entry = gazetteer_entry(&lower[index..index + length])?;
+            (joined && words[0].is_capitalized() && (words[0].text.len() > 2 || words[0].text.chars().all(|c| !c.is_lowercase())))
+                .then_some((length, entry))
+        });
+        if let Some((length, (canonical, kind))) = known {
+            add(Entity { text: canonical.to_string(), kind: Some(kind.to_string()) });
+            index += length;
+            continue;
+        }
+        let token = &tokens[index];
+        let acronym = token.text.chars().count() > 1 && token.text.chars().all(|c| c.is_uppercase() || c.is_numeric() || c == '.');
+        if !token.is_capitalized() || (title_case && !acronym) {
+            index += 1;
+            continue;
+        }
+        //Extend over capitalised words and connectors that lead to another capitalised word
+        let mut end = index + 1;
+        while !title_case && end < tokens.len() && end - index < MAX_ENTITY_WORDS && !tokens[end].phrase_start {
+            if tokens[end].is_capitalized() {
+                end += 1;
+            } else if NAME_CONNECTORS.contains(&lower[end].as_str())
+                && tokens.get(end + 1).is_some_and(|next| next.is_capitalized() && !next.phrase_start)
+            {
+                end += 2;
+            } else {
+                break;
+            }
+        }
+        let mut words: Vec<&Token> = tokens[index..end].iter().collect();
+        while words.first().is_some_and(|word| is_stopword(&word.lower())) {
+            words.remove(0);
+        }
+        //A lone capitalised word opening a sentence is usually just the first word
+        let lone_opener = words.len() == 1 && words[0].sentence_start && !acronym;
+        if !words.is_empty() && !lone_opener {
+            let text = words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ");
+            add(Entity { text, kind: None });
+        }
+        index = end;
+    }
+    found.truncate(MAX_ENTITIES);
+    found
+}
+
+//Adds the requested enrichments to every article. Keyword rarity is measured across the articles given
+pub fn enrich_articles(articles: &mut [Article], options: &EnrichOptions, taxonomy: &Taxonomy) {
+    if options.is_empty() {
+        return;
+    }
+    let tokenized: Vec<Vec<Token>> = articles.iter().map(article_tokens).collect();
+    let mut document_frequency: HashMap<String, usize> = HashMap::new();
+    for tokens in &tokenized {
+        let words: HashSet<String> = tokens.iter().map(Token::lower).collect();
+        for word in words {
+            *document_frequency.entry(word).or_insert(0) += 1;
+        }
+    }
+    let documents = articles.len() as f64;
+    let idf = |word: &str| {
+        let frequency = document_frequency.get(word).copied().unwrap_or(0) as f64;
+        ((documents + 1.0) / (frequency + 1.0)).ln() + 1.0
+    };
+    for (article, tokens) in articles.iter_mut().zip(tokenized.iter()) {
+        if options.keywords {
+            article.enrichment.keywords = Some(keywords(tokens, &idf));
+        }
+        if options.entities {
+            //Title case in the headline must not turn off name spotting in the body, so they are looked at apart
+            let title = tokenize(&article.title);
+            let mut found = entities(&title);
+            for entity in entities(&tokens[title.len()..]) {
+                if !found.iter().any(|seen| seen.text.eq_ignore_ascii_case(&entity.text)) {
+                    found.push(entity);
+                }
+            }
+            found.truncate(MAX_ENTITIES);
+            article.enrichment.entities = Some(found);
+        }
+        if options.topic {
+            let words: Vec<String> = tokens.iter().map(Token::lower).collect();
+            article.enrichment.topic = taxonomy.classify(&words);
+        }
+        if options.summary {
+            article.enrichment.summary = article.body.as_deref().and_then(|body| summarize(body, options.summary_sentences));
+        }
+        if options.language {
+            let text = match &article.body {
+                Some(body) => format!("{}\n{}", article.title, body),
+                None => article.title.clone(),
+            };
+            article.enrichment.language = detect_language(&text).map(str::to_string);
+        }
+        if options.sentiment {
+            article.enrichment.sentiment = Some(Sentiment {
+                title: score(&article.title),
+                body: article.body.as_deref().map(score),
+            });
+        }
+    }
+}
+
+//Fetches article bodies when they are needed and adds the requested enrichments. Bodies are only returned when
+//`expand` was asked for
+pub async fn enrich(fetcher: &Fetcher, articles: &mut [Article], options: &EnrichOptions, taxonomy: &Taxonomy) {
+    if options.needs_body() {
+        expand_articles(fetcher, articles).await;
+    }
+    enrich_articles(articles, options, taxonomy);
+    if !options.expand {
+        for article in articles.iter_mut() {
+            article.body = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn words(text: &str) -> Vec<String> {
+        tokenize(text).iter().map(Token::lower).collect()
+    }
+
+    fn names(entities: &[Entity]) -> Vec<(&str, Option<&str>)> {
+        entities.iter().map(|entity| (entity.text.as_str(), entity.kind.as_deref())).collect()
+    }
+
+    #[test]
+    fn phrases_break_at_stopwords_punctuation_and_length() {
+        let phrases = candidate_phrases(&tokenize("Central bank raises interest rates, markets fall in 2024"));
+        assert_eq!(phrases, vec![vec!["central", "bank", "raises"], vec!["interest", "rates"], vec!["markets", "fall"]]);
+    }
+
+    #[test]
+    fn keywords_rank_by_degree_and_rarity() {
+        let tokens = tokenize("Solar panel prices drop. Solar panel makers struggle as the drop continues.");
+        let found = keywords(&tokens, &|_| 1.0);
+        assert_eq!(found[0], "solar panel prices");
+        assert!(found.iter().all(|keyword| !keyword.split(' ').any(is_stopword)));
+        //With equal RAKE scores the rarer word comes first
+        let rarity = |word: &str| if word == "beta" { 2.0 } else { 1.0 };
+        assert_eq!(keywords(&tokenize("alpha. beta."), &rarity), vec!["beta", "alpha"]);
+    }
+
+    #[test]
+    fn finds_names_and_gazetteer_entries() {
+        let found = entities(&tokenize("Officials from NATO held long talks with Angela Merkel and the Bank of England in Paris over several days, aides said on Tuesday."));
+        assert_eq!(
+            names(&found),
+            vec![
+                ("NATO", Some("organization")),
+                ("Angela Merkel", None),
+                ("Bank of England", None),
+                ("Paris", Some("place")),
+                ("Tuesday", None),
+            ]
+        );
+        //Lower case words are not names, even when they are in the gazetteer
+        assert!(entities(&tokenize("Join us for a chat about the fed")).is_empty());
+    }
+
+    #[test]
+    fn title_case_headlines_only_yield_known_names_and_acronyms() {
+        let tokens = tokenize("Apple Unveils New Chip Designs For AI Data Centers");
+        assert!(is_title_case(&tokens));
+        assert_eq!(names(&entities(&tokens)), vec![("Apple", Some("organization")), ("AI", None)]);
+        assert!(!is_title_case(&tokenize("Apple unveils new chip designs for data centers")));
+    }
+
+    #[test]
+    fn classifies_by_most_term_hits() {
+        let taxonomy = Taxonomy::default();
+        assert_eq!(taxonomy.classify(&words("The election campaign rattled the stock market")), Some("politics".to_string()));
+        assert_eq!(taxonomy.classify(&words("Investors weigh interest rates")), Some("business".to_string()));
+        assert_eq!(taxonomy.classify(&words("A quiet afternoon")), None);
+        //A tie goes to the first topic by name
+        assert_eq!(taxonomy.classify(&words("The film opened during the war")), Some("entertainment".to_string()));
+    }
+
+    #[test]
+    fn validates_taxonomies() {
+        assert!(Taxonomy::default().validate().is_ok());
+        assert!(Taxonomy { topics: BTreeMap::new() }.validate().is_err());
+        let mut topics = BTreeMap::new();
+        topics.insert("cars".to_string(), vec!["engine".to_string(), " ".to_string()]);
+        assert!(Taxonomy { topics: topics.clone() }.validate().is_err());
+        topics.insert("cars".to_string(), vec![]);
+        assert!(Taxonomy { topics }.validate().is_err());
+    }
+
+    #[test]
+    fn reads_options_from_params() {
+        let options = EnrichOptions::from_params(&params(&[("enrich", "keywords, topic")])).unwrap();
+        assert!(options.keywords && options.topic && !options.entities && !options.summary);
+        assert!(!options.needs_body());
+        let options = EnrichOptions::from_params(&params(&[("enrich", "all"), ("summary_sentences", "2")])).unwrap();
+        assert!(options.needs_body() && options.language && options.summary_sentences == 2);
+        assert!(EnrichOptions::from_params(&params(&[])).unwrap().is_empty());
+        assert!(!EnrichOptions::from_params(&params(&[("expand", "true")])).unwrap().is_empty());
+        assert!(EnrichOptions::from_params(&params(&[("enrich", "mood")])).is_err());
+        assert!(EnrichOptions::from_params(&params(&[("summary_sentences", "0")])).is_err());
+    }
+}
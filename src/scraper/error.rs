@@ -0,0 +1,48 @@
+use std::fmt;
+
+use serde_json::json;
+use worker::Response;
+
+use crate::scraper::block::BlockReason;
+
+#[derive(Debug, Clone)]
+pub enum ScrapeError {
+    Disallowed(String),
+    //The site answered with a captcha, consent wall or similar instead of the page
+    Blocked(String, BlockReason),
+    Fetch(String),
+    InvalidInput(String),
+    NoResults,
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScrapeError::Disallowed(url) => write!(f, "Disallowed by robots.txt: {}", url),
+            ScrapeError::Blocked(url, reason) => write!(f, "Blocked ({}): {}", reason, url),
+            ScrapeError::Fetch(e) => write!(f, "Fetch Error: {}", e),
+            ScrapeError::InvalidInput(e) => write!(f, "Invalid Input: {}", e),
+            ScrapeError::NoResults => write!(f, "No articles found"),
+        }
+    }
+}
+
+impl ScrapeError {
+    pub fn status(&self) -> u16 {
+        match self {
+            ScrapeError::Disallowed(_) => 403,
+            ScrapeError::Blocked(_, _) => 503,
+            ScrapeError::Fetch(_) => 502,
+            ScrapeError::InvalidInput(_) => 400,
+            ScrapeError::NoResults => 500,
+        }
+    }
+    pub fn response(&self) -> Response {
+        //Blocks get a body clients can tell apart from other failures
+        if let ScrapeError::Blocked(url, reason) = self {
+            let body = json!({ "Status": "blocked", "Reason": reason, "Url": url });
+            return Response::from_json(&body).unwrap().with_status(self.status());
+        }
+        Response::error(&format!("Error: {}", self), self.status()).unwrap()
+    }
+}
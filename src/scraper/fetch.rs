@@ -0,0 +1,408 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::DateTime;
+use rand::{thread_rng, Rng};
+use reqwest::header::{ACCEPT_LANGUAGE, CONTENT_TYPE, COOKIE, RETRY_AFTER, SET_COOKIE, USER_AGENT};
+use reqwest::{Client, Method, Response, StatusCode, Url};
+use worker::kv::KvStore;
+use worker::{Date, Env};
+
+use crate::jobs::job::SCRAPES_KV;
+use crate::scraper::block::detect_block;
+use crate::scraper::encoding::decode_document;
+use crate::scraper::error::ScrapeError;
+use crate::scraper::profile::{CookieJar, RequestProfile};
+use crate::scraper::robots::Robots;
+use crate::utils::sleep;
+
+pub const DEFAULT_USER_AGENT: &str = "web-scraper-wasm/0.1 (+https://api.shivadityas.com)";
+
+//robots.txt is cached for a day, an unreachable one is retried after an hour
+const ROBOTS_TTL: u64 = 60 * 60 * 24;
+const ROBOTS_ERROR_TTL: u64 = 60 * 60;
+//Crawl-delay is capped so one request cannot stall for minutes
+const MAX_CRAWL_DELAY_MS: u64 = 10_000;
+//Network errors, 429 and 5xx are retried with jittered exponential backoff
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE_MS: u64 = 500;
+const RETRY_MAX_MS: u64 = 8_000;
+//A Retry-After longer than this is not waited out, the request fails instead
+const MAX_RETRY_AFTER_MS: u64 = 20_000;
+//Requests in flight to one host at a time, the rest wait for a free slot
+const MAX_CONCURRENT_PER_HOST: usize = 4;
+const HOST_SLOT_POLL_MS: u64 = 50;
+
+thread_local! {
+    //robots.txt per origin along with when the entry expires
+    static ROBOTS_CACHE: RefCell<HashMap<String, (u64, Robots)>> = RefCell::new(HashMap::new());
+    //Next time each host may be fetched when it asks for a Crawl-delay
+    static NEXT_FETCH: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
+    //Requests currently in flight per host
+    static IN_FLIGHT: RefCell<HashMap<String, usize>> = RefCell::new(HashMap::new());
+}
+
+//One of a host's concurrent request slots, given back when dropped
+struct HostSlot {
+    host: String,
+}
+
+impl HostSlot {
+    async fn acquire(host: &str) -> Self {
+        loop {
+            let acquired = IN_FLIGHT.with(|in_flight| {
+                let mut in_flight = in_flight.borrow_mut();
+                let count = in_flight.entry(host.to_string()).or_insert(0);
+                if *count >= MAX_CONCURRENT_PER_HOST {
+                    return false;
+                }
+                *count += 1;
+                true
+            });
+            if acquired {
+                return HostSlot { host: host.to_string() };
+            }
+            sleep(HOST_SLOT_POLL_MS).await;
+        }
+    }
+}
+
+impl Drop for HostSlot {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|in_flight| {
+            let mut in_flight = in_flight.borrow_mut();
+            if let Some(count) = in_flight.get_mut(&self.host) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    in_flight.remove(&self.host);
+                }
+            }
+        });
+    }
+}
+
+//501 means the server will never support the request, so it is not worth retrying
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || (status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED)
+}
+
+//Retry-After as either delay seconds or an HTTP date, measured from `now`
+fn retry_after_ms(response: &Response, now: u64) -> Option<u64> {
+    parse_retry_after(response.headers().get(RETRY_AFTER)?.to_str().ok()?, now)
+}
+
+fn parse_retry_after(value: &str, now: u64) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        //Anything too large to count in milliseconds is past the retry cap anyway
+        return Some(seconds.saturating_mul(1000));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?.timestamp_millis().max(0) as u64;
+    Some(at.saturating_sub(now))
+}
+
+//Exponential backoff with the upper half of the window picked at random, so retries from
+//concurrent requests do not land on the host at the same moment
+fn backoff_ms(attempt: u32) -> u64 {
+    let ceiling = (RETRY_BASE_MS << attempt).min(RETRY_MAX_MS);
+    thread_rng().gen_range(ceiling / 2..=ceiling)
+}
+
+//Every scraper fetch goes through here so robots.txt and politeness rules are applied in one place
+#[derive(Clone)]
+pub struct Fetcher {
+    pub user_agent: String,
+    pub respect_robots: bool,
+    cache: Option<KvStore>,
+    profile: RequestProfile,
+    //Shared by clones, so the pages of one crawl rotate through the same pool and keep the same cookies
+    rotation: Rc<Cell<usize>>,
+    cookies: Option<Rc<RefCell<CookieJar>>>,
+}
+
+impl Fetcher {
+    //Uses the `SCRAPER_USER_AGENT` var when set and caches robots.txt in KV
+    pub fn from_env(env: &Env) -> Self {
+        let user_agent = match env.var("SCRAPER_USER_AGENT") {
+            Ok(user_agent) => user_agent.to_string(),
+            Err(_) => DEFAULT_USER_AGENT.to_string(),
+        };
+        Fetcher {
+            user_agent,
+            respect_robots: true,
+            cache: env.kv(SCRAPES_KV).ok(),
+            profile: RequestProfile::default(),
+            rotation: Rc::new(Cell::new(0)),
+            cookies: None,
+        }
+    }
+
+    //Sends requests the way the profile describes, with a fresh cookie jar when it keeps cookies. robots.txt and
+    //Crawl-delay stay matched against the scraper's own user agent, a profile's only go out in the header
+    pub fn with_profile(mut self, profile: RequestProfile) -> Self {
+        self.cookies = match profile.cookies {
+            true => Some(Rc::new(RefCell::new(CookieJar::default()))),
+            false => None,
+        };
+        self.profile = profile;
+        self
+    }
+
+    fn next_user_agent(&self) -> String {
+        match self.profile.user_agents.len() {
+            0 => self.user_agent.clone(),
+            len => {
+                let index = self.rotation.get();
+                self.rotation.set(index.wrapping_add(1));
+                self.profile.user_agents[index % len].clone()
+            }
+        }
+    }
+
+    pub fn ignore_robots(mut self) -> Self {
+        self.respect_robots = false;
+        self
+    }
+
+    pub async fn robots(&self, url: &Url) -> Robots {
+        let origin = url.origin().ascii_serialization();
+        let now = Date::now().as_millis();
+        let cached = ROBOTS_CACHE.with(|cache| match cache.borrow().get(&origin) {
+            Some((expires_at, robots)) if *expires_at > now => Some(robots.clone()),
+            _ => None,
+        });
+        if let Some(robots) = cached {
+            return robots;
+        }
+        let key = format!("robots:{}", origin);
+        if let Some(kv) = &self.cache {
+            if let Ok(Some(robots)) = kv.get(&key).json::<Robots>().await {
+                ROBOTS_CACHE.with(|cache| cache.borrow_mut().insert(origin, (now + ROBOTS_TTL * 1000, robots.clone())));
+                return robots;
+            }
+        }
+        let response = match Url::parse(&format!("{}/robots.txt", origin)) {
+            Ok(robots_url) => self.execute(Method::GET, robots_url).await,
+            Err(e) => Err(ScrapeError::InvalidInput(e.to_string())),
+        };
+        let (robots, ttl) = match response {
+            Ok((response, _slot)) if response.status().is_success() => match response.text().await {
+                Ok(body) => (Robots::parse(&body), ROBOTS_TTL),
+                Err(_) => (Robots::disallow_all(), ROBOTS_ERROR_TTL),
+            },
+            //A rate limited or refused robots.txt says nothing about what is allowed, so the host is left alone and asked again later
+            Ok((response, _)) if matches!(response.status().as_u16(), 401 | 403 | 429) => (Robots::disallow_all(), ROBOTS_ERROR_TTL),
+            Ok((response, _)) if response.status().is_client_error() => (Robots::allow_all(), ROBOTS_TTL),
+            _ => (Robots::disallow_all(), ROBOTS_ERROR_TTL),
+        };
+        if let Some(kv) = &self.cache {
+            if let Ok(robots_json) = serde_json::to_string(&robots) {
+                if let Ok(put) = kv.put(&key, robots_json) {
+                    let _ = put.expiration_ttl(ttl).execute().await;
+                }
+            }
+        }
+        ROBOTS_CACHE.with(|cache| cache.borrow_mut().insert(origin, (now + ttl * 1000, robots.clone())));
+        robots
+    }
+
+    //Waits until the host's Crawl-delay has passed since the previous fetch, reserving the next slot first
+    //so concurrent fetches queue up behind each other
+    async fn wait_for_turn(&self, host: &str, delay_ms: u64) {
+        let wait = NEXT_FETCH.with(|next_fetch| {
+            let mut next_fetch = next_fetch.borrow_mut();
+            let now = Date::now().as_millis();
+            let slot = match next_fetch.get(host) {
+                Some(next) => (*next).max(now),
+                None => now,
+            };
+            next_fetch.insert(host.to_string(), slot + delay_ms);
+            slot - now
+        });
+        if wait > 0 {
+            sleep(wait).await;
+        }
+    }
+
+    //Checks robots.txt and waits out the host's Crawl-delay
+    async fn check_allowed(&self, url: &str) -> Result<Url, ScrapeError> {
+        let parsed = Url::parse(url).map_err(|e| ScrapeError::InvalidInput(format!("{}: {}", url, e)))?;
+        if self.respect_robots {
+            let robots = self.robots(&parsed).await;
+            let mut path = parsed.path().to_string();
+            if let Some(query) = parsed.query() {
+                path.push('?');
+                path.push_str(query);
+            }
+            if !robots.is_allowed(&self.user_agent, &path) {
+                return Err(ScrapeError::Disallowed(url.to_string()));
+            }
+            if let Some(delay) = robots.crawl_delay(&self.user_agent) {
+                let delay_ms = ((delay * 1000.0) as u64).min(MAX_CRAWL_DELAY_MS);
+                self.wait_for_turn(parsed.host_str().unwrap_or(""), delay_ms).await;
+            }
+        }
+        Ok(parsed)
+    }
+
+    //Sends an idempotent request, retrying network errors, 429 and 5xx. The last response is returned
+    //as is once the retries run out, or straight away when it asks to wait longer than MAX_RETRY_AFTER_MS.
+    //It comes with the host slot it was sent in, which should be held until the body is read
+    async fn execute(&self, method: Method, url: Url) -> Result<(Response, HostSlot), ScrapeError> {
+        let host = url.host_str().unwrap_or("").to_string();
+        let target = self.profile.relay_target(&url).map_err(ScrapeError::InvalidInput)?;
+        let user_agent = self.next_user_agent();
+        let client = Client::new();
+        let mut attempt = 0;
+        loop {
+            let mut request = client.request(method.clone(), target.clone()).header(USER_AGENT, user_agent.as_str());
+            for (name, value) in &self.profile.headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            if let Some(accept_language) = &self.profile.accept_language {
+                request = request.header(ACCEPT_LANGUAGE, accept_language.as_str());
+            }
+            if let Some(cookie) = self.cookies.as_ref().and_then(|jar| jar.borrow().header(&url, Date::now().as_millis())) {
+                request = request.header(COOKIE, cookie);
+            }
+            let slot = HostSlot::acquire(&host).await;
+            let result = request.send().await;
+            if let (Ok(response), Some(jar)) = (&result, &self.cookies) {
+                for set_cookie in response.headers().get_all(SET_COOKIE) {
+                    if let Ok(set_cookie) = set_cookie.to_str() {
+                        jar.borrow_mut().store(&url, set_cookie, Date::now().as_millis());
+                    }
+                }
+            }
+            let wait = match &result {
+                Ok(response) if is_retryable(response.status()) => match retry_after_ms(response, Date::now().as_millis()) {
+                    Some(wait) if wait > MAX_RETRY_AFTER_MS => None,
+                    Some(wait) => Some(wait),
+                    None => Some(backoff_ms(attempt)),
+                },
+                Ok(_) => None,
+                Err(_) => Some(backoff_ms(attempt)),
+            };
+            match wait {
+                Some(wait) if attempt < MAX_RETRIES => {
+                    drop(slot);
+                    attempt += 1;
+                    sleep(wait).await;
+                }
+                _ => return result.map(|response| (response, slot)).map_err(|e| ScrapeError::Fetch(e.to_string())),
+            }
+        }
+    }
+
+    //Checks robots.txt, then sends the request and fails on a non-success status, telling bot blocks apart
+    async fn send(&self, url: &str) -> Result<(Response, HostSlot), ScrapeError> {
+        let parsed = self.check_allowed(url).await?;
+        let (response, slot) = self.execute(Method::GET, parsed).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let final_url = response.url().clone();
+            let body = response.text().await.unwrap_or_default();
+            if let Some(reason) = detect_block(status.as_u16(), &final_url, &body) {
+                return Err(ScrapeError::Blocked(url.to_string(), reason));
+            }
+            return Err(ScrapeError::Fetch(format!("{} responded with {}", url, status)));
+        }
+        Ok((response, slot))
+    }
+
+    //Returns the status code of a URL without downloading it, servers that reject HEAD are asked with GET
+    pub async fn status(&self, url: &str) -> Result<u16, ScrapeError> {
+        let parsed = self.check_allowed(url).await?;
+        let (response, _) = self.execute(Method::HEAD, parsed.clone()).await?;
+        let status = response.status().as_u16();
+        if status != 405 && status != 501 {
+            return Ok(status);
+        }
+        let (response, _) = self.execute(Method::GET, parsed).await?;
+        Ok(response.status().as_u16())
+    }
+
+    //Fetches a page and returns its body decoded from whatever character encoding it was served in
+    pub async fn fetch_html(&self, url: &str) -> Result<String, ScrapeError> {
+        let (response, slot) = self.send(url).await?;
+        let content_type = response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let final_url = response.url().clone();
+        let tld = final_url.host_str().and_then(|host| host.rsplit('.').next()).map(str::to_string);
+        let bytes = response.bytes().await.map_err(|e| ScrapeError::Fetch(e.to_string()))?;
+        drop(slot);
+        let body = decode_document(&bytes, content_type.as_deref(), tld.as_deref());
+        //Challenges and consent walls often come back as 200
+        if let Some(reason) = detect_block(200, &final_url, &body) {
+            return Err(ScrapeError::Blocked(url.to_string(), reason));
+        }
+        Ok(body)
+    }
+
+    //Fetches a body as raw bytes, for compressed or non HTML documents
+    pub async fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, ScrapeError> {
+        let (response, _slot) = self.send(url).await?;
+        let bytes = response.bytes().await.map_err(|e| ScrapeError::Fetch(e.to_string()))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_retry_after_seconds_and_dates() {
+        assert_eq!(parse_retry_after("120", 0), Some(120_000));
+        assert_eq!(parse_retry_after(" 0 ", 0), Some(0));
+        assert_eq!(parse_retry_after(&u64::MAX.to_string(), 0), Some(u64::MAX));
+        //Sun, 06 Nov 1994 08:49:37 GMT is 784111777 seconds after the epoch
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(parse_retry_after(date, 784_111_777_000 - 5_000), Some(5_000));
+        //A date already passed means no wait at all
+        assert_eq!(parse_retry_after(date, 784_111_777_000 + 5_000), Some(0));
+        assert_eq!(parse_retry_after("soon", 0), None);
+        assert_eq!(parse_retry_after("-5", 0), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        for _ in 0..100 {
+            assert!((250..=500).contains(&backoff_ms(0)));
+            assert!((1_000..=2_000).contains(&backoff_ms(2)));
+            assert!((RETRY_MAX_MS / 2..=RETRY_MAX_MS).contains(&backoff_ms(4)));
+            assert!((RETRY_MAX_MS / 2..=RETRY_MAX_MS).contains(&backoff_ms(10)));
+        }
+    }
+
+    #[test]
+    fn profile_user_agents_only_go_in_the_header() {
+        let fetcher = Fetcher {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            respect_robots: true,
+            cache: None,
+            profile: RequestProfile::default(),
+            rotation: Rc::new(Cell::new(0)),
+            cookies: None,
+        };
+        assert_eq!(fetcher.next_user_agent(), DEFAULT_USER_AGENT);
+        let profile = RequestProfile { user_agents: vec!["Browser/1".to_string(), "Browser/2".to_string()], ..RequestProfile::default() };
+        let fetcher = fetcher.with_profile(profile);
+        assert_eq!(fetcher.user_agent, DEFAULT_USER_AGENT);
+        assert_eq!(fetcher.next_user_agent(), "Browser/1");
+        assert_eq!(fetcher.next_user_agent(), "Browser/2");
+        assert_eq!(fetcher.next_user_agent(), "Browser/1");
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable(StatusCode::NOT_IMPLEMENTED));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+        assert!(!is_retryable(StatusCode::FORBIDDEN));
+        assert!(!is_retryable(StatusCode::OK));
+    }
+}
@@ -0,0 +1,183 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::scraper::scraper::Article;
+use crate::scraper::text::stopwords;
+
+//Languages written in the Latin alphabet are told apart by their most frequent letter trigrams, a space marks the
+//start or end of a word
+const TRIGRAMS: [(&str, [&str; 30]); 7] = [
+    ("de", ["en ", "er ", "ch ", "der", "ie ", "ich", "ein", "sch", "die", " de", " di", "und", "nd ", " un", "den", "in ", "te ", "che", " ei", "gen", "ung", "ng ", "cht", "es ", "ine", " da", "das", "ber", "ten", "auf"]),
+    ("en", ["the", "he ", " th", " an", "and", "nd ", "ing", "ng ", " of", "of ", " to", "to ", "ion", "ed ", " in", "in ", "er ", "on ", "es ", "tio", "ent", "is ", "at ", " co", "re ", " fo", "for", "or ", "hat", "ter"]),
+    ("es", ["de ", " de", "os ", "la ", " la", "ión", "el ", " el", "en ", "es ", "as ", " en", "ent", "do ", " co", "que", " qu", "ue ", "ado", "nte", "del", "con", "ra ", "los", " lo", "ar ", "ien", "aci", "cio", "par"]),
+    ("fr", ["es ", " de", "de ", "ent", "le ", " le", "nt ", "la ", " la", "ion", "les", "on ", " et", "et ", "re ", "des", "que", " qu", "ue ", "ne ", "our", "men", "tio", "ait", " pa", "par", "e d", "ans", " so", "eur"]),
+    ("it", ["di ", " di", "la ", "to ", "re ", "ne ", "ell", "che", " ch", "del", " de", "lla", "one", "ion", " co", "per", " pe", "are", "ent", " il", "il ", "no ", "zio", "ato", "nte", "gli", " un", "tta", "sta", "ti "]),
+    ("nl", ["en ", "de ", " de", "an ", "van", " va", "et ", "het", " he", "ing", "er ", " ee", "een", "ij ", "nd ", "aar", "oor", "ver", " ve", "ge ", "cht", "ten", "gen", "sch", " in", "and", "te ", "die", "ijk", " zi"]),
+    ("pt", ["de ", " de", "os ", "do ", " do", "da ", "ão ", "ent", "ção", "es ", " co", "que", " qu", "ue ", "as ", " a ", "ra ", "com", "nte", "em ", "par", "ado", "dos", "men", "ões", " pa", "ia ", "ar ", "est", "um "]),
+];
+
+//Letters found in only one or two of the Latin alphabet languages
+const DIACRITICS: [(char, &str); 12] = [
+    ('ñ', "es"), ('¿', "es"), ('¡', "es"), ('ß', "de"), ('ä', "de"), ('ö', "de"), ('ü', "de"), ('ã', "pt"), ('õ', "pt"),
+    ('ç', "pt"), ('ç', "fr"), ('è', "fr"),
+];
+
+//A stopword says more about the language than a trigram
+const STOPWORD_WEIGHT: usize = 3;
+const DIACRITIC_WEIGHT: usize = 2;
+//Below this a couple of shared trigrams would decide, e.g. for a headline made of names
+const MIN_SCORE: usize = 4;
+//Long bodies are identified from their start
+const MAX_DETECT_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+fn script(c: char) -> Option<Script> {
+    match c as u32 {
+        0x0041..=0x024f if c.is_alphabetic() => Some(Script::Latin),
+        0x0370..=0x03ff => Some(Script::Greek),
+        0x0400..=0x04ff => Some(Script::Cyrillic),
+        0x0590..=0x05ff => Some(Script::Hebrew),
+        0x0600..=0x06ff | 0x0750..=0x077f => Some(Script::Arabic),
+        0x0900..=0x097f => Some(Script::Devanagari),
+        0x0e00..=0x0e7f => Some(Script::Thai),
+        0x1100..=0x11ff | 0xac00..=0xd7af => Some(Script::Hangul),
+        0x3040..=0x30ff => Some(Script::Kana),
+        0x3400..=0x4dbf | 0x4e00..=0x9fff => Some(Script::Han),
+        _ => None,
+    }
+}
+
+//Latin alphabet text: trigram, stopword and diacritic evidence for each language, the best if it clearly leads
+fn detect_latin(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect();
+    let mut trigrams: Vec<String> = vec![];
+    for word in &words {
+        let padded: Vec<char> = format!(" {} ", word).chars().collect();
+        trigrams.extend(padded.windows(3).map(|window| window.iter().collect::<String>()));
+    }
+    let mut scores: Vec<(&'static str, usize)> = TRIGRAMS
+        .iter()
+        .map(|(language, common)| {
+            let mut score = trigrams.iter().filter(|trigram| common.contains(&trigram.as_str())).count();
+            let stopwords = stopwords(language);
+            score += STOPWORD_WEIGHT * words.iter().filter(|word| stopwords.contains(&word.as_str())).count();
+            score += DIACRITIC_WEIGHT * text.chars().filter(|c| DIACRITICS.contains(&(*c, *language))).count();
+            (*language, score)
+        })
+        .collect();
+    scores.sort_by_key(|(_, score)| Reverse(*score));
+    //A tie means the text does not tell the languages apart
+    match scores.as_slice() {
+        [(language, best), (_, second), ..] if *best >= MIN_SCORE && best > second => Some(language),
+        _ => None,
+    }
+}
+
+//ISO 639-1 code of the language a text is written in, from its script and, for the Latin alphabet, its letter
+//trigrams and stopwords. None when there is too little to go on
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let text: String = text.chars().take(MAX_DETECT_CHARS).collect::<String>().to_lowercase();
+    let mut scripts: HashMap<Script, usize> = HashMap::new();
+    for c in text.chars() {
+        if let Some(script) = script(c) {
+            *scripts.entry(script).or_insert(0) += 1;
+        }
+    }
+    let count = |script: Script| scripts.get(&script).copied().unwrap_or(0);
+    //Japanese mixes kana with Chinese characters
+    if count(Script::Kana) > 0 && count(Script::Kana) + count(Script::Han) >= count(Script::Latin) {
+        return Some("ja");
+    }
+    let (dominant, _) = scripts.iter().max_by_key(|(_, count)| **count)?;
+    match dominant {
+        Script::Latin => detect_latin(&text),
+        //Ukrainian has letters Russian does not
+        Script::Cyrillic if text.contains(['і', 'ї', 'є', 'ґ']) => Some("uk"),
+        Script::Cyrillic => Some("ru"),
+        Script::Greek => Some("el"),
+        Script::Arabic => Some("ar"),
+        Script::Hebrew => Some("he"),
+        Script::Devanagari => Some("hi"),
+        Script::Thai => Some("th"),
+        Script::Hangul => Some("ko"),
+        Script::Kana => Some("ja"),
+        Script::Han => Some("zh"),
+    }
+}
+
+//Languages asked for with `lang=en,de`, None when every language is wanted
+pub fn language_filter(params: &HashMap<String, String>) -> Option<Vec<String>> {
+    let languages: Vec<String> = params
+        .get("lang")?
+        .split(',')
+        .map(|language| language.trim().to_lowercase())
+        .filter(|language| !language.is_empty())
+        .collect();
+    (!languages.is_empty()).then_some(languages)
+}
+
+//Keeps the articles detected as one of the languages, articles whose language is unknown are dropped
+pub fn keep_languages(articles: &mut Vec<Article>, languages: &[String]) {
+    articles.retain(|article| {
+        article.enrichment.language.as_ref().is_some_and(|language| languages.contains(language))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_latin_alphabet_languages() {
+        assert_eq!(detect_language("The government announced new measures for the economy and the housing market"), Some("en"));
+        assert_eq!(detect_language("Die Regierung hat neue Maßnahmen für die Wirtschaft und den Wohnungsmarkt angekündigt"), Some("de"));
+        assert_eq!(detect_language("El gobierno anunció nuevas medidas para la economía y el mercado de la vivienda"), Some("es"));
+        assert_eq!(detect_language("Le gouvernement a annoncé de nouvelles mesures pour l'économie et le marché du logement"), Some("fr"));
+        assert_eq!(detect_language("Il governo ha annunciato nuove misure per l'economia e il mercato della casa"), Some("it"));
+        assert_eq!(detect_language("De regering heeft nieuwe maatregelen aangekondigd voor de economie en de woningmarkt"), Some("nl"));
+        assert_eq!(detect_language("O governo anunciou novas medidas para a economia e o mercado de habitação"), Some("pt"));
+    }
+
+    #[test]
+    fn detects_other_scripts() {
+        assert_eq!(detect_language("Правительство объявило новые меры"), Some("ru"));
+        assert_eq!(detect_language("Уряд оголосив нові заходи для економіки"), Some("uk"));
+        assert_eq!(detect_language("政府は新しい対策を発表した"), Some("ja"));
+        assert_eq!(detect_language("政府宣布了新的经济措施"), Some("zh"));
+        assert_eq!(detect_language("정부가 새로운 대책을 발표했다"), Some("ko"));
+        assert_eq!(detect_language("Η κυβέρνηση ανακοίνωσε νέα μέτρα"), Some("el"));
+    }
+
+    #[test]
+    fn gives_up_on_too_little_text() {
+        assert_eq!(detect_language(""), None);
+        assert_eq!(detect_language("2024"), None);
+        assert_eq!(detect_language("Macron Biden"), None);
+    }
+
+    #[test]
+    fn parses_the_filter() {
+        let params: HashMap<String, String> = vec![("lang".to_string(), " EN, de,,".to_string())].into_iter().collect();
+        assert_eq!(language_filter(&params), Some(vec!["en".to_string(), "de".to_string()]));
+        let params: HashMap<String, String> = vec![("lang".to_string(), ",".to_string())].into_iter().collect();
+        assert_eq!(language_filter(&params), None);
+        assert_eq!(language_filter(&HashMap::new()), None);
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use futures::future::join_all;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::transform::collapse_whitespace;
+
+//HEAD checks per request and how many run at once
+pub const MAX_CHECKS: usize = 100;
+const CHECK_CONCURRENCY: usize = 6;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    Anchor,
+    Image,
+    Script,
+    Stylesheet,
+    Iframe,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub url: String,
+    //Anchor text, or the alt text of an image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rel: Vec<String>,
+    pub internal: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Link {
+    pub fn is_broken(&self) -> bool {
+        self.error.is_some() || self.status.is_some_and(|status| status >= 400)
+    }
+}
+
+fn same_site(a: &Url, b: &Url) -> bool {
+    let host = |url: &Url| url.host_str().map(|host| host.trim_start_matches("www.").to_lowercase());
+    host(a) == host(b)
+}
+
+//What relative URLs in a page resolve against, its <base href> when it has one
+pub fn document_base(document: &Html, page_url: &Url) -> Url {
+    let base_selector = Selector::parse("base[href]").unwrap();
+    document
+        .select(&base_selector)
+        .next()
+        .and_then(|base| base.value().attr("href"))
+        .and_then(|href| page_url.join(href).ok())
+        .unwrap_or_else(|| page_url.clone())
+}
+
+//Every anchor, image, script, stylesheet and iframe on the page with its URL made absolute.
+//Only http(s) links are listed, so mailto:, javascript: and data: URLs are left out
+pub fn collect_links(document: &Html, page_url: &Url) -> Vec<Link> {
+    let base = document_base(document, page_url);
+    let sources: [(LinkKind, &str, &str); 6] = [
+        (LinkKind::Anchor, "a[href], area[href]", "href"),
+        (LinkKind::Image, "img[src]", "src"),
+        (LinkKind::Script, "script[src]", "src"),
+        (LinkKind::Stylesheet, "link[href]", "href"),
+        (LinkKind::Iframe, "iframe[src]", "src"),
+        (LinkKind::Image, "source[srcset], img[srcset]", "srcset"),
+    ];
+    let mut links = vec![];
+    for (kind, selector, attr) in sources {
+        let selector = Selector::parse(selector).unwrap();
+        for element in document.select(&selector) {
+            let rel: Vec<String> = element.value().attr("rel").map(|rel| rel.split_whitespace().map(|value| value.to_lowercase()).collect()).unwrap_or_default();
+            if element.value().name() == "link" && !rel.iter().any(|value| value == "stylesheet") {
+                continue;
+            }
+            let value = element.value().attr(attr).unwrap_or("");
+            //srcset lists candidates as "url descriptor, url descriptor"
+            let targets: Vec<&str> = match attr {
+                "srcset" => value.split(',').filter_map(|candidate| candidate.split_whitespace().next()).collect(),
+                _ => vec![value.trim()],
+            };
+            for target in targets {
+                let url = match base.join(target) {
+                    Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+                    _ => continue,
+                };
+                links.push(Link {
+                    kind,
+                    internal: same_site(&url, page_url),
+                    url: url.to_string(),
+                    text: link_text(element, kind),
+                    rel: rel.clone(),
+                    status: None,
+                    error: None,
+                });
+            }
+        }
+    }
+    links
+}
+
+fn link_text(element: ElementRef, kind: LinkKind) -> Option<String> {
+    let text = match kind {
+        LinkKind::Anchor => collapse_whitespace(&element.text().collect::<Vec<_>>().join(" ")),
+        LinkKind::Image => element.value().attr("alt").map(collapse_whitespace).unwrap_or_default(),
+        _ => return None,
+    };
+    match text.is_empty() {
+        true => None,
+        false => Some(text),
+    }
+}
+
+//Checks each distinct URL once, ignoring fragments, up to MAX_CHECKS. Returns how many URLs were left unchecked
+pub async fn check_links(fetcher: &Fetcher, links: &mut [Link]) -> usize {
+    let mut targets: Vec<String> = vec![];
+    for link in links.iter() {
+        let target = link.url.split('#').next().unwrap_or(&link.url).to_string();
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    let unchecked = targets.len().saturating_sub(MAX_CHECKS);
+    targets.truncate(MAX_CHECKS);
+    let mut results: HashMap<String, Result<u16, String>> = HashMap::new();
+    for chunk in targets.chunks(CHECK_CONCURRENCY) {
+        let statuses = join_all(chunk.iter().map(|target| fetcher.status(target))).await;
+        for (target, status) in chunk.iter().zip(statuses) {
+            results.insert(target.clone(), status.map_err(|e| e.to_string()));
+        }
+    }
+    for link in links.iter_mut() {
+        let target = link.url.split('#').next().unwrap_or(&link.url);
+        match results.get(target) {
+            Some(Ok(status)) => link.status = Some(*status),
+            Some(Err(e)) => link.error = Some(e.clone()),
+            None => {}
+        }
+    }
+    unchecked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(html: &str, page_url: &str) -> Vec<Link> {
+        collect_links(&Html::parse_document(html), &Url::parse(page_url).unwrap())
+    }
+
+    fn urls(links: &[Link], kind: LinkKind) -> Vec<&str> {
+        links.iter().filter(|link| link.kind == kind).map(|link| link.url.as_str()).collect()
+    }
+
+    #[test]
+    fn resolves_against_base_href() {
+        let found = links(
+            r#"<head><base href="https://cdn.example.com/assets/"></head><body><a href="page">Page</a><img src="/logo.png" alt=" The  logo "></body>"#,
+            "https://example.com/news/story",
+        );
+        assert_eq!(urls(&found, LinkKind::Anchor), vec!["https://cdn.example.com/assets/page"]);
+        assert_eq!(urls(&found, LinkKind::Image), vec!["https://cdn.example.com/logo.png"]);
+        assert_eq!(found[0].text.as_deref(), Some("Page"));
+        assert_eq!(found[1].text.as_deref(), Some("The logo"));
+        let found = links(r#"<a href="page">Page</a>"#, "https://example.com/news/story");
+        assert_eq!(urls(&found, LinkKind::Anchor), vec!["https://example.com/news/page"]);
+    }
+
+    #[test]
+    fn splits_srcset_candidates() {
+        let found = links(
+            r#"<picture><source srcset="/a-1x.jpg 1x, /a-2x.jpg 2x"></picture><img srcset=" /b.jpg 480w,/c.jpg 800w ">"#,
+            "https://example.com/",
+        );
+        assert_eq!(
+            urls(&found, LinkKind::Image),
+            vec!["https://example.com/a-1x.jpg", "https://example.com/a-2x.jpg", "https://example.com/b.jpg", "https://example.com/c.jpg"]
+        );
+    }
+
+    #[test]
+    fn keeps_only_stylesheet_links() {
+        let found = links(
+            r#"<link rel="Stylesheet" href="/site.css"><link rel="icon" href="/favicon.ico"><link rel="preload" href="/font.woff2"><link href="/no-rel.css"><script src="/app.js"></script><iframe src="https://video.example.net/embed"></iframe>"#,
+            "https://example.com/",
+        );
+        assert_eq!(urls(&found, LinkKind::Stylesheet), vec!["https://example.com/site.css"]);
+        assert_eq!(found.iter().find(|link| link.kind == LinkKind::Stylesheet).unwrap().rel, vec!["stylesheet"]);
+        assert_eq!(urls(&found, LinkKind::Script), vec!["https://example.com/app.js"]);
+        assert_eq!(urls(&found, LinkKind::Iframe), vec!["https://video.example.net/embed"]);
+    }
+
+    #[test]
+    fn drops_non_http_links() {
+        let found = links(
+            r#"<a href="mailto:news@example.com">Mail</a><a href="javascript:void(0)">Menu</a><img src="data:image/png;base64,AAAA"><a href="tel:123">Call</a><a href="/ok">Ok</a>"#,
+            "https://example.com/",
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].url, "https://example.com/ok");
+    }
+
+    #[test]
+    fn marks_internal_links_ignoring_www() {
+        let found = links(
+            r#"<a href="https://www.example.com/a">A</a><a href="https://example.com/b">B</a><a href="https://news.example.com/c">C</a><a href="https://other.org/d">D</a>"#,
+            "https://www.example.com/",
+        );
+        let internal: Vec<bool> = found.iter().map(|link| link.internal).collect();
+        assert_eq!(internal, vec![true, true, false, false]);
+    }
+}
@@ -1 +1,25 @@
-pub mod scraper;
\ No newline at end of file
+pub mod scraper;
+pub mod sources;
+pub mod recipe;
+pub mod dedup;
+pub mod error;
+pub mod robots;
+pub mod fetch;
+pub mod crawl;
+pub mod sitemap;
+pub mod transform;
+pub mod xpath;
+pub mod table;
+pub mod links;
+pub mod encoding;
+pub mod profile;
+pub mod block;
+pub mod text;
+pub mod enrich;
+pub mod body;
+pub mod summary;
+pub mod sentiment;
+pub mod language;
+pub mod query;
+pub mod search;
+pub mod publishers;
\ No newline at end of file
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime};
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+const MAX_HEADERS: usize = 32;
+const MAX_USER_AGENTS: usize = 32;
+//Cookies kept per jar, the oldest are dropped first
+const MAX_COOKIES: usize = 200;
+
+//How the fetcher presents itself to sites: extra headers, user agents, cookies and where requests are sent
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RequestProfile {
+    //Sent with every request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    //Rotated request by request. robots.txt is still matched against the scraper's own user agent
+    #[serde(default)]
+    pub user_agents: Vec<String>,
+    #[serde(default)]
+    pub accept_language: Option<String>,
+    //Keeps cookies set by responses and sends them back on later requests, e.g. the next pages of a crawl
+    #[serde(default)]
+    pub cookies: bool,
+    //Requests go to this fetch relay instead of the site. `{url}` is replaced with the percent encoded
+    //target, without a placeholder the target is added as the `url` query param
+    #[serde(default)]
+    pub relay_url: Option<String>,
+}
+
+impl RequestProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.headers.len() > MAX_HEADERS {
+            return Err(format!("A profile may set at most {} headers", MAX_HEADERS));
+        }
+        for (name, value) in &self.headers {
+            let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name '{}'", name))?;
+            if header == reqwest::header::HOST || header == reqwest::header::CONTENT_LENGTH {
+                return Err(format!("The {} header cannot be set", name));
+            }
+            HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header '{}'", name))?;
+        }
+        if self.user_agents.len() > MAX_USER_AGENTS {
+            return Err(format!("A profile may list at most {} user agents", MAX_USER_AGENTS));
+        }
+        for user_agent in &self.user_agents {
+            if user_agent.trim().is_empty() || HeaderValue::from_str(user_agent).is_err() {
+                return Err(format!("Invalid user agent '{}'", user_agent));
+            }
+        }
+        if let Some(accept_language) = &self.accept_language {
+            HeaderValue::from_str(accept_language).map_err(|_| "Invalid accept_language".to_string())?;
+        }
+        if self.relay_url.is_some() {
+            self.relay_target(&Url::parse("https://example.com/").unwrap())?;
+        }
+        Ok(())
+    }
+
+    //Where a request for `url` is actually sent
+    pub fn relay_target(&self, url: &Url) -> Result<Url, String> {
+        let relay = match &self.relay_url {
+            Some(relay) => relay,
+            None => return Ok(url.clone()),
+        };
+        let encoded: String = url::form_urlencoded::byte_serialize(url.as_str().as_bytes()).collect();
+        let target = match relay.contains("{url}") {
+            true => Url::parse(&relay.replace("{url}", &encoded)),
+            false => Url::parse(relay).map(|mut target| {
+                target.query_pairs_mut().append_pair("url", url.as_str());
+                target
+            }),
+        };
+        match target {
+            Ok(target) if target.scheme() == "http" || target.scheme() == "https" => Ok(target),
+            Ok(_) => Err("relay_url must be an http(s) URL".to_string()),
+            Err(e) => Err(format!("Invalid relay_url: {}", e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    //Without a Domain attribute the cookie goes back to the exact host only
+    host_only: bool,
+    path: String,
+    secure: bool,
+    //Milliseconds after which it is no longer sent, session cookies last as long as the jar
+    expires: Option<u64>,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    //A cookie with the same name, domain and path overwrites this one
+    fn replaced_by(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let domain_matches = match self.host_only {
+            true => host == self.domain,
+            false => host == self.domain || host.ends_with(&format!(".{}", self.domain)),
+        };
+        let path = url.path();
+        let path_matches = path == self.path
+            || (path.starts_with(&self.path) && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain_matches && path_matches && (!self.secure || url.scheme() == "https")
+    }
+}
+
+//Cookies set by the sites a fetcher visits, shared by every clone of that fetcher
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+//The directory of the request path, the path a cookie gets when Set-Cookie has none
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => url.path()[..index].to_string(),
+    }
+}
+
+//Expires dates as sent by servers, RFC 1123 and the older RFC 850 and asctime forms
+fn parse_expires(value: &str) -> Option<u64> {
+    let millis = match DateTime::parse_from_rfc2822(value) {
+        Ok(date) => date.timestamp_millis(),
+        Err(_) => ["%A, %d-%b-%y %H:%M:%S GMT", "%a, %d-%b-%Y %H:%M:%S GMT", "%a %b %e %H:%M:%S %Y"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())?
+            .and_utc()
+            .timestamp_millis(),
+    };
+    Some(millis.max(0) as u64)
+}
+
+impl CookieJar {
+    //Records a Set-Cookie header received from `url` at `now` in milliseconds. Max-Age takes precedence over
+    //Expires, and a cookie set to expire by then deletes the stored one
+    pub fn store(&mut self, url: &Url, set_cookie: &str, now: u64) {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return,
+        };
+        let mut parts = set_cookie.split(';');
+        let (name, value) = match parts.next().and_then(|pair| pair.split_once('=')) {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim().to_string(), value.trim().to_string()),
+            _ => return,
+        };
+        let mut cookie = Cookie {
+            name,
+            value,
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            secure: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => (attribute.trim().to_lowercase(), ""),
+            };
+            match key.as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_lowercase();
+                    //A site may only set cookies for itself or a parent domain
+                    if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                        return;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(now.saturating_add_signed(seconds.saturating_mul(1000)));
+                    }
+                }
+                "expires" => {
+                    if let Some(expires) = parse_expires(value) {
+                        cookie.expires = Some(expires);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if max_age.is_some() {
+            cookie.expires = max_age;
+        }
+        self.cookies.retain(|existing| !(existing.is_expired(now) || existing.replaced_by(&cookie)));
+        if cookie.is_expired(now) {
+            return;
+        }
+        self.cookies.push(cookie);
+        if self.cookies.len() > MAX_COOKIES {
+            self.cookies.remove(0);
+        }
+    }
+
+    //Value for the Cookie header of a request to `url` at `now`, longer paths first
+    pub fn header(&self, url: &Url, now: u64) -> Option<String> {
+        let mut matching: Vec<&Cookie> = self.cookies.iter().filter(|cookie| !cookie.is_expired(now) && cookie.matches(url)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = matching.iter().map(|cookie| format!("{}={}", cookie.name, cookie.value)).collect();
+        Some(pairs.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //2024-01-01T00:00:00Z
+    const NOW: u64 = 1_704_067_200_000;
+
+    fn url(link: &str) -> Url {
+        Url::parse(link).unwrap()
+    }
+
+    #[test]
+    fn sends_cookies_back_by_domain_and_path() {
+        let mut jar = CookieJar::default();
+        jar.store(&url("https://www.example.com/news/today"), "session=abc; Path=/; Secure", NOW);
+        jar.store(&url("https://www.example.com/news/today"), "section=world", NOW);
+        jar.store(&url("https://www.example.com/"), "shared=1; Domain=.example.com", NOW);
+        jar.store(&url("https://www.example.com/"), "evil=1; Domain=other.com", NOW);
+        assert_eq!(jar.header(&url("https://www.example.com/news/later"), NOW).as_deref(), Some("section=world; session=abc; shared=1"));
+        assert_eq!(jar.header(&url("http://www.example.com/"), NOW).as_deref(), Some("shared=1"));
+        assert_eq!(jar.header(&url("https://cdn.example.com/"), NOW).as_deref(), Some("shared=1"));
+        assert_eq!(jar.header(&url("https://other.com/"), NOW), None);
+    }
+
+    #[test]
+    fn honours_max_age() {
+        let mut jar = CookieJar::default();
+        let site = url("https://example.com/");
+        jar.store(&site, "token=1; Max-Age=60", NOW);
+        assert_eq!(jar.header(&site, NOW + 59_000).as_deref(), Some("token=1"));
+        assert_eq!(jar.header(&site, NOW + 60_000), None);
+        jar.store(&site, "token=2; Max-Age=3600", NOW);
+        jar.store(&site, "token=; Max-Age=0", NOW);
+        assert_eq!(jar.header(&site, NOW), None);
+    }
+
+    #[test]
+    fn honours_expires() {
+        let mut jar = CookieJar::default();
+        let site = url("https://example.com/");
+        jar.store(&site, "a=1; Expires=Tue, 02 Jan 2024 00:00:00 GMT", NOW);
+        jar.store(&site, "b=1; Expires=Tuesday, 02-Jan-24 00:00:00 GMT", NOW);
+        jar.store(&site, "c=1; Expires=Sun, 31 Dec 2023 00:00:00 GMT", NOW);
+        jar.store(&site, "d=1; Expires=Sun, 31 Dec 2023 00:00:00 GMT; Max-Age=60", NOW);
+        jar.store(&site, "e=1; Expires=not a date", NOW);
+        assert_eq!(jar.header(&site, NOW).as_deref(), Some("a=1; b=1; d=1; e=1"));
+        assert_eq!(jar.header(&site, NOW + 24 * 3600 * 1000).as_deref(), Some("e=1"));
+    }
+
+    #[test]
+    fn deletes_with_an_expiry_in_the_past() {
+        let mut jar = CookieJar::default();
+        let site = url("https://example.com/");
+        jar.store(&site, "session=abc", NOW);
+        jar.store(&site, "session=deleted; Expires=Thu, 01 Jan 1970 00:00:00 GMT", NOW);
+        assert_eq!(jar.header(&site, NOW), None);
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::scraper::error::ScrapeError;
+use crate::scraper::scraper::Article;
+
+const MAX_CUSTOM_PUBLISHERS: usize = 200;
+const MAX_PUBLISHER_DOMAINS: usize = 10;
+const MAX_PUBLISHER_ALIASES: usize = 20;
+//Links on these hosts point at an aggregator's redirect page rather than the publisher
+const AGGREGATOR_HOSTS: [&str; 1] = ["news.google.com"];
+
+//Name, domains with the main one first, ISO 3166 country, ISO 639-1 language and other names the publisher goes by
+type BuiltIn = (&'static str, &'static [&'static str], &'static str, &'static str, &'static [&'static str]);
+
+const PUBLISHERS: [BuiltIn; 55] = [
+    ("The New York Times", &["nytimes.com"], "US", "en", &["New York Times", "NYT", "NYTimes"]),
+    ("The Washington Post", &["washingtonpost.com"], "US", "en", &["Washington Post"]),
+    ("The Wall Street Journal", &["wsj.com"], "US", "en", &["Wall Street Journal", "WSJ"]),
+    ("CNN", &["cnn.com"], "US", "en", &["CNN International", "CNN Business"]),
+    ("Fox News", &["foxnews.com"], "US", "en", &["FOX News"]),
+    ("NBC News", &["nbcnews.com"], "US", "en", &[]),
+    ("CBS News", &["cbsnews.com"], "US", "en", &[]),
+    ("ABC News", &["abcnews.go.com"], "US", "en", &[]),
+    ("NPR", &["npr.org"], "US", "en", &[]),
+    ("Associated Press", &["apnews.com"], "US", "en", &["AP News", "AP", "The Associated Press"]),
+    ("Reuters", &["reuters.com"], "GB", "en", &[]),
+    ("Bloomberg", &["bloomberg.com"], "US", "en", &["Bloomberg.com"]),
+    ("CNBC", &["cnbc.com"], "US", "en", &[]),
+    ("USA Today", &["usatoday.com"], "US", "en", &[]),
+    ("Los Angeles Times", &["latimes.com"], "US", "en", &["LA Times"]),
+    ("Politico", &["politico.com"], "US", "en", &[]),
+    ("Axios", &["axios.com"], "US", "en", &[]),
+    ("The Hill", &["thehill.com"], "US", "en", &[]),
+    ("Forbes", &["forbes.com"], "US", "en", &[]),
+    ("Business Insider", &["businessinsider.com"], "US", "en", &["Insider"]),
+    ("MarketWatch", &["marketwatch.com"], "US", "en", &[]),
+    ("Newsweek", &["newsweek.com"], "US", "en", &[]),
+    ("Time", &["time.com"], "US", "en", &["TIME Magazine"]),
+    ("The Atlantic", &["theatlantic.com"], "US", "en", &[]),
+    ("Vox", &["vox.com"], "US", "en", &[]),
+    ("HuffPost", &["huffpost.com"], "US", "en", &["Huffington Post"]),
+    ("Yahoo News", &["news.yahoo.com"], "US", "en", &[]),
+    ("Yahoo Finance", &["finance.yahoo.com"], "US", "en", &[]),
+    ("ESPN", &["espn.com"], "US", "en", &[]),
+    ("TechCrunch", &["techcrunch.com"], "US", "en", &[]),
+    ("The Verge", &["theverge.com"], "US", "en", &[]),
+    ("Wired", &["wired.com"], "US", "en", &[]),
+    ("Ars Technica", &["arstechnica.com"], "US", "en", &[]),
+    ("BBC", &["bbc.com", "bbc.co.uk"], "GB", "en", &["BBC News", "BBC Sport"]),
+    ("The Guardian", &["theguardian.com"], "GB", "en", &["Guardian", "The Guardian US"]),
+    ("Financial Times", &["ft.com"], "GB", "en", &["FT"]),
+    ("The Independent", &["independent.co.uk"], "GB", "en", &["Independent"]),
+    ("The Telegraph", &["telegraph.co.uk"], "GB", "en", &["Telegraph", "The Daily Telegraph"]),
+    ("Sky News", &["news.sky.com"], "GB", "en", &[]),
+    ("The Economist", &["economist.com"], "GB", "en", &[]),
+    ("Al Jazeera", &["aljazeera.com"], "QA", "en", &["Al Jazeera English"]),
+    ("Deutsche Welle", &["dw.com"], "DE", "en", &["DW"]),
+    ("Der Spiegel", &["spiegel.de"], "DE", "de", &["SPIEGEL", "DER SPIEGEL"]),
+    ("Süddeutsche Zeitung", &["sueddeutsche.de"], "DE", "de", &["SZ"]),
+    ("Frankfurter Allgemeine Zeitung", &["faz.net"], "DE", "de", &["FAZ", "Frankfurter Allgemeine"]),
+    ("Le Monde", &["lemonde.fr"], "FR", "fr", &[]),
+    ("Le Figaro", &["lefigaro.fr"], "FR", "fr", &[]),
+    ("France 24", &["france24.com"], "FR", "en", &[]),
+    ("El País", &["elpais.com"], "ES", "es", &["El Pais"]),
+    ("Corriere della Sera", &["corriere.it"], "IT", "it", &[]),
+    ("The Times of India", &["timesofindia.indiatimes.com"], "IN", "en", &["Times of India"]),
+    ("Hindustan Times", &["hindustantimes.com"], "IN", "en", &[]),
+    ("South China Morning Post", &["scmp.com"], "HK", "en", &["SCMP"]),
+    ("CBC News", &["cbc.ca"], "CA", "en", &["CBC"]),
+    ("The Sydney Morning Herald", &["smh.com.au"], "AU", "en", &["Sydney Morning Herald"]),
+];
+
+//A registry entry. `favicon` defaults to /favicon.ico on the main domain
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Publisher {
+    pub name: String,
+    pub domains: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+}
+
+impl Publisher {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > 100 {
+            return Err(format!("Invalid publisher name: '{}'", self.name));
+        }
+        if self.domains.is_empty() || self.domains.len() > MAX_PUBLISHER_DOMAINS {
+            return Err(format!("Publisher '{}' needs between 1 and {} domains", self.name, MAX_PUBLISHER_DOMAINS));
+        }
+        if let Some(domain) = self.domains.iter().find(|domain| normalize_domain(domain).is_none()) {
+            return Err(format!("Publisher '{}' has an invalid domain: '{}'", self.name, domain));
+        }
+        if self.aliases.len() > MAX_PUBLISHER_ALIASES || self.aliases.iter().any(|alias| name_key(alias).is_empty()) {
+            return Err(format!("Publisher '{}' needs at most {} aliases, none empty", self.name, MAX_PUBLISHER_ALIASES));
+        }
+        let is_code = |code: &Option<String>| code.as_ref().is_none_or(|code| code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()));
+        if !is_code(&self.country) || !is_code(&self.language) {
+            return Err(format!("Publisher '{}' needs two letter country and language codes", self.name));
+        }
+        for url in self.logo.iter().chain(self.favicon.iter()) {
+            if Url::parse(url).map_or(true, |url| !matches!(url.scheme(), "http" | "https")) {
+                return Err(format!("Publisher '{}' has an invalid image URL: '{}'", self.name, url));
+            }
+        }
+        Ok(())
+    }
+
+    fn info(&self) -> PublisherInfo {
+        let domain = normalize_domain(&self.domains[0]).unwrap_or_default();
+        PublisherInfo {
+            name: self.name.clone(),
+            favicon: self.favicon.clone().unwrap_or_else(|| format!("https://{}/favicon.ico", domain)),
+            domain,
+            logo: self.logo.clone(),
+            country: self.country.as_ref().map(|country| country.to_uppercase()),
+            language: self.language.as_ref().map(|language| language.to_lowercase()),
+        }
+    }
+}
+
+//What an article carries about its publisher
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PublisherInfo {
+    pub name: String,
+    pub domain: String,
+    pub favicon: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+//A user's own entries, checked before they are saved
+pub fn validate_publishers(publishers: &[Publisher]) -> Result<(), String> {
+    if publishers.len() > MAX_CUSTOM_PUBLISHERS {
+        return Err(format!("At most {} publishers can be saved", MAX_CUSTOM_PUBLISHERS));
+    }
+    publishers.iter().try_for_each(Publisher::validate)
+}
+
+pub fn built_in_publishers() -> Vec<Publisher> {
+    PUBLISHERS
+        .iter()
+        .map(|(name, domains, country, language, aliases)| Publisher {
+            name: name.to_string(),
+            domains: domains.iter().map(|domain| domain.to_string()).collect(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            country: Some(country.to_string()),
+            language: Some(language.to_string()),
+            logo: None,
+            favicon: None,
+        })
+        .collect()
+}
+
+//Lower cased host without `www.`, from a bare domain or a URL. None when it is not a host name
+pub fn normalize_domain(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+    let url = match value.contains("://") {
+        true => Url::parse(&value).ok()?,
+        false => Url::parse(&format!("https://{}", value)).ok()?,
+    };
+    let host = url.host_str()?.trim_end_matches('.');
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    (host.contains('.') && !host.starts_with('.')).then(|| host.to_string())
+}
+
+//Whether `host` is `domain` or one of its subdomains
+fn on_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+//Lower cased letters and digits with a leading "the" dropped, so `The Guardian` and `guardian` are the same name
+fn name_key(name: &str) -> String {
+    let key: String = name.to_lowercase().chars().filter(|c| c.is_alphanumeric() || c.is_whitespace()).collect();
+    let key = key.trim();
+    let key = key.strip_prefix("the ").unwrap_or(key);
+    key.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+//Built-in publishers with a user's own entries on top. A user entry replaces every built-in one sharing a domain
+pub struct PublisherRegistry {
+    publishers: Vec<Publisher>,
+    by_name: HashMap<String, usize>,
+}
+
+impl PublisherRegistry {
+    pub fn new(custom: &[Publisher]) -> Self {
+        let mut publishers: Vec<Publisher> = custom
+            .iter()
+            .cloned()
+            .map(|mut publisher| {
+                publisher.domains = publisher.domains.iter().filter_map(|domain| normalize_domain(domain)).collect();
+                publisher
+            })
+            .filter(|publisher| !publisher.domains.is_empty())
+            .collect();
+        let custom_domains: Vec<String> = publishers.iter().flat_map(|publisher| publisher.domains.clone()).collect();
+        publishers.extend(built_in_publishers().into_iter().filter(|publisher| {
+            !publisher.domains.iter().any(|domain| custom_domains.contains(domain))
+        }));
+        let mut by_name = HashMap::new();
+        for (i, publisher) in publishers.iter().enumerate() {
+            for name in std::iter::once(&publisher.name).chain(publisher.aliases.iter()) {
+                //Earlier entries, the user's, win a shared name
+                by_name.entry(name_key(name)).or_insert(i);
+            }
+            for domain in &publisher.domains {
+                by_name.entry(name_key(domain)).or_insert(i);
+            }
+        }
+        PublisherRegistry { publishers, by_name }
+    }
+
+    pub fn publishers(&self) -> &[Publisher] {
+        &self.publishers
+    }
+
+    //The entry serving a host, the most specific domain wins so news.yahoo.com is not taken for another yahoo.com site
+    pub fn by_domain(&self, host: &str) -> Option<&Publisher> {
+        self.publishers
+            .iter()
+            .filter_map(|publisher| {
+                let domain = publisher.domains.iter().filter(|domain| on_domain(host, domain)).max_by_key(|domain| domain.len())?;
+                Some((publisher, domain.len()))
+            })
+            .max_by_key(|(_, length)| *length)
+            .map(|(publisher, _)| publisher)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Publisher> {
+        self.by_name.get(&name_key(name)).map(|i| &self.publishers[*i])
+    }
+
+    //Main domain of the publisher a domain belongs to, so filtering by bbc.co.uk also matches BBC articles filed
+    //under bbc.com
+    fn canonical_domain(&self, domain: &str) -> String {
+        match self.by_domain(domain) {
+            Some(publisher) => publisher.info().domain,
+            None => domain.to_string(),
+        }
+    }
+
+    //Publisher of an article: the entry for its link's host, else the entry for its source name. An unknown
+    //publisher of a direct link is described from the link alone
+    pub fn resolve(&self, article: &Article) -> Option<PublisherInfo> {
+        let host = normalize_domain(&article.link).filter(|host| !AGGREGATOR_HOSTS.contains(&host.as_str()));
+        if let Some(publisher) = host.as_deref().and_then(|host| self.by_domain(host)) {
+            return Some(publisher.info());
+        }
+        if let Some(publisher) = self.by_name(&article.sourcename) {
+            return Some(publisher.info());
+        }
+        //Sources fall back to the site's origin for a name, which reads better as a domain
+        let name = match normalize_domain(&article.sourcename) {
+            Some(domain) if article.sourcename.contains("://") => domain,
+            _ => clean_name(&article.sourcename),
+        };
+        host.map(|domain| PublisherInfo {
+            name: if name.is_empty() { domain.clone() } else { name },
+            favicon: format!("https://{}/favicon.ico", domain),
+            domain,
+            logo: None,
+            country: None,
+            language: None,
+        })
+    }
+
+    //Fills in each article's publisher and replaces its source name and icon with the registry's
+    pub fn apply(&self, articles: &mut [Article]) {
+        for article in articles.iter_mut() {
+            match self.resolve(article) {
+                Some(publisher) => {
+                    article.sourcename = publisher.name.clone();
+                    article.sourcelink = publisher.favicon.clone();
+                    article.publisher = Some(publisher);
+                }
+                None => article.sourcename = clean_name(&article.sourcename),
+            }
+        }
+    }
+}
+
+fn clean_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+//Publishers asked for with `include_publishers=` and left out with `exclude_publishers=`, both comma separated
+//domains
+#[derive(Debug, Clone, Default)]
+pub struct PublisherFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PublisherFilter {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, ScrapeError> {
+        let domains = |name: &str| -> Result<Vec<String>, ScrapeError> {
+            let mut domains = vec![];
+            for value in params.get(name).map(String::as_str).unwrap_or_default().split(',') {
+                if value.trim().is_empty() {
+                    continue;
+                }
+                match normalize_domain(value) {
+                    Some(domain) => domains.push(domain),
+                    None => return Err(ScrapeError::InvalidInput(format!("{} has an invalid domain: '{}'", name, value))),
+                }
+            }
+            Ok(domains)
+        };
+        Ok(PublisherFilter { include: domains("include_publishers")?, exclude: domains("exclude_publishers")? })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    //Articles whose publisher is unknown only pass when no publishers are asked for
+    pub fn apply(&self, articles: &mut Vec<Article>, registry: &PublisherRegistry) {
+        if self.is_empty() {
+            return;
+        }
+        let include: Vec<String> = self.include.iter().map(|domain| registry.canonical_domain(domain)).collect();
+        let exclude: Vec<String> = self.exclude.iter().map(|domain| registry.canonical_domain(domain)).collect();
+        articles.retain(|article| match &article.publisher {
+            Some(publisher) => {
+                (include.is_empty() || include.iter().any(|domain| on_domain(&publisher.domain, domain)))
+                    && !exclude.iter().any(|domain| on_domain(&publisher.domain, domain))
+            }
+            None => include.is_empty(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scraper::enrich::Enrichment;
+
+    use super::*;
+
+    fn article(link: &str, sourcename: &str) -> Article {
+        Article {
+            title: String::new(),
+            link: link.to_string(),
+            time: String::new(),
+            author: String::new(),
+            sourcelink: String::new(),
+            sourcename: sourcename.to_string(),
+            image_link: String::new(),
+            body: None,
+            publisher: None,
+            enrichment: Enrichment::default(),
+        }
+    }
+
+    fn custom(name: &str, domains: &[&str]) -> Publisher {
+        Publisher {
+            name: name.to_string(),
+            domains: domains.iter().map(|domain| domain.to_string()).collect(),
+            aliases: vec![],
+            country: None,
+            language: None,
+            logo: None,
+            favicon: None,
+        }
+    }
+
+    #[test]
+    fn normalizes_domains() {
+        assert_eq!(normalize_domain("https://www.BBC.co.uk/news/world?x=1"), Some("bbc.co.uk".to_string()));
+        assert_eq!(normalize_domain("www.example.com."), Some("example.com".to_string()));
+        assert_eq!(normalize_domain(" Example.com "), Some("example.com".to_string()));
+        assert_eq!(normalize_domain("localhost"), None);
+        assert_eq!(normalize_domain("not a domain"), None);
+    }
+
+    #[test]
+    fn matches_subdomains_only_at_a_dot() {
+        assert!(on_domain("bbc.com", "bbc.com"));
+        assert!(on_domain("news.bbc.com", "bbc.com"));
+        assert!(!on_domain("notbbc.com", "bbc.com"));
+        assert!(!on_domain("bbc.com", "news.bbc.com"));
+    }
+
+    #[test]
+    fn name_keys_ignore_case_punctuation_and_the() {
+        assert_eq!(name_key("The Guardian"), "guardian");
+        assert_eq!(name_key("guardian"), "guardian");
+        assert_eq!(name_key("Bloomberg.com"), "bloombergcom");
+        assert_eq!(name_key("Theatlantic"), "theatlantic");
+    }
+
+    #[test]
+    fn most_specific_domain_wins() {
+        let registry = PublisherRegistry::new(&[]);
+        assert_eq!(registry.by_domain("news.yahoo.com").unwrap().name, "Yahoo News");
+        assert_eq!(registry.by_domain("uk.finance.yahoo.com").unwrap().name, "Yahoo Finance");
+        assert!(registry.by_domain("mail.yahoo.com").is_none());
+        assert_eq!(registry.by_name("the nytimes").unwrap().name, "The New York Times");
+    }
+
+    #[test]
+    fn resolves_past_aggregator_links() {
+        let registry = PublisherRegistry::new(&[]);
+        let info = registry.resolve(&article("https://news.google.com/articles/abc", "The Guardian")).unwrap();
+        assert_eq!(info.name, "The Guardian");
+        assert_eq!(info.domain, "theguardian.com");
+        assert_eq!(info.favicon, "https://theguardian.com/favicon.ico");
+        let info = registry.resolve(&article("https://www.bbc.co.uk/news/1", "Some Name")).unwrap();
+        assert_eq!(info.name, "BBC");
+        //Unknown publishers of a direct link are described by the link
+        let info = registry.resolve(&article("https://blog.example.org/post", "  Example   Blog ")).unwrap();
+        assert_eq!((info.name.as_str(), info.domain.as_str()), ("Example Blog", "blog.example.org"));
+        assert!(registry.resolve(&article("https://news.google.com/articles/abc", "Nobody")).is_none());
+    }
+
+    #[test]
+    fn custom_entries_replace_built_in_ones() {
+        let registry = PublisherRegistry::new(&[custom("BBC World", &["https://www.bbc.co.uk"]), custom("Guardian Blog", &["blog.guardian.example"])]);
+        assert_eq!(registry.by_domain("bbc.com"), None);
+        assert_eq!(registry.by_domain("bbc.co.uk").unwrap().name, "BBC World");
+        assert_eq!(registry.by_name("BBC News"), None);
+        assert_eq!(registry.by_name("guardian").unwrap().name, "The Guardian");
+        assert_eq!(registry.publishers().len(), PUBLISHERS.len() + 1);
+    }
+
+    #[test]
+    fn filters_by_publisher_domain() {
+        let registry = PublisherRegistry::new(&[]);
+        let mut articles = vec![
+            article("https://www.bbc.com/news/1", ""),
+            article("https://www.theguardian.com/world/2", ""),
+            article("https://news.google.com/articles/3", "Unknown"),
+        ];
+        registry.apply(&mut articles);
+        let params: HashMap<String, String> = vec![("include_publishers".to_string(), "bbc.co.uk".to_string())].into_iter().collect();
+        let mut included = articles.clone();
+        PublisherFilter::from_params(&params).unwrap().apply(&mut included, &registry);
+        assert_eq!(included.len(), 1);
+        assert_eq!(included[0].sourcename, "BBC");
+        let params: HashMap<String, String> = vec![("exclude_publishers".to_string(), "https://bbc.com".to_string())].into_iter().collect();
+        let mut excluded = articles.clone();
+        PublisherFilter::from_params(&params).unwrap().apply(&mut excluded, &registry);
+        assert_eq!(excluded.len(), 2);
+        assert!(excluded.iter().all(|article| article.sourcename != "BBC"));
+        let params: HashMap<String, String> = vec![("include_publishers".to_string(), "bad domain".to_string())].into_iter().collect();
+        assert!(PublisherFilter::from_params(&params).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+
+use crate::scraper::error::ScrapeError;
+use crate::scraper::recipe::Record;
+use crate::scraper::scraper::Article;
+
+pub const MAX_LIMIT: usize = 1000;
+//Compiled size limit for `q=/.../` patterns
+const PATTERN_SIZE_LIMIT: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Time,
+    Source,
+    Title,
+    Sentiment,
+}
+
+impl SortKey {
+    fn parse(value: &str) -> Result<Self, ScrapeError> {
+        match value {
+            "time" => Ok(SortKey::Time),
+            "source" => Ok(SortKey::Source),
+            "title" => Ok(SortKey::Title),
+            "sentiment" => Ok(SortKey::Sentiment),
+            _ => Err(ScrapeError::InvalidInput(format!("Unknown sort: {}, expected time, source, title or sentiment", value))),
+        }
+    }
+
+    //Newest and most positive first, names alphabetically
+    fn descending_by_default(&self) -> bool {
+        matches!(self, SortKey::Time | SortKey::Sentiment)
+    }
+
+    fn compare(&self, a: &Record, b: &Record) -> Option<Ordering> {
+        match self {
+            SortKey::Time => Some(text_field(a, "time")?.cmp(text_field(b, "time")?)),
+            SortKey::Source => Some(text_field(a, "sourcename")?.to_lowercase().cmp(&text_field(b, "sourcename")?.to_lowercase())),
+            SortKey::Title => Some(text_field(a, "title")?.to_lowercase().cmp(&text_field(b, "title")?.to_lowercase())),
+            SortKey::Sentiment => title_sentiment(a)?.partial_cmp(&title_sentiment(b)?),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TitleMatch {
+    Substring(String),
+    Pattern(Regex),
+}
+
+impl TitleMatch {
+    //`q=/pattern/` is a case-insensitive regex, anything else a case-insensitive substring
+    fn parse(value: &str) -> Result<Self, ScrapeError> {
+        match value.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            Some(pattern) => RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .size_limit(PATTERN_SIZE_LIMIT)
+                .build()
+                .map(TitleMatch::Pattern)
+                .map_err(|e| ScrapeError::InvalidInput(format!("Invalid pattern '{}': {}", pattern, e))),
+            None => Ok(TitleMatch::Substring(value.to_lowercase())),
+        }
+    }
+
+    fn is_match(&self, title: &str) -> bool {
+        match self {
+            TitleMatch::Substring(text) => title.to_lowercase().contains(text),
+            TitleMatch::Pattern(pattern) => pattern.is_match(title),
+        }
+    }
+}
+
+fn text_field<'a>(record: &'a Record, name: &str) -> Option<&'a str> {
+    record.get(name).and_then(Value::as_str)
+}
+
+fn title_sentiment(record: &Record) -> Option<f64> {
+    record.get("sentiment")?.get("title")?.get("compound")?.as_f64()
+}
+
+//What to return of a source's results: filters, then the sort order, then the page and the fields to keep
+#[derive(Debug, Clone, Default)]
+pub struct ResultQuery {
+    sourcename: Option<String>,
+    author: Option<String>,
+    title: Option<TitleMatch>,
+    min_sentiment: Option<f64>,
+    max_sentiment: Option<f64>,
+    //Key and whether it is descending
+    sort: Option<(SortKey, bool)>,
+    offset: usize,
+    limit: Option<usize>,
+    pub fields: Vec<String>,
+}
+
+impl ResultQuery {
+    //Reads `sourcename=`, `author=`, `q=`, `min_sentiment=`, `max_sentiment=`, `sort=`, `order=`, `offset=`,
+    //`limit=` and `fields=`. `default_limit` applies when no limit is given
+    pub fn from_params(params: &HashMap<String, String>, default_limit: Option<usize>) -> Result<Self, ScrapeError> {
+        let number = |name: &str| -> Result<Option<usize>, ScrapeError> {
+            match params.get(name) {
+                None => Ok(None),
+                Some(value) => value.parse::<usize>().map(Some).map_err(|_| ScrapeError::InvalidInput(format!("{} must be a whole number", name))),
+            }
+        };
+        let sentiment = |name: &str| -> Result<Option<f64>, ScrapeError> {
+            match params.get(name) {
+                None => Ok(None),
+                Some(value) => match value.parse::<f64>() {
+                    Ok(value) if (-1.0..=1.0).contains(&value) => Ok(Some(value)),
+                    _ => Err(ScrapeError::InvalidInput(format!("{} must be a number from -1 to 1", name))),
+                },
+            }
+        };
+        let sort = match params.get("sort") {
+            None => None,
+            Some(sort) => {
+                let key = SortKey::parse(sort)?;
+                let descending = match params.get("order").map(String::as_str) {
+                    None => key.descending_by_default(),
+                    Some("desc") => true,
+                    Some("asc") => false,
+                    Some(other) => return Err(ScrapeError::InvalidInput(format!("Unknown order: {}, expected asc or desc", other))),
+                };
+                Some((key, descending))
+            }
+        };
+        let limit = match number("limit")? {
+            Some(limit) if limit > MAX_LIMIT => return Err(ScrapeError::InvalidInput(format!("limit may be at most {}", MAX_LIMIT))),
+            Some(limit) => Some(limit),
+            None => default_limit,
+        };
+        let fields = params
+            .get("fields")
+            .map(|fields| fields.split(',').map(str::trim).filter(|field| !field.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default();
+        Ok(ResultQuery {
+            sourcename: params.get("sourcename").map(|name| name.to_lowercase()),
+            author: params.get("author").map(|author| author.to_lowercase()),
+            title: params.get("q").map(|q| TitleMatch::parse(q)).transpose()?,
+            min_sentiment: sentiment("min_sentiment")?,
+            max_sentiment: sentiment("max_sentiment")?,
+            sort,
+            offset: number("offset")?.unwrap_or(0),
+            limit,
+            fields,
+        })
+    }
+
+    //Sentiment filters and sorting need the articles scored
+    pub fn needs_sentiment(&self) -> bool {
+        self.min_sentiment.is_some() || self.max_sentiment.is_some() || matches!(self.sort, Some((SortKey::Sentiment, _)))
+    }
+
+    //Whether the query only sorts or pages in source order without filtering
+    fn only_pages(&self) -> bool {
+        self.sourcename.is_none()
+            && self.author.is_none()
+            && self.title.is_none()
+            && self.min_sentiment.is_none()
+            && self.max_sentiment.is_none()
+            && self.sort.is_none()
+    }
+
+    //Results a source that reads a fixed number of entries should produce. When the results are filtered or sorted,
+    //here or by `narrowed` steps like dedupe, the page has to be picked from the whole set, otherwise the first
+    //offset + limit are enough
+    pub fn source_limit(&self, narrowed: bool) -> Option<usize> {
+        match narrowed || !self.only_pages() {
+            true => Some(MAX_LIMIT),
+            false => self.limit.map(|limit| self.offset + limit),
+        }
+    }
+
+    //Records missing a filtered field are left out
+    fn keeps(&self, record: &Record) -> bool {
+        if let Some(sourcename) = &self.sourcename {
+            if text_field(record, "sourcename").is_none_or(|name| name.to_lowercase() != *sourcename) {
+                return false;
+            }
+        }
+        if let Some(author) = &self.author {
+            if text_field(record, "author").is_none_or(|name| !name.to_lowercase().contains(author.as_str())) {
+                return false;
+            }
+        }
+        if let Some(title) = &self.title {
+            if text_field(record, "title").is_none_or(|text| !title.is_match(text)) {
+                return false;
+            }
+        }
+        if self.min_sentiment.is_some() || self.max_sentiment.is_some() {
+            let compound = match title_sentiment(record) {
+                Some(compound) => compound,
+                None => return false,
+            };
+            if self.min_sentiment.is_some_and(|min| compound < min) || self.max_sentiment.is_some_and(|max| compound > max) {
+                return false;
+            }
+        }
+        true
+    }
+
+    //Filters, sorts and pages the records, returning the page and how many records matched in total
+    pub fn select(&self, mut records: Vec<Record>) -> (Vec<Record>, usize) {
+        records.retain(|record| self.keeps(record));
+        if let Some((key, descending)) = self.sort {
+            //Records without the field go last either way, the sort is stable otherwise
+            records.sort_by(|a, b| match key.compare(a, b) {
+                Some(order) if descending => order.reverse(),
+                Some(order) => order,
+                None => key.compare(a, a).is_none().cmp(&key.compare(b, b).is_none()),
+            });
+        }
+        let total = records.len();
+        let page = records.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect();
+        (page, total)
+    }
+
+    //Keeps only the `fields=` of every record, all of them when none are given
+    pub fn project(&self, records: Vec<Record>) -> Vec<Record> {
+        if self.fields.is_empty() {
+            return records;
+        }
+        records
+            .into_iter()
+            .map(|mut record| {
+                let mut projected = Record::new();
+                for field in &self.fields {
+                    if let Some(value) = record.remove(field) {
+                        projected.insert(field.clone(), value);
+                    }
+                }
+                projected
+            })
+            .collect()
+    }
+
+    //`select` for callers that need articles back, fields are not projected
+    pub fn select_articles(&self, articles: Vec<Article>) -> (Vec<Article>, usize) {
+        let (page, total) = self.select(articles_to_records(&articles));
+        let articles = page.into_iter().filter_map(|record| serde_json::from_value(Value::Object(record)).ok()).collect();
+        (articles, total)
+    }
+}
+
+pub fn articles_to_records(articles: &[Article]) -> Vec<Record> {
+    articles
+        .iter()
+        .filter_map(|article| match serde_json::to_value(article) {
+            Ok(Value::Object(record)) => Some(record),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> ResultQuery {
+        ResultQuery::from_params(&params(pairs), None).unwrap()
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            json!({ "title": "Rates rise again", "sourcename": "Reuters", "author": "Ann Lee", "time": "2024-01-02T00:00:00Z", "sentiment": { "title": { "compound": 0.2 } } }),
+            json!({ "title": "Storm warning issued", "sourcename": "BBC", "author": "Bob Smith", "time": "2024-01-03T00:00:00Z", "sentiment": { "title": { "compound": -0.6 } } }),
+            json!({ "title": "Team wins final", "sourcename": "reuters", "author": "ann lee", "time": "2024-01-01T00:00:00Z", "sentiment": { "title": { "compound": 0.7 } } }),
+            json!({ "title": "Untimed story", "sourcename": "AP" }),
+        ]
+        .into_iter()
+        .map(|record| match record {
+            Value::Object(record) => record,
+            _ => unreachable!(),
+        })
+        .collect()
+    }
+
+    fn titles(records: &[Record]) -> Vec<&str> {
+        records.iter().map(|record| text_field(record, "title").unwrap()).collect()
+    }
+
+    #[test]
+    fn filters_records() {
+        let (page, total) = query(&[("sourcename", "REUTERS")]).select(records());
+        assert_eq!((titles(&page), total), (vec!["Rates rise again", "Team wins final"], 2));
+        let (page, _) = query(&[("author", "LEE"), ("q", "wins")]).select(records());
+        assert_eq!(titles(&page), vec!["Team wins final"]);
+        let (page, _) = query(&[("q", "/^(rates|storm)\\b/")]).select(records());
+        assert_eq!(titles(&page), vec!["Rates rise again", "Storm warning issued"]);
+        let (page, _) = query(&[("min_sentiment", "0"), ("max_sentiment", "0.5")]).select(records());
+        assert_eq!(titles(&page), vec!["Rates rise again"]);
+    }
+
+    #[test]
+    fn sorts_with_missing_fields_last() {
+        let (page, _) = query(&[("sort", "time")]).select(records());
+        assert_eq!(titles(&page), vec!["Storm warning issued", "Rates rise again", "Team wins final", "Untimed story"]);
+        let (page, _) = query(&[("sort", "time"), ("order", "asc")]).select(records());
+        assert_eq!(titles(&page), vec!["Team wins final", "Rates rise again", "Storm warning issued", "Untimed story"]);
+        let (page, _) = query(&[("sort", "source")]).select(records());
+        assert_eq!(titles(&page), vec!["Untimed story", "Storm warning issued", "Rates rise again", "Team wins final"]);
+    }
+
+    #[test]
+    fn pages_and_projects() {
+        let query = query(&[("sort", "sentiment"), ("offset", "1"), ("limit", "1"), ("fields", "title, sourcename")]);
+        let (page, total) = query.select(records());
+        assert_eq!(total, 4);
+        assert_eq!(query.project(page), vec![json!({ "title": "Rates rise again", "sourcename": "Reuters" }).as_object().unwrap().clone()]);
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        for pairs in [
+            vec![("limit", "1001")],
+            vec![("offset", "-1")],
+            vec![("sort", "length")],
+            vec![("sort", "time"), ("order", "up")],
+            vec![("min_sentiment", "2")],
+            vec![("q", "/(unclosed/")],
+        ] {
+            assert!(ResultQuery::from_params(&params(&pairs), None).is_err(), "{:?}", pairs);
+        }
+    }
+
+    #[test]
+    fn pushes_the_limit_down_only_for_plain_pages() {
+        let plain = ResultQuery::from_params(&params(&[("offset", "10"), ("limit", "5")]), None).unwrap();
+        assert_eq!(plain.source_limit(false), Some(15));
+        assert_eq!(plain.source_limit(true), Some(MAX_LIMIT));
+        assert_eq!(query(&[("limit", "5"), ("sort", "time")]).source_limit(false), Some(MAX_LIMIT));
+        assert_eq!(query(&[("limit", "5"), ("author", "ann")]).source_limit(false), Some(MAX_LIMIT));
+        assert_eq!(ResultQuery::from_params(&params(&[]), Some(20)).unwrap().source_limit(false), Some(20));
+        assert_eq!(query(&[]).source_limit(false), None);
+    }
+}
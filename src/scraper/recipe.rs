@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use ego_tree::NodeId;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::scraper::links::document_base;
+use crate::scraper::table::parse_table;
+use crate::scraper::transform::{Pipeline, Transform};
+use crate::scraper::xpath::{Evaluator, XPath, XValue};
+
+//One extracted item, keyed by field name
+pub type Record = Map<String, Value>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldSpec {
+    //Selector relative to the item, empty means the item itself
+    #[serde(default)]
+    pub selector: String,
+    //Attribute to read instead of the text content
+    #[serde(default)]
+    pub attr: Option<String>,
+    //Applied in order to the extracted value
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+    //Records where this field ends up null or empty are dropped
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl FieldSpec {
+    pub fn new(selector: &str, attr: Option<&str>, transforms: Vec<Transform>, required: bool) -> Self {
+        FieldSpec {
+            selector: selector.to_string(),
+            attr: attr.map(|attr| attr.to_string()),
+            transforms,
+            required,
+        }
+    }
+}
+
+//Describes how to turn a page into records: every match of `item_selector` becomes one record
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recipe {
+    pub item_selector: String,
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldSpec>,
+    //`item_selector` selects tables and each row becomes a record keyed by the column headers,
+    //fields then refer to columns by header and their selectors are ignored
+    #[serde(default)]
+    pub table: bool,
+}
+
+impl Recipe {
+    //A recipe that only has an item selector, each item is recorded by its text
+    pub fn from_selector(selector: &str) -> Self {
+        Recipe {
+            item_selector: selector.to_string(),
+            fields: BTreeMap::new(),
+            table: false,
+        }
+    }
+
+    //Checks that every selector and transform in the recipe compiles
+    pub fn validate(&self) -> Result<(), String> {
+        NodeSelector::parse(&self.item_selector)?;
+        for (name, field) in &self.fields {
+            if !field.selector.is_empty() {
+                NodeSelector::parse(&field.selector).map_err(|e| format!("Field '{}': {}", name, e))?;
+            }
+            Pipeline::compile(&field.transforms).map_err(|e| format!("Field '{}': {}", name, e))?;
+        }
+        Ok(())
+    }
+}
+
+//A CSS selector, or an XPath 1.0 expression when prefixed with `xpath:`
+pub enum NodeSelector {
+    Css(Selector),
+    XPath(XPath),
+}
+
+//What a selector matched, XPath text, attribute and scalar results are returned as strings
+pub enum Match<'a> {
+    Element(ElementRef<'a>),
+    Text(String),
+}
+
+impl NodeSelector {
+    pub fn parse(selector: &str) -> Result<Self, String> {
+        match selector.strip_prefix("xpath:") {
+            Some(expression) => XPath::parse(expression.trim()).map(NodeSelector::XPath).map_err(|e| format!("Invalid XPath '{}': {}", expression, e)),
+            None => Selector::parse(selector).map(NodeSelector::Css).map_err(|e| format!("Invalid selector '{}': {:?}", selector, e)),
+        }
+    }
+
+    //Matches anywhere in the document
+    pub fn select_document<'a>(&self, evaluator: &Evaluator<'a>, document: &'a Html) -> Result<Vec<Match<'a>>, String> {
+        match self {
+            NodeSelector::Css(selector) => Ok(document.select(selector).map(Match::Element).collect()),
+            NodeSelector::XPath(xpath) => xpath_matches(evaluator, xpath, document.tree.root().id()),
+        }
+    }
+
+    //Matches below `scope`, which is also the context node of an XPath expression
+    pub fn select<'a>(&self, evaluator: &Evaluator<'a>, scope: ElementRef<'a>) -> Result<Vec<Match<'a>>, String> {
+        match self {
+            NodeSelector::Css(selector) => Ok(scope.select(selector).map(Match::Element).collect()),
+            NodeSelector::XPath(xpath) => xpath_matches(evaluator, xpath, scope.id()),
+        }
+    }
+}
+
+fn xpath_matches<'a>(evaluator: &Evaluator<'a>, xpath: &XPath, context: NodeId) -> Result<Vec<Match<'a>>, String> {
+    Ok(match evaluator.evaluate(xpath, context)? {
+        XValue::Nodes(nodes) => nodes
+            .iter()
+            .map(|node| match evaluator.element(node) {
+                Some(element) => Match::Element(element),
+                None => Match::Text(evaluator.string_value(node)),
+            })
+            .collect(),
+        scalar => vec![Match::Text(evaluator.string(&scalar))],
+    })
+}
+
+fn element_text(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ").trim().to_string()
+}
+
+fn extract_field<'a>(evaluator: &Evaluator<'a>, item: ElementRef<'a>, field: &FieldSpec, selector: Option<&NodeSelector>, html: bool) -> Result<Option<String>, String> {
+    let element = match selector {
+        Some(selector) => match selector.select(evaluator, item)?.into_iter().next() {
+            Some(Match::Element(element)) => element,
+            Some(Match::Text(text)) => return Ok(Some(text.trim().to_string())),
+            None => return Ok(None),
+        },
+        None => item,
+    };
+    Ok(match &field.attr {
+        Some(attr) => element.value().attr(attr).map(|value| value.to_string()),
+        None if html => Some(element.inner_html()),
+        None => Some(element_text(element)),
+    })
+}
+
+//A field with its selector and transforms compiled
+struct CompiledField<'a> {
+    name: &'a str,
+    spec: &'a FieldSpec,
+    selector: Option<NodeSelector>,
+    pipeline: Pipeline,
+}
+
+//Runs each field's transforms over the raw value in the record, None when a required field ends up empty
+fn finish_record(mut record: Record, fields: &[CompiledField], page_url: &Url) -> Option<Record> {
+    for field in fields {
+        let value = field.pipeline.apply(record.remove(field.name).unwrap_or(Value::Null), page_url);
+        let missing = match &value {
+            Value::Null => true,
+            Value::String(text) => text.is_empty(),
+            _ => false,
+        };
+        if field.spec.required && missing {
+            return None;
+        }
+        record.insert(field.name.to_string(), value);
+    }
+    Some(record)
+}
+
+//Applies the recipe to a page. Relative links in it resolve against its <base href>, or `page_url` without one
+pub fn extract_records(document: &Html, recipe: &Recipe, page_url: &Url) -> Result<Vec<Record>, String> {
+    let page_url = &document_base(document, page_url);
+    let item_selector = NodeSelector::parse(&recipe.item_selector)?;
+    let mut fields = vec![];
+    for (name, spec) in &recipe.fields {
+        let selector = match spec.selector.is_empty() {
+            true => None,
+            false => Some(NodeSelector::parse(&spec.selector)?),
+        };
+        let pipeline = Pipeline::compile(&spec.transforms).map_err(|e| format!("Field '{}': {}", name, e))?;
+        fields.push(CompiledField { name, spec, selector, pipeline });
+    }
+    let evaluator = Evaluator::new(document);
+    let items: Vec<ElementRef> = item_selector
+        .select_document(&evaluator, document)?
+        .into_iter()
+        .filter_map(|found| match found {
+            Match::Element(element) => Some(element),
+            Match::Text(_) => None,
+        })
+        .collect();
+    if recipe.table {
+        let rows = items
+            .into_iter()
+            .filter(|item| item.value().name() == "table")
+            .enumerate()
+            .flat_map(|(index, table)| parse_table(table, index).rows);
+        return Ok(rows.filter_map(|row| finish_record(row, &fields, page_url)).collect());
+    }
+    let mut records = vec![];
+    for item in items {
+        let mut record = Record::new();
+        if recipe.fields.is_empty() {
+            record.insert("text".to_string(), Value::String(element_text(item)));
+        }
+        for field in &fields {
+            let value = extract_field(&evaluator, item, field.spec, field.selector.as_ref(), field.pipeline.wants_html())?;
+            record.insert(field.name.to_string(), value.map(Value::String).unwrap_or(Value::Null));
+        }
+        if let Some(record) = finish_record(record, &fields, page_url) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RobotsRule {
+    pub allow: bool,
+    pub pattern: String,
+}
+
+//Rules shared by one or more consecutive `User-agent` lines
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RobotsGroup {
+    pub user_agents: Vec<String>,
+    pub rules: Vec<RobotsRule>,
+    #[serde(default)]
+    pub crawl_delay: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Robots {
+    pub groups: Vec<RobotsGroup>,
+    #[serde(default)]
+    pub sitemaps: Vec<String>,
+}
+
+impl Robots {
+    //No robots.txt (a 4xx other than 401, 403 or 429) means everything may be crawled
+    pub fn allow_all() -> Self {
+        Robots::default()
+    }
+
+    //An unreachable or refused robots.txt (5xx, 401, 403, 429 or network error) means nothing may be crawled
+    pub fn disallow_all() -> Self {
+        Robots {
+            groups: vec![RobotsGroup {
+                user_agents: vec!["*".to_string()],
+                rules: vec![RobotsRule { allow: false, pattern: "/".to_string() }],
+                crawl_delay: None,
+            }],
+            sitemaps: vec![],
+        }
+    }
+
+    pub fn parse(body: &str) -> Self {
+        let mut robots = Robots::default();
+        let mut current: Option<RobotsGroup> = None;
+        //User-agent lines directly after each other share the group that follows them
+        let mut collecting_agents = false;
+        for line in body.lines() {
+            let line = match line.find('#') {
+                Some(index) => &line[..index],
+                None => line,
+            };
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "user-agent" => {
+                    if !collecting_agents {
+                        if let Some(group) = current.take() {
+                            robots.groups.push(group);
+                        }
+                        current = Some(RobotsGroup::default());
+                        collecting_agents = true;
+                    }
+                    if let Some(group) = current.as_mut() {
+                        group.user_agents.push(value.to_lowercase());
+                    }
+                }
+                "allow" | "disallow" => {
+                    collecting_agents = false;
+                    //An empty disallow allows everything, which is the default anyway
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = current.as_mut() {
+                        group.rules.push(RobotsRule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    collecting_agents = false;
+                    if let (Some(group), Ok(delay)) = (current.as_mut(), value.parse::<f64>()) {
+                        group.crawl_delay = Some(delay);
+                    }
+                }
+                "sitemap" => robots.sitemaps.push(value.to_string()),
+                _ => {}
+            }
+        }
+        if let Some(group) = current {
+            robots.groups.push(group);
+        }
+        robots
+    }
+
+    //The group naming the longest token contained in our product token, falling back to `*`
+    pub fn group_for(&self, user_agent: &str) -> Option<&RobotsGroup> {
+        //"web-scraper-wasm/0.1 (+https://...)" is matched as "web-scraper-wasm"
+        let user_agent = user_agent.split(|c: char| c == '/' || c.is_whitespace()).next().unwrap_or("").to_lowercase();
+        let mut best: Option<(&RobotsGroup, usize)> = None;
+        let mut wildcard = None;
+        for group in &self.groups {
+            for agent in &group.user_agents {
+                if agent == "*" {
+                    wildcard = wildcard.or(Some(group));
+                } else if user_agent.contains(agent.as_str()) && best.is_none_or(|(_, len)| agent.len() > len) {
+                    best = Some((group, agent.len()));
+                }
+            }
+        }
+        best.map(|(group, _)| group).or(wildcard)
+    }
+
+    //`path` is the URL path including its query string
+    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
+        let group = match self.group_for(user_agent) {
+            Some(group) => group,
+            None => return true,
+        };
+        //The longest matching pattern wins, on a tie allow wins
+        let mut verdict: Option<(usize, bool)> = None;
+        for rule in &group.rules {
+            if !pattern_matches(&rule.pattern, path) {
+                continue;
+            }
+            let length = rule.pattern.len();
+            verdict = match verdict {
+                Some((best, allow)) if best > length || (best == length && allow) => Some((best, allow)),
+                _ => Some((length, rule.allow)),
+            };
+        }
+        verdict.is_none_or(|(_, allow)| allow)
+    }
+
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<f64> {
+        self.group_for(user_agent).and_then(|group| group.crawl_delay)
+    }
+}
+
+//Matches a robots.txt path pattern, `*` matches any run of characters and a trailing `$` anchors the end
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if !path.starts_with(parts[0]) {
+        return false;
+    }
+    let mut position = parts[0].len();
+    for (index, part) in parts.iter().enumerate().skip(1) {
+        let last = index == parts.len() - 1;
+        if last && anchored {
+            return path.len() >= position + part.len() && path.ends_with(part);
+        }
+        match path[position..].find(part) {
+            Some(offset) => position += offset + part.len(),
+            None => return false,
+        }
+    }
+    !anchored || position == path.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "User-agent: *\nDisallow: /private\nAllow: /private/open\n\nUser-agent: web-scraper-wasm\nUser-agent: other-bot\nDisallow: /search$\nDisallow: /*.pdf\nCrawl-delay: 2.5\nSitemap: https://example.com/sitemap.xml\n";
+
+    #[test]
+    fn picks_the_most_specific_group() {
+        let robots = Robots::parse(ROBOTS);
+        assert_eq!(robots.crawl_delay("web-scraper-wasm/0.1 (+https://example.com)"), Some(2.5));
+        assert_eq!(robots.crawl_delay("SomeBrowser/1.0"), None);
+        assert!(robots.is_allowed("web-scraper-wasm/0.1", "/private"));
+        assert!(!robots.is_allowed("SomeBrowser/1.0", "/private/page"));
+        assert_eq!(robots.sitemaps, vec!["https://example.com/sitemap.xml".to_string()]);
+    }
+
+    #[test]
+    fn longest_pattern_wins_and_allow_wins_ties() {
+        let robots = Robots::parse("User-agent: *\nDisallow: /a\nAllow: /a/b\nDisallow: /c\nAllow: /c\n");
+        assert!(!robots.is_allowed("bot", "/a/c"));
+        assert!(robots.is_allowed("bot", "/a/b/c"));
+        assert!(robots.is_allowed("bot", "/c"));
+    }
+
+    #[test]
+    fn matches_wildcards_and_anchors() {
+        let robots = Robots::parse(ROBOTS);
+        assert!(!robots.is_allowed("web-scraper-wasm", "/search"));
+        assert!(robots.is_allowed("web-scraper-wasm", "/search/page"));
+        assert!(!robots.is_allowed("web-scraper-wasm", "/files/report.pdf"));
+        assert!(robots.is_allowed("web-scraper-wasm", "/files/report.html"));
+    }
+
+    #[test]
+    fn allow_all_and_disallow_all() {
+        assert!(Robots::allow_all().is_allowed("bot", "/anything"));
+        assert!(!Robots::disallow_all().is_allowed("bot", "/"));
+    }
+}
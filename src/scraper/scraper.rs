@@ -1,12 +1,23 @@
-use std::io::Error;
+use std::collections::BTreeMap;
 
-use worker::console_log;
-use reqwest::Client;
-use scraper::{Html, Selector};
-use serde::Serialize;
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::Url;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+use crate::scraper::block::{is_tiny_document, BlockReason};
+use crate::scraper::enrich::Enrichment;
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::publishers::PublisherInfo;
+use crate::scraper::recipe::{extract_records, FieldSpec, Record, Recipe};
+use crate::scraper::transform::Transform;
 
-#[derive(Serialize, Debug)]
+//Default Google News topic, the US "Top stories" page
+pub const GOOGLE_NEWS_DEFAULT_TOPIC: &str = "CAAqJggKIiBDQkFTRWdvSUwyMHZNRFZxYUdjU0FtVnVHZ0pWVXlnQVAB";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Article {
     pub title: String,
     pub link: String,
@@ -14,96 +25,81 @@ pub struct Article {
     pub author: String,
     pub sourcelink: String,
     pub sourcename: String,
-    pub image_link: String
+    pub image_link: String,
+    //Main text of the article page, only filled in when articles are expanded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    //Registry entry for the source, see `PublisherRegistry::apply`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<PublisherInfo>,
+    #[serde(flatten)]
+    pub enrichment: Enrichment
 }
 
-pub async fn scrape_website(url : &str) -> String {
-   let client = Client::new();
-   let mut res = client.get(url).send().await.unwrap();
-   let body = res.text().await.unwrap();
-   let document = Html::parse_document(&body);
-   //Select the elements of type article
-    let selector = Selector::parse("article").unwrap();
-    let articles = document.select(&selector);
-    for article in articles {
-        console_log!("{:?}", article);
+//Fetches a single page and applies the recipe to it
+pub async fn scrape_website(fetcher: &Fetcher, url: &str, recipe: &Recipe) -> Result<Vec<Record>, ScrapeError> {
+    let page_url = Url::parse(url).map_err(|e| ScrapeError::InvalidInput(format!("{}: {}", url, e)))?;
+    let body = fetcher.fetch_html(url).await?;
+    let document = Html::parse_document(&body);
+    let records = extract_records(&document, recipe, &page_url).map_err(ScrapeError::InvalidInput)?;
+    if records.is_empty() && is_tiny_document(&document) {
+        return Err(ScrapeError::Blocked(url.to_string(), BlockReason::TinyPage));
     }
-    url.to_string()
+    Ok(records)
 }
 
+//Applies one recipe to many pages with at most `concurrency` fetches in flight. Results are in the order of
+//`urls` and each page succeeds or fails on its own
+pub async fn scrape_many(fetcher: &Fetcher, urls: &[String], recipe: &Recipe, concurrency: usize) -> Vec<Result<Vec<Record>, ScrapeError>> {
+    scrape_stream(fetcher, urls, recipe, concurrency).collect().await
+}
 
-pub async fn google_news_scraper() -> Result<Vec<Article>, Error> {
-    let client = Client::new();
-    let mut res = client.get("https://news.google.com/topics/CAAqJggKIiBDQkFTRWdvSUwyMHZNRFZxYUdjU0FtVnVHZ0pWVXlnQVAB").send().await.unwrap();
-    let body = res.text().await.unwrap();
-    let document = Html::parse_document(&body);
-    let selector = Selector::parse("article").unwrap();
-    let articles = document.select(&selector);
-    let mut article_list = vec![];
-    for article in articles {
-        let title_selector = Selector::parse("a[data-n-tid='29']").unwrap();
-        let title = match article.select(&title_selector).next() {
-            Some(title) => title.text().collect::<Vec<_>>().join(" "),
-            None => "".to_string()
-        };
-        let link = match article.select(&title_selector).next() {
-            Some(link) => link.value().attr("href").unwrap().to_string(),
-            None => "".to_string()
-        };
-        let time_selector = Selector::parse("time").unwrap();
-        let time = match article.select(&time_selector).next() {
-            Some(time) => time.value().attr("datetime").unwrap().to_string(),
-            None => "".to_string()
-        };
-        //Author selector has class name "PJK1m"
-        let author_selector = Selector::parse("span.PJK1m").unwrap();
-        let mut author = match article.select(&author_selector).next() {
-            Some(author) => author.text().collect::<Vec<_>>().join(" "),
-            None => "".to_string()
-        };
-        if author.is_empty() {
-            author = "Syndicated Source".to_string();
-        }
-        //Source Image selector has class name "qEdqNd"
-        let sourcelink_selector = Selector::parse("img.qEdqNd").unwrap();
-        let sourcelink = match article.select(&sourcelink_selector).next() {
-            Some(sourcelink) => sourcelink.value().attr("src").unwrap().to_string(),
-            None => "".to_string()
-        };
-        //Source name has div[data-n-tid='9']
-        let source_name_selector = Selector::parse("div[data-n-tid='9']").unwrap();
-        let sourcename = match article.select(&source_name_selector).next() {
-            Some(sourcename) => sourcename.text().collect::<Vec<_>>().join(" "),
-            None => "".to_string()
-        };
-        //If the article has figure tag, it has an image inside it with img tag with class Quavad
-        let image_selector = Selector::parse("figure img.Quavad").unwrap();
-        let image_link = match article.select(&image_selector).next() {
-            Some(image_link) => image_link.value().attr("src").unwrap().to_string(),
-            None => "".to_string()
-        };
-        let article = Article {
-            title,
-            link,
-            time,
-            author,
-            sourcelink,
-            sourcename,
-            image_link
-        };
-        //If any of the fields are empty, we don't want to include the article
-        if article.title.is_empty() || article.link.is_empty() || article.time.is_empty() || article.author.is_empty() || article.sourcelink.is_empty() || article.sourcename.is_empty() {
-            continue;
-        } else {
-            article_list.push(article);
-        }
-    }
-    //Get the first 10 if there are more than 10 articles
-    if article_list.len() > 10 {
-        article_list.truncate(10);
+//Same as `scrape_many` but yields each page as soon as it and the pages before it are done
+pub fn scrape_stream<'a>(
+    fetcher: &'a Fetcher,
+    urls: &'a [String],
+    recipe: &'a Recipe,
+    concurrency: usize,
+) -> impl Stream<Item = Result<Vec<Record>, ScrapeError>> + 'a {
+    stream::iter(urls).map(move |url| scrape_website(fetcher, url, recipe)).buffered(concurrency.max(1))
+}
+
+//Google News article cards, only the image is optional
+fn google_news_recipe() -> Recipe {
+    let mut fields = BTreeMap::new();
+    fields.insert("title".to_string(), FieldSpec::new("a[data-n-tid='29']", None, vec![Transform::Trim], true));
+    fields.insert("link".to_string(), FieldSpec::new("a[data-n-tid='29']", Some("href"), vec![Transform::AbsoluteUrl], true));
+    fields.insert("time".to_string(), FieldSpec::new("time", Some("datetime"), vec![], true));
+    //Author selector has class name "PJK1m"
+    let syndicated = Transform::Default { value: Value::String("Syndicated Source".to_string()) };
+    fields.insert("author".to_string(), FieldSpec::new("span.PJK1m", None, vec![Transform::Trim, syndicated], true));
+    //Source Image selector has class name "qEdqNd"
+    fields.insert("sourcelink".to_string(), FieldSpec::new("img.qEdqNd", Some("src"), vec![Transform::AbsoluteUrl], true));
+    //Source name has div[data-n-tid='9']
+    fields.insert("sourcename".to_string(), FieldSpec::new("div[data-n-tid='9']", None, vec![Transform::Trim], true));
+    //If the article has figure tag, it has an image inside it with img tag with class Quavad
+    let no_image = Transform::Default { value: Value::String("".to_string()) };
+    fields.insert("image_link".to_string(), FieldSpec::new("figure img.Quavad", Some("src"), vec![Transform::AbsoluteUrl, no_image], false));
+    Recipe {
+        item_selector: "article".to_string(),
+        fields,
+        table: false,
     }
+}
+
+pub async fn google_news_scraper(fetcher: &Fetcher) -> Result<Vec<Article>, ScrapeError> {
+    google_news_topic_scraper(fetcher, GOOGLE_NEWS_DEFAULT_TOPIC).await
+}
+
+pub async fn google_news_topic_scraper(fetcher: &Fetcher, topic: &str) -> Result<Vec<Article>, ScrapeError> {
+    let records = scrape_website(fetcher, &format!("https://news.google.com/topics/{}", topic), &google_news_recipe()).await?;
+    //Required fields are checked by the recipe so every remaining record is a complete article
+    let article_list: Vec<Article> = records
+        .into_iter()
+        .filter_map(|record| serde_json::from_value(Value::Object(record)).ok())
+        .collect();
     match article_list.len() {
-        0 => Err(Error::new(std::io::ErrorKind::Other, "No articles found")),
+        0 => Err(ScrapeError::NoResults),
         _ => Ok(article_list)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,474 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::DateTime;
+
+use crate::scraper::error::ScrapeError;
+use crate::scraper::scraper::Article;
+use crate::scraper::text::{is_cjk, is_stopword};
+use crate::scraper::transform::parse_date;
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+pub const MAX_SEARCH_LIMIT: usize = 50;
+//Longer queries are cut, every term costs a shard read
+const MAX_QUERY_TERMS: usize = 16;
+//A title word counts as much as this many body words
+const TITLE_WEIGHT: u32 = 3;
+//BM25 term frequency saturation and length normalisation
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+const SNIPPET_CHARS: usize = 200;
+//Text kept before the first match in a snippet
+const SNIPPET_LEAD: usize = 40;
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+//Porter's stemmer. A consonant is a letter other than a vowel, and `y` when it follows a vowel or starts the word
+fn is_consonant(word: &[u8], i: usize) -> bool {
+    match word[i] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => false,
+        b'y' => i == 0 || !is_consonant(word, i - 1),
+        _ => true,
+    }
+}
+
+//m in [C](VC)^m[V], how many vowel consonant sequences the stem has
+fn measure(stem: &[u8]) -> usize {
+    let mut i = 0;
+    let mut m = 0;
+    while i < stem.len() && is_consonant(stem, i) {
+        i += 1;
+    }
+    loop {
+        while i < stem.len() && !is_consonant(stem, i) {
+            i += 1;
+        }
+        if i >= stem.len() {
+            return m;
+        }
+        while i < stem.len() && is_consonant(stem, i) {
+            i += 1;
+        }
+        m += 1;
+    }
+}
+
+fn has_vowel(stem: &[u8]) -> bool {
+    (0..stem.len()).any(|i| !is_consonant(stem, i))
+}
+
+fn ends_double_consonant(stem: &[u8]) -> bool {
+    let n = stem.len();
+    n >= 2 && stem[n - 1] == stem[n - 2] && is_consonant(stem, n - 1)
+}
+
+//Consonant, vowel, consonant where the last is not w, x or y, as in hop or fil
+fn ends_cvc(stem: &[u8]) -> bool {
+    let n = stem.len();
+    n >= 3
+        && is_consonant(stem, n - 3)
+        && !is_consonant(stem, n - 2)
+        && is_consonant(stem, n - 1)
+        && !matches!(stem[n - 1], b'w' | b'x' | b'y')
+}
+
+//Replaces the first of the suffixes the word ends with when its stem passes `condition`. Returns whether a suffix
+//matched at all, a match whose stem fails stops the step just the same
+fn replace_suffix(word: &mut Vec<u8>, rules: &[(&str, &str)], condition: impl Fn(&[u8]) -> bool) -> bool {
+    for (suffix, replacement) in rules {
+        if word.ends_with(suffix.as_bytes()) {
+            let stem_len = word.len() - suffix.len();
+            if condition(&word[..stem_len]) {
+                word.truncate(stem_len);
+                word.extend_from_slice(replacement.as_bytes());
+            }
+            return true;
+        }
+    }
+    false
+}
+
+const STEP2: [(&str, &str); 20] = [
+    ("ational", "ate"), ("tional", "tion"), ("enci", "ence"), ("anci", "ance"), ("izer", "ize"), ("abli", "able"),
+    ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous"), ("ization", "ize"), ("ation", "ate"),
+    ("ator", "ate"), ("alism", "al"), ("iveness", "ive"), ("fulness", "ful"), ("ousness", "ous"), ("aliti", "al"),
+    ("iviti", "ive"), ("biliti", "ble"),
+];
+const STEP3: [(&str, &str); 7] = [
+    ("icate", "ic"), ("ative", ""), ("alize", "al"), ("iciti", "ic"), ("ical", "ic"), ("ful", ""), ("ness", ""),
+];
+const STEP4: [&str; 18] = [
+    "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ou", "ism", "ate", "iti", "ous",
+    "ive", "ize",
+];
+
+//Reduces an English word to its stem, e.g. `elections` and `elected` to `elect`. Words with other letters than a to
+//z are left alone
+pub fn stem(word: &str) -> String {
+    if word.len() <= 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+        return word.to_string();
+    }
+    let mut w = word.as_bytes().to_vec();
+    //1a: plurals
+    replace_suffix(&mut w, &[("sses", "ss"), ("ies", "i"), ("ss", "ss"), ("s", "")], |_| true);
+    //1b: past tenses and gerunds
+    if w.ends_with(b"eed") {
+        if measure(&w[..w.len() - 3]) > 0 {
+            w.pop();
+        }
+    } else {
+        let mut removed = false;
+        for suffix in ["ed", "ing"] {
+            if w.ends_with(suffix.as_bytes()) && has_vowel(&w[..w.len() - suffix.len()]) {
+                w.truncate(w.len() - suffix.len());
+                removed = true;
+                break;
+            }
+        }
+        if removed {
+            if w.ends_with(b"at") || w.ends_with(b"bl") || w.ends_with(b"iz") {
+                w.push(b'e');
+            } else if ends_double_consonant(&w) && !matches!(w[w.len() - 1], b'l' | b's' | b'z') {
+                w.pop();
+            } else if measure(&w) == 1 && ends_cvc(&w) {
+                w.push(b'e');
+            }
+        }
+    }
+    //1c
+    if w.ends_with(b"y") && has_vowel(&w[..w.len() - 1]) {
+        let last = w.len() - 1;
+        w[last] = b'i';
+    }
+    replace_suffix(&mut w, &STEP2, |stem| measure(stem) > 0);
+    replace_suffix(&mut w, &STEP3, |stem| measure(stem) > 0);
+    //4: -ion only goes after s or t
+    let step4: Vec<(&str, &str)> = STEP4.iter().map(|suffix| (*suffix, "")).collect();
+    if !replace_suffix(&mut w, &step4, |stem| measure(stem) > 1) && w.ends_with(b"ion") {
+        let stem_len = w.len() - 3;
+        if measure(&w[..stem_len]) > 1 && stem_len > 0 && matches!(w[stem_len - 1], b's' | b't') {
+            w.truncate(stem_len);
+        }
+    }
+    //5
+    if w.ends_with(b"e") {
+        let stem = &w[..w.len() - 1];
+        if measure(stem) > 1 || (measure(stem) == 1 && !ends_cvc(stem)) {
+            w.pop();
+        }
+    }
+    if measure(&w) > 1 && ends_double_consonant(&w) && w.ends_with(b"l") {
+        w.pop();
+    }
+    String::from_utf8(w).unwrap_or_else(|_| word.to_string())
+}
+
+//Byte ranges of the words of a text
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                spans.push((from, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        spans.push((from, text.len()));
+    }
+    spans
+}
+
+//Index terms of a single word: its stem, nothing for stopwords and single letters. Chinese, Japanese and Korean
+//runs become overlapping character pairs as they have no spaces
+fn word_terms(word: &str) -> Vec<String> {
+    let word = word.to_lowercase();
+    if word.chars().any(is_cjk) {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() == 1 {
+            return vec![word];
+        }
+        return chars.windows(2).map(|pair| pair.iter().collect()).collect();
+    }
+    if word.chars().count() < 2 || is_stopword(&word) {
+        return vec![];
+    }
+    vec![stem(&word)]
+}
+
+//Search terms of a text, in order and with repeats
+pub fn analyze(text: &str) -> Vec<String> {
+    word_spans(text).into_iter().flat_map(|(start, end)| word_terms(&text[start..end])).collect()
+}
+
+//Term frequencies of an article and its length in terms, title words weighted over body words
+pub fn document_terms(article: &Article) -> (HashMap<String, u32>, u32) {
+    let mut frequencies: HashMap<String, u32> = HashMap::new();
+    let mut length = 0;
+    let fields = [(article.title.as_str(), TITLE_WEIGHT), (article.body.as_deref().unwrap_or_default(), 1)];
+    for (text, weight) in fields {
+        for term in analyze(text) {
+            *frequencies.entry(term).or_insert(0) += weight;
+            length += weight;
+        }
+    }
+    (frequencies, length)
+}
+
+//Okapi BM25 weight of one query term in one document
+pub fn bm25(frequency: f64, document_frequency: usize, documents: usize, length: f64, average_length: f64) -> f64 {
+    let (n, df) = (documents as f64, document_frequency as f64);
+    let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+    let norm = if average_length > 0.0 { length / average_length } else { 1.0 };
+    idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * norm))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+//Up to SNIPPET_CHARS of the text around the densest stretch of matching words, HTML escaped with the matches in
+//<mark> tags. None when no word matches
+pub fn snippet(text: &str, terms: &HashSet<String>) -> Option<String> {
+    let spans = word_spans(text);
+    let matches: Vec<bool> = spans
+        .iter()
+        .map(|(start, end)| word_terms(&text[*start..*end]).iter().any(|term| terms.contains(term)))
+        .collect();
+    let matched: Vec<usize> = (0..spans.len()).filter(|i| matches[*i]).collect();
+    let lowered: Vec<String> = matched.iter().map(|i| text[spans[*i].0..spans[*i].1].to_lowercase()).collect();
+    let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    //The window starting a little before a match, in bytes
+    let window = |first: usize| -> (usize, usize) {
+        let start = char_starts.partition_point(|i| *i < spans[first].0).saturating_sub(SNIPPET_LEAD + 1);
+        let end = char_starts.get(start + SNIPPET_CHARS).copied().unwrap_or(text.len());
+        (char_starts.get(start).copied().unwrap_or(0), end)
+    };
+    //Slides the window over the matches, keeping count of the distinct matching words inside it. Windows only move
+    //forward, so each match enters and leaves once. The window covering the most wins, earlier windows on a tie
+    let mut covered: HashMap<&str, usize> = HashMap::new();
+    let (mut entered, mut left) = (0, 0);
+    let mut best: Option<(usize, usize)> = None;
+    for &first in &matched {
+        let (start, end) = window(first);
+        while entered < matched.len() && spans[matched[entered]].1 <= end {
+            *covered.entry(&lowered[entered]).or_insert(0) += 1;
+            entered += 1;
+        }
+        while left < entered && spans[matched[left]].0 < start {
+            if let Some(count) = covered.get_mut(lowered[left].as_str()) {
+                *count -= 1;
+                if *count == 0 {
+                    covered.remove(lowered[left].as_str());
+                }
+            }
+            left += 1;
+        }
+        if best.is_none_or(|(most, _)| covered.len() > most) {
+            best = Some((covered.len(), first));
+        }
+    }
+    let (start, end) = window(best?.1);
+    let words: Vec<usize> = (0..spans.len()).filter(|i| spans[*i].0 >= start && spans[*i].1 <= end).collect();
+    let (first, last) = (*words.first()?, *words.last()?);
+    let mut out = String::new();
+    if spans[first].0 > 0 {
+        out.push('…');
+    }
+    let mut position = spans[first].0;
+    for i in words {
+        let (word_start, word_end) = spans[i];
+        out.push_str(&escape_html(&text[position..word_start]));
+        let word = escape_html(&text[word_start..word_end]);
+        match matches[i] {
+            true => out.push_str(&format!("<mark>{}</mark>", word)),
+            false => out.push_str(&word),
+        }
+        position = word_end;
+    }
+    if spans[last].1 < text.len() {
+        out.push('…');
+    }
+    Some(out.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+//Milliseconds since the epoch of a date in any format `parse_date` knows. A `to` date without a time covers the whole
+//day
+fn parse_bound(text: &str, end_of_day: bool) -> Option<u64> {
+    let date = DateTime::parse_from_rfc3339(&parse_date(text, None)?).ok()?;
+    let millis = date.timestamp_millis();
+    //Dates before 1970 are not expected in news
+    let millis = (millis >= 0).then_some(millis as u64)?;
+    let date_only = !text.contains(':') && !text.chars().all(|c| c.is_ascii_digit());
+    Some(if end_of_day && date_only { millis + DAY_MILLIS - 1 } else { millis })
+}
+
+//Publication time of an article in milliseconds, when its `time` can be read
+pub fn published_millis(article: &Article) -> Option<u64> {
+    parse_bound(&article.time, false)
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    //Distinct query terms
+    pub terms: Vec<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub source: Option<String>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl SearchQuery {
+    //Reads `q=`, `from=`, `to=`, `source=`, `offset=` and `limit=`
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, ScrapeError> {
+        let q = params.get("q").map(|q| q.trim()).unwrap_or_default();
+        if q.is_empty() {
+            return Err(ScrapeError::InvalidInput("q is required".to_string()));
+        }
+        let mut terms = vec![];
+        for term in analyze(q) {
+            if !terms.contains(&term) && terms.len() < MAX_QUERY_TERMS {
+                terms.push(term);
+            }
+        }
+        if terms.is_empty() {
+            return Err(ScrapeError::InvalidInput(format!("'{}' has no searchable words", q)));
+        }
+        let bound = |name: &str, end_of_day: bool| -> Result<Option<u64>, ScrapeError> {
+            match params.get(name) {
+                None => Ok(None),
+                Some(value) => parse_bound(value, end_of_day)
+                    .map(Some)
+                    .ok_or_else(|| ScrapeError::InvalidInput(format!("{} is not a date: {}", name, value))),
+            }
+        };
+        let number = |name: &str| -> Result<Option<usize>, ScrapeError> {
+            match params.get(name) {
+                None => Ok(None),
+                Some(value) => value.parse::<usize>().map(Some).map_err(|_| ScrapeError::InvalidInput(format!("{} must be a whole number", name))),
+            }
+        };
+        let limit = number("limit")?.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        if limit == 0 || limit > MAX_SEARCH_LIMIT {
+            return Err(ScrapeError::InvalidInput(format!("limit must be from 1 to {}", MAX_SEARCH_LIMIT)));
+        }
+        Ok(SearchQuery {
+            terms,
+            from: bound("from", false)?,
+            to: bound("to", true)?,
+            source: params.get("source").cloned(),
+            offset: number("offset")?.unwrap_or(0),
+            limit,
+        })
+    }
+
+    //Whether an article indexed from `source` at `time` is in the requested range
+    pub fn keeps(&self, source: &str, time: u64) -> bool {
+        self.source.as_ref().is_none_or(|wanted| wanted == source)
+            && self.from.is_none_or(|from| time >= from)
+            && self.to.is_none_or(|to| time <= to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(words: &[&str]) -> HashSet<String> {
+        words.iter().map(|word| stem(word)).collect()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn stems_like_porter() {
+        let cases = [
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("agreed", "agre"),
+            ("hopping", "hop"),
+            ("filing", "file"),
+            ("happy", "happi"),
+            ("relational", "relat"),
+            ("generalizations", "gener"),
+            ("electrical", "electr"),
+            ("adjustment", "adjust"),
+            ("controlling", "control"),
+            ("sky", "sky"),
+        ];
+        for (word, expected) in cases {
+            assert_eq!(stem(word), expected, "{}", word);
+        }
+    }
+
+    #[test]
+    fn analyzes_text_into_terms() {
+        assert_eq!(analyze("The Markets are rallying, a 2nd day!"), vec!["market", "ralli", "2nd", "dai"]);
+        assert_eq!(analyze("東京都"), vec!["東京", "京都"]);
+    }
+
+    #[test]
+    fn weights_title_terms() {
+        let article: Article = serde_json::from_value(serde_json::json!({
+            "title": "Storm warning",
+            "link": "",
+            "time": "",
+            "author": "",
+            "sourcelink": "",
+            "sourcename": "",
+            "image_link": "",
+            "body": "The storm arrives tonight"
+        }))
+        .unwrap();
+        let (frequencies, length) = document_terms(&article);
+        assert_eq!(frequencies["storm"], TITLE_WEIGHT + 1);
+        assert_eq!(frequencies["tonight"], 1);
+        assert_eq!(length, 2 * TITLE_WEIGHT + 3);
+    }
+
+    #[test]
+    fn ranks_with_bm25() {
+        //Rarer terms, more occurrences and shorter documents score higher
+        assert!(bm25(1.0, 1, 100, 10.0, 10.0) > bm25(1.0, 50, 100, 10.0, 10.0));
+        assert!(bm25(3.0, 5, 100, 10.0, 10.0) > bm25(1.0, 5, 100, 10.0, 10.0));
+        assert!(bm25(1.0, 5, 100, 5.0, 10.0) > bm25(1.0, 5, 100, 20.0, 10.0));
+        //Term frequency saturates
+        assert!(bm25(100.0, 5, 100, 10.0, 10.0) < bm25(1.0, 5, 100, 10.0, 10.0) * (K1 + 1.0));
+        assert!(bm25(1.0, 100, 100, 10.0, 10.0) > 0.0);
+    }
+
+    #[test]
+    fn snippets_highlight_and_escape() {
+        assert_eq!(snippet("Storms <hit> the coast & storms move on", &terms(&["storm"])).as_deref(), Some("<mark>Storms</mark> &lt;hit&gt; the coast &amp; <mark>storms</mark> move on"));
+        assert_eq!(snippet("Nothing to see here", &terms(&["storm"])), None);
+    }
+
+    #[test]
+    fn snippets_pick_the_densest_window() {
+        let filler = "lorem ipsum dolor sit amet ".repeat(20);
+        let text = format!("Rates mentioned once. {}Rates and inflation together here. {}", filler, filler);
+        let found = snippet(&text, &terms(&["rates", "inflation"])).unwrap();
+        assert!(found.starts_with('…') && found.ends_with('…'));
+        assert!(found.contains("<mark>Rates</mark> and <mark>inflation</mark>"), "{}", found);
+        assert!(found.replace("<mark>", "").replace("</mark>", "").chars().count() <= SNIPPET_CHARS + 2);
+    }
+
+    #[test]
+    fn parses_search_params() {
+        let query = SearchQuery::from_params(&params(&[("q", "Storm storms and rain"), ("from", "2024-01-01"), ("to", "2024-01-31"), ("source", "bbc")])).unwrap();
+        assert_eq!(query.terms, vec!["storm", "rain"]);
+        assert_eq!(query.from, Some(1_704_067_200_000));
+        assert_eq!(query.to, Some(1_706_745_600_000 - 1));
+        assert!(query.keeps("bbc", 1_706_745_599_999));
+        assert!(!query.keeps("bbc", 1_706_745_600_000));
+        assert!(!query.keeps("cnn", 1_704_067_200_000));
+        for pairs in [vec![], vec![("q", "the and")], vec![("q", "storm"), ("limit", "0")], vec![("q", "storm"), ("from", "soon")]] {
+            assert!(SearchQuery::from_params(&params(&pairs)).is_err(), "{:?}", pairs);
+        }
+    }
+}
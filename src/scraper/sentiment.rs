@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use crate::scraper::text::{tokenize, Token};
+
+//Normalises the summed valence into -1..1, the value VADER uses
+const NORMALIZATION_ALPHA: f64 = 15.0;
+//Valence change for an intensifier, an upper case word among lower case ones, and each exclamation mark
+const BOOSTER_INCREMENT: f64 = 0.293;
+const CAPS_INCREMENT: f64 = 0.733;
+const EXCLAMATION_INCREMENT: f64 = 0.292;
+const MAX_EXCLAMATIONS: usize = 4;
+//A negated word keeps a little less than its opposite valence
+const NEGATION_SCALAR: f64 = -0.74;
+//How many words before a sentiment word negations and intensifiers reach
+const LOOKBACK: usize = 3;
+
+//Valence from -4 (most negative) to 4 (most positive) of English words common in news
+const LEXICON: [(&str, f64); 257] = [
+    ("abuse", -3.2), ("accused", -1.9), ("acclaimed", 2.4), ("accomplish", 1.8), ("achievement", 2.2), ("agree", 1.5),
+    ("agreement", 1.6), ("alarm", -1.9), ("alarming", -2.1), ("anger", -2.7), ("angry", -2.3), ("anxiety", -2.2),
+    ("arrest", -1.9), ("arrested", -2.0), ("attack", -2.1), ("attacks", -2.1), ("award", 2.5), ("awarded", 2.1),
+    ("awful", -2.0), ("bad", -2.5), ("ban", -2.1), ("bankrupt", -2.6), ("bankruptcy", -2.6), ("beat", 1.4),
+    ("beautiful", 2.9), ("benefit", 2.0), ("benefits", 1.9), ("best", 3.2), ("better", 1.9), ("blame", -1.4),
+    ("bomb", -2.2), ("boom", 1.9), ("boost", 1.7), ("boosts", 1.7), ("brave", 2.4), ("breakthrough", 2.5),
+    ("bright", 1.9), ("brilliant", 2.8), ("broken", -1.9), ("brutal", -3.1), ("calm", 1.3), ("catastrophe", -3.4),
+    ("catastrophic", -3.3), ("celebrate", 2.7), ("celebrates", 2.6), ("chaos", -2.6), ("charged", -1.4), ("cheer", 2.3),
+    ("collapse", -2.4), ("collapses", -2.4), ("comeback", 1.9), ("concern", -1.4), ("concerns", -1.4), ("condemn", -2.2),
+    ("condemns", -2.2), ("conflict", -1.8), ("corrupt", -3.0), ("corruption", -3.0), ("crash", -2.4), ("crashes", -2.4),
+    ("crime", -2.5), ("crisis", -3.1), ("critical", -1.3), ("criticism", -1.9), ("criticized", -1.8), ("cruel", -2.8),
+    ("cut", -1.1), ("cuts", -1.1), ("damage", -2.2), ("damaged", -1.9), ("danger", -2.4), ("dangerous", -2.1),
+    ("dead", -3.3), ("deadly", -2.9), ("death", -2.9), ("deaths", -2.9), ("debt", -1.5), ("decline", -1.1),
+    ("declines", -1.1), ("defeat", -2.0), ("deficit", -1.4), ("delay", -1.3), ("delayed", -1.3), ("destroy", -2.5),
+    ("destroyed", -2.6), ("devastating", -3.0), ("die", -2.9), ("dies", -2.9), ("died", -2.6), ("disaster", -3.1),
+    ("dispute", -1.7), ("drop", -1.1), ("drops", -1.1), ("easy", 1.9), ("emergency", -1.6), ("encouraging", 2.4),
+    ("enjoy", 2.2), ("excellent", 3.2), ("excited", 2.4), ("exciting", 2.2), ("fail", -2.5), ("failed", -2.3),
+    ("fails", -2.3), ("failure", -2.3), ("fake", -2.1), ("fall", -1.1), ("falls", -1.1), ("fear", -2.2),
+    ("fears", -2.2), ("fight", -1.6), ("fine", 0.8), ("fire", -1.4), ("flood", -1.8), ("fraud", -2.8), ("free", 2.3),
+    ("fun", 2.3), ("gain", 2.0), ("gains", 1.8), ("good", 1.9), ("great", 3.1), ("grief", -2.2), ("grow", 1.3),
+    ("growth", 1.6), ("guilty", -1.8), ("happy", 2.7), ("harm", -2.5), ("hate", -2.7), ("heal", 2.0), ("healthy", 1.7),
+    ("help", 1.7), ("helps", 1.6), ("hero", 2.6), ("hope", 1.9), ("hopes", 1.8), ("horrible", -2.5), ("hostage", -2.4),
+    ("hurt", -2.4), ("illegal", -2.6), ("improve", 1.9), ("improved", 2.1), ("improves", 1.8), ("injured", -1.7),
+    ("injury", -1.8), ("innovative", 1.9), ("inspiring", 2.6), ("jobs", 0.8), ("joy", 2.8), ("kill", -3.7),
+    ("killed", -3.5), ("killing", -3.4), ("landmark", 1.5), ("lawsuit", -1.3), ("layoffs", -2.2), ("leak", -1.4),
+    ("lose", -1.7), ("loses", -1.7), ("loss", -1.3), ("losses", -1.7), ("lost", -1.3), ("love", 3.2), ("lucky", 1.8),
+    ("murder", -3.7), ("nice", 1.8), ("optimism", 2.5), ("optimistic", 2.3), ("outage", -1.6), ("outbreak", -2.2),
+    ("pain", -2.3), ("panic", -2.3), ("peace", 2.5), ("perfect", 2.7), ("plunge", -2.1), ("plunges", -2.1),
+    ("poor", -2.1), ("popular", 1.8), ("positive", 2.3), ("poverty", -2.3), ("praise", 2.6), ("praised", 2.4),
+    ("problem", -1.7), ("problems", -1.7), ("profit", 1.9), ("progress", 1.8), ("protect", 1.6), ("protest", -1.0),
+    ("protests", -1.0), ("rally", 1.3), ("rallies", 1.3), ("recession", -2.2), ("record", 0.8), ("recover", 1.7),
+    ("recovery", 1.8), ("rescue", 2.0), ("rescued", 1.9), ("resign", -1.0), ("resigns", -1.0), ("rise", 0.8),
+    ("rises", 0.8), ("risk", -1.1), ("risks", -1.1), ("riot", -2.6), ("sad", -2.1), ("safe", 1.9), ("scam", -2.7),
+    ("scandal", -2.4), ("scare", -2.2), ("shooting", -2.5), ("shortage", -1.8), ("slump", -1.8), ("slumps", -1.8),
+    ("smart", 1.7), ("soar", 2.2), ("soars", 2.2), ("strong", 2.3), ("stronger", 2.0), ("struggle", -1.7),
+    ("struggles", -1.6), ("success", 2.7), ("successful", 2.8), ("sue", -1.4), ("suffer", -2.1), ("support", 1.7),
+    ("surge", 1.4), ("surges", 1.4), ("surprise", 1.1), ("survive", 1.2), ("terrible", -2.8), ("terror", -3.0),
+    ("terrorist", -3.1), ("threat", -2.4), ("threatens", -2.1), ("thrive", 2.5), ("tragedy", -3.1), ("tragic", -3.0),
+    ("triumph", 3.2), ("trouble", -1.7), ("tumble", -1.7), ("tumbles", -1.7), ("unrest", -2.0), ("victims", -2.1),
+    ("victory", 2.8), ("violence", -3.1), ("violent", -2.9), ("war", -2.9), ("warn", -1.3), ("warning", -1.4),
+    ("warns", -1.4), ("weak", -1.9), ("welcome", 2.0), ("win", 2.8), ("wins", 2.7), ("won", 2.7), ("worried", -1.9),
+    ("worry", -1.9), ("worse", -2.1), ("worst", -3.1), ("wrong", -2.1),
+];
+
+//Words that strengthen or, when negative, weaken the sentiment word after them
+const BOOSTERS: [(&str, f64); 18] = [
+    ("very", 1.0), ("extremely", 1.0), ("really", 1.0), ("highly", 1.0), ("hugely", 1.0), ("incredibly", 1.0),
+    ("deeply", 1.0), ("sharply", 1.0), ("significantly", 1.0), ("totally", 1.0), ("most", 1.0), ("so", 1.0),
+    ("slightly", -1.0), ("somewhat", -1.0), ("barely", -1.0), ("marginally", -1.0), ("partly", -1.0), ("hardly", -1.0),
+];
+
+const NEGATIONS: [&str; 11] = ["not", "no", "never", "none", "nobody", "nothing", "neither", "nor", "cannot", "without", "nowhere"];
+
+//VADER style scores, `compound` is the overall polarity from -1 to 1 and the others are the shares of the text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SentimentScores {
+    pub compound: f64,
+    pub positive: f64,
+    pub negative: f64,
+    pub neutral: f64,
+}
+
+//Scores of an article's title and, when it was expanded, its body
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sentiment {
+    pub title: SentimentScores,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<SentimentScores>,
+}
+
+fn valence(word: &str) -> Option<f64> {
+    LEXICON.iter().find(|(entry, _)| *entry == word).map(|(_, valence)| *valence)
+}
+
+fn booster(word: &str) -> Option<f64> {
+    BOOSTERS.iter().find(|(entry, _)| *entry == word).map(|(_, direction)| direction * BOOSTER_INCREMENT)
+}
+
+fn is_negation(word: &str) -> bool {
+    NEGATIONS.contains(&word) || word.ends_with("n't") || word.ends_with("n’t")
+}
+
+fn is_shouted(token: &Token) -> bool {
+    token.text.chars().any(char::is_alphabetic) && !token.text.chars().any(char::is_lowercase)
+}
+
+//Three decimals, without negative zero
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0 + 0.0
+}
+
+//Scores English text with the VADER rules: intensifiers and upper case strengthen a word, a negation in the three
+//words before it flips it, words after "but" outweigh those before it and exclamation marks add emphasis
+pub fn score(text: &str) -> SentimentScores {
+    let tokens = tokenize(text);
+    let words: Vec<String> = tokens.iter().map(Token::lower).collect();
+    //Shouting only means something when the rest of the text is not shouted too
+    let mixed_case = tokens.iter().any(|token| !is_shouted(token));
+    let mut sentiments: Vec<f64> = vec![0.0; words.len()];
+    for (index, word) in words.iter().enumerate() {
+        let mut value = match valence(word) {
+            Some(value) => value,
+            None => continue,
+        };
+        if mixed_case && is_shouted(&tokens[index]) {
+            value += CAPS_INCREMENT * value.signum();
+        }
+        let window = index.saturating_sub(LOOKBACK)..index;
+        for (distance, before) in words[window.clone()].iter().rev().enumerate() {
+            if let Some(increment) = booster(before) {
+                //Intensifiers further away count for less
+                value += increment * (1.0 - 0.05 * distance as f64) * value.signum();
+            }
+        }
+        if words[window].iter().any(|before| is_negation(before)) {
+            value *= NEGATION_SCALAR;
+        }
+        sentiments[index] = value;
+    }
+    if let Some(but) = words.iter().position(|word| word == "but") {
+        for (index, value) in sentiments.iter_mut().enumerate() {
+            match index.cmp(&but) {
+                std::cmp::Ordering::Less => *value *= 0.5,
+                std::cmp::Ordering::Greater => *value *= 1.5,
+                std::cmp::Ordering::Equal => {}
+            }
+        }
+    }
+
+    let exclamations = text.matches('!').count().min(MAX_EXCLAMATIONS) as f64 * EXCLAMATION_INCREMENT;
+    let total: f64 = sentiments.iter().sum();
+    let emphasized = if total == 0.0 { 0.0 } else { total + exclamations * total.signum() };
+    let compound = emphasized / (emphasized * emphasized + NORMALIZATION_ALPHA).sqrt();
+
+    let mut positive: f64 = sentiments.iter().filter(|value| **value > 0.0).map(|value| value + 1.0).sum();
+    let mut negative: f64 = sentiments.iter().filter(|value| **value < 0.0).map(|value| value - 1.0).sum::<f64>().abs();
+    let neutral = sentiments.iter().filter(|value| **value == 0.0).count() as f64;
+    if positive > negative {
+        positive += exclamations;
+    } else if negative > positive {
+        negative += exclamations;
+    }
+    let sum = positive + negative + neutral;
+    if sum == 0.0 {
+        return SentimentScores { compound: 0.0, positive: 0.0, negative: 0.0, neutral: 1.0 };
+    }
+    SentimentScores {
+        compound: round(compound),
+        positive: round(positive / sum),
+        negative: round(negative / sum),
+        neutral: round(neutral / sum),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_polarity() {
+        assert!(score("Markets surge on great growth news").compound > 0.5);
+        assert!(score("Terrible storm leaves town in grief").compound < -0.5);
+        assert_eq!(score("The council meets on Tuesday"), SentimentScores { compound: 0.0, positive: 0.0, negative: 0.0, neutral: 1.0 });
+        assert_eq!(score(""), SentimentScores { compound: 0.0, positive: 0.0, negative: 0.0, neutral: 1.0 });
+    }
+
+    #[test]
+    fn shares_add_up() {
+        let scores = score("A good start but a bad ending");
+        assert!((scores.positive + scores.negative + scores.neutral - 1.0).abs() < 0.01);
+        assert!(scores.positive > 0.0 && scores.negative > 0.0);
+    }
+
+    #[test]
+    fn negation_flips_and_boosters_strengthen() {
+        assert!(score("The results were not good").compound < 0.0);
+        assert!(score("The results weren't good").compound < 0.0);
+        assert!(score("The results were very good").compound > score("The results were good").compound);
+    }
+
+    #[test]
+    fn emphasis_and_contrast() {
+        assert!(score("The results were GOOD").compound > score("The results were good").compound);
+        //Shouting everything is not emphasis
+        assert_eq!(score("THE RESULTS WERE GOOD").compound, score("the results were good").compound);
+        assert!(score("The results were good!!").compound > score("The results were good").compound);
+        //The clause after "but" decides
+        assert!(score("The start was great but the ending was terrible").compound < 0.0);
+    }
+}
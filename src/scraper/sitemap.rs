@@ -0,0 +1,232 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use reqwest::Url;
+use roxmltree::{Document, Node};
+
+use crate::scraper::encoding::decode_document;
+use crate::scraper::enrich::Enrichment;
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::scraper::Article;
+
+const NEWS_NAMESPACE: &str = "http://www.google.com/schemas/sitemap-news/0.9";
+const IMAGE_NAMESPACE: &str = "http://www.google.com/schemas/sitemap-image/1.1";
+
+//Child sitemaps read from an index, newest first, and entries returned by default
+const MAX_CHILD_SITEMAPS: usize = 5;
+const DEFAULT_LIMIT: usize = 100;
+//The sitemap protocol caps a sitemap at 50MB uncompressed, anything larger is refused rather than inflated into memory
+const MAX_SITEMAP_BYTES: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SitemapEntry {
+    pub loc: String,
+    pub lastmod: Option<String>,
+    pub title: Option<String>,
+    pub publication_date: Option<String>,
+    pub publication_name: Option<String>,
+    pub language: Option<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sitemap {
+    //<sitemapindex>, the locations of child sitemaps with their lastmod
+    Index(Vec<(String, Option<String>)>),
+    //<urlset>
+    UrlSet(Vec<SitemapEntry>),
+}
+
+//Gzipped sitemaps are recognised by their magic bytes rather than the file name
+pub fn decode_body(bytes: &[u8]) -> Result<String, ScrapeError> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut xml = vec![];
+        GzDecoder::new(bytes)
+            .take(MAX_SITEMAP_BYTES + 1)
+            .read_to_end(&mut xml)
+            .map_err(|e| ScrapeError::Fetch(format!("Invalid gzip sitemap: {}", e)))?;
+        if xml.len() as u64 > MAX_SITEMAP_BYTES {
+            return Err(ScrapeError::Fetch(format!("Sitemap is larger than {} bytes uncompressed", MAX_SITEMAP_BYTES)));
+        }
+        return Ok(decode_document(&xml, None, None));
+    }
+    if bytes.len() as u64 > MAX_SITEMAP_BYTES {
+        return Err(ScrapeError::Fetch(format!("Sitemap is larger than {} bytes", MAX_SITEMAP_BYTES)));
+    }
+    Ok(decode_document(bytes, None, None))
+}
+
+fn child_text(node: Node, name: &str, namespace: Option<&str>) -> Option<String> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name && (namespace.is_none() || child.tag_name().namespace() == namespace))
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn child_element<'a, 'input>(node: Node<'a, 'input>, name: &str, namespace: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name && child.tag_name().namespace() == Some(namespace))
+}
+
+fn parse_entry(url: Node) -> Option<SitemapEntry> {
+    let mut entry = SitemapEntry {
+        loc: child_text(url, "loc", None)?,
+        lastmod: child_text(url, "lastmod", None),
+        ..SitemapEntry::default()
+    };
+    if let Some(news) = child_element(url, "news", NEWS_NAMESPACE) {
+        entry.title = child_text(news, "title", Some(NEWS_NAMESPACE));
+        entry.publication_date = child_text(news, "publication_date", Some(NEWS_NAMESPACE));
+        if let Some(publication) = child_element(news, "publication", NEWS_NAMESPACE) {
+            entry.publication_name = child_text(publication, "name", Some(NEWS_NAMESPACE));
+            entry.language = child_text(publication, "language", Some(NEWS_NAMESPACE));
+        }
+    }
+    if let Some(image) = child_element(url, "image", IMAGE_NAMESPACE) {
+        entry.image = child_text(image, "loc", Some(IMAGE_NAMESPACE));
+    }
+    Some(entry)
+}
+
+pub fn parse_sitemap(xml: &str) -> Result<Sitemap, ScrapeError> {
+    let document = Document::parse(xml).map_err(|e| ScrapeError::Fetch(format!("Invalid sitemap: {}", e)))?;
+    let root = document.root_element();
+    match root.tag_name().name() {
+        "sitemapindex" => Ok(Sitemap::Index(
+            root.children()
+                .filter(|child| child.is_element() && child.tag_name().name() == "sitemap")
+                .filter_map(|sitemap| Some((child_text(sitemap, "loc", None)?, child_text(sitemap, "lastmod", None))))
+                .collect(),
+        )),
+        "urlset" => Ok(Sitemap::UrlSet(
+            root.children()
+                .filter(|child| child.is_element() && child.tag_name().name() == "url")
+                .filter_map(parse_entry)
+                .collect(),
+        )),
+        other => Err(ScrapeError::Fetch(format!("Unexpected sitemap root <{}>", other))),
+    }
+}
+
+async fn fetch_sitemap(fetcher: &Fetcher, url: &str) -> Result<Sitemap, ScrapeError> {
+    let bytes = fetcher.fetch_bytes(url).await?;
+    parse_sitemap(&decode_body(&bytes)?)
+}
+
+//Sitemaps advertised in robots.txt, falling back to /sitemap.xml
+pub async fn discover_sitemaps(fetcher: &Fetcher, site: &str) -> Result<Vec<String>, ScrapeError> {
+    let url = Url::parse(site).map_err(|e| ScrapeError::InvalidInput(format!("{}: {}", site, e)))?;
+    let robots = fetcher.robots(&url).await;
+    if !robots.sitemaps.is_empty() {
+        return Ok(robots.sitemaps);
+    }
+    Ok(vec![format!("{}/sitemap.xml", url.origin().ascii_serialization())])
+}
+
+//Reads the sitemaps, expanding indexes one level deep, and returns up to `limit` entries
+pub async fn read_sitemaps(fetcher: &Fetcher, sitemaps: Vec<String>, limit: usize) -> Result<Vec<SitemapEntry>, ScrapeError> {
+    let mut entries = vec![];
+    let mut last_error = None;
+    for sitemap_url in sitemaps {
+        let sitemap = match fetch_sitemap(fetcher, &sitemap_url).await {
+            Ok(sitemap) => sitemap,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        match sitemap {
+            Sitemap::UrlSet(found) => entries.extend(found),
+            Sitemap::Index(mut children) => {
+                //W3C datetimes compare correctly as strings, children without lastmod go last
+                children.sort_by(|a, b| b.1.cmp(&a.1));
+                for (child, _) in children.into_iter().take(MAX_CHILD_SITEMAPS) {
+                    match fetch_sitemap(fetcher, &child).await {
+                        Ok(Sitemap::UrlSet(found)) => entries.extend(found),
+                        //Indexes of indexes are not followed
+                        Ok(Sitemap::Index(_)) => {}
+                        Err(e) => last_error = Some(e),
+                    }
+                    if entries.len() >= limit {
+                        break;
+                    }
+                }
+            }
+        }
+        if entries.len() >= limit {
+            break;
+        }
+    }
+    if entries.is_empty() {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+    }
+    entries.truncate(limit);
+    Ok(entries)
+}
+
+fn entry_to_article(entry: SitemapEntry) -> Article {
+    let origin = Url::parse(&entry.loc).map(|url| url.origin().ascii_serialization()).unwrap_or_default();
+    Article {
+        title: entry.title.unwrap_or_default(),
+        time: entry.publication_date.or(entry.lastmod).unwrap_or_default(),
+        author: String::new(),
+        sourcelink: if origin.is_empty() { String::new() } else { format!("{}/favicon.ico", origin) },
+        sourcename: entry.publication_name.unwrap_or(origin),
+        image_link: entry.image.unwrap_or_default(),
+        link: entry.loc,
+        body: None,
+        publisher: None,
+        enrichment: Enrichment::default(),
+    }
+}
+
+//Source for news sites: `sitemap` names a sitemap directly, otherwise it is discovered from `url`
+pub async fn sitemap_scraper(fetcher: &Fetcher, url: Option<&String>, sitemap: Option<&String>, limit: Option<&String>) -> Result<Vec<Article>, ScrapeError> {
+    let limit = limit.and_then(|limit| limit.parse::<usize>().ok()).unwrap_or(DEFAULT_LIMIT);
+    let sitemaps = match (sitemap, url) {
+        (Some(sitemap), _) => vec![sitemap.clone()],
+        (None, Some(url)) => discover_sitemaps(fetcher, url).await?,
+        (None, None) => return Err(ScrapeError::InvalidInput("Either url or sitemap is required".to_string())),
+    };
+    let entries = read_sitemaps(fetcher, sitemaps, limit).await?;
+    let mut articles: Vec<Article> = entries.into_iter().map(entry_to_article).collect();
+    //Newest first
+    articles.sort_by(|a, b| b.time.cmp(&a.time));
+    match articles.len() {
+        0 => Err(ScrapeError::NoResults),
+        _ => Ok(articles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decodes_gzipped_sitemaps() {
+        let xml = "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\"><url><loc>https://example.com/a</loc></url></urlset>";
+        assert_eq!(decode_body(&gzip(xml.as_bytes())).unwrap(), xml);
+        assert_eq!(decode_body(xml.as_bytes()).unwrap(), xml);
+    }
+
+    #[test]
+    fn refuses_sitemaps_over_the_limit() {
+        let bomb = gzip(&vec![b' '; MAX_SITEMAP_BYTES as usize + 1]);
+        assert!(decode_body(&bomb).is_err());
+    }
+}
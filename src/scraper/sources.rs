@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+use crate::scraper::error::ScrapeError;
+use crate::scraper::fetch::Fetcher;
+use crate::scraper::scraper::{google_news_scraper, google_news_topic_scraper, Article};
+use crate::scraper::sitemap::sitemap_scraper;
+
+//Names of the sources available to /api/scrape and scheduled jobs
+pub const SOURCES: [&str; 2] = ["google_news", "sitemap"];
+
+pub fn is_known_source(source: &str) -> bool {
+    SOURCES.contains(&source)
+}
+
+//How many articles a source returns when no `limit=` is given. A Google News page holds far more cards than its top
+//stories
+pub fn default_limit(source: &str) -> Option<usize> {
+    match source {
+        "google_news" => Some(10),
+        _ => None,
+    }
+}
+
+//Runs a named source with its user supplied params
+pub async fn run_source(fetcher: &Fetcher, source: &str, params: &HashMap<String, String>) -> Result<Vec<Article>, ScrapeError> {
+    match source {
+        "google_news" => match params.get("topic") {
+            Some(topic) => google_news_topic_scraper(fetcher, topic).await,
+            None => google_news_scraper(fetcher).await
+        },
+        "sitemap" => sitemap_scraper(fetcher, params.get("url"), params.get("sitemap"), params.get("limit")).await,
+        _ => Err(ScrapeError::InvalidInput(format!("Unknown source: {}", source)))
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+
+use crate::scraper::language::detect_language;
+use crate::scraper::text::{is_cjk, split_sentences, stopwords, tokenize, Token};
+
+pub const DEFAULT_SUMMARY_SENTENCES: usize = 3;
+pub const MAX_SUMMARY_SENTENCES: usize = 10;
+//Only the start of very long bodies is ranked, the graph grows with the square of the sentence count
+const MAX_RANKED_SENTENCES: usize = 150;
+//Sentences with fewer content words are headings or fragments
+const MIN_SENTENCE_WORDS: usize = 4;
+const DAMPING: f64 = 0.85;
+const MAX_ITERATIONS: usize = 50;
+const CONVERGENCE: f64 = 0.0001;
+
+//Content words of a sentence. Chinese, Japanese and Korean are not written with spaces, so their runs of
+//characters are cut into overlapping pairs instead
+fn sentence_terms(sentence: &str, stopwords: &[&str]) -> HashSet<String> {
+    let mut terms = HashSet::new();
+    for word in tokenize(sentence).iter().map(Token::lower) {
+        if word.chars().any(is_cjk) {
+            let chars: Vec<char> = word.chars().collect();
+            if chars.len() == 1 {
+                terms.insert(word);
+            }
+            for pair in chars.windows(2) {
+                terms.insert(pair.iter().collect());
+            }
+        } else if word.chars().count() > 1 && !stopwords.contains(&word.as_str()) {
+            terms.insert(word);
+        }
+    }
+    terms
+}
+
+//TextRank sentence similarity, shared words normalised by the sentence lengths
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let shared = a.intersection(b).count();
+    let norm = (a.len() as f64).ln() + (b.len() as f64).ln();
+    if shared == 0 || norm <= 0.0 {
+        return 0.0;
+    }
+    shared as f64 / norm
+}
+
+//Weighted PageRank over the sentence similarity graph
+fn rank(terms: &[HashSet<String>]) -> Vec<f64> {
+    let count = terms.len();
+    let mut weights = vec![vec![0.0; count]; count];
+    for i in 0..count {
+        for j in (i + 1)..count {
+            let weight = similarity(&terms[i], &terms[j]);
+            weights[i][j] = weight;
+            weights[j][i] = weight;
+        }
+    }
+    let totals: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+    let mut scores = vec![1.0; count];
+    for _ in 0..MAX_ITERATIONS {
+        let next: Vec<f64> = (0..count)
+            .map(|i| {
+                let incoming: f64 = (0..count).filter(|j| totals[*j] > 0.0).map(|j| weights[j][i] / totals[j] * scores[j]).sum();
+                (1.0 - DAMPING) + DAMPING * incoming
+            })
+            .collect();
+        let change: f64 = next.iter().zip(&scores).map(|(new, old)| (new - old).abs()).sum();
+        scores = next;
+        if change < CONVERGENCE {
+            break;
+        }
+    }
+    scores
+}
+
+//Extractive summary: the `sentences` highest ranked sentences of the text, in the order they appear
+pub fn summarize(text: &str, sentences: usize) -> Option<String> {
+    let all: Vec<String> = split_sentences(text).into_iter().take(MAX_RANKED_SENTENCES).collect();
+    let stopwords = stopwords(detect_language(text).unwrap_or("en"));
+    let candidates: Vec<(usize, HashSet<String>)> = all
+        .iter()
+        .enumerate()
+        .map(|(index, sentence)| (index, sentence_terms(sentence, stopwords)))
+        .filter(|(_, terms)| terms.len() >= MIN_SENTENCE_WORDS)
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let terms: Vec<HashSet<String>> = candidates.iter().map(|(_, terms)| terms.clone()).collect();
+    let scores = rank(&terms);
+    let mut ranked: Vec<(usize, f64)> = candidates.iter().map(|(index, _)| *index).zip(scores).collect();
+    //Stable, so earlier sentences win ties
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    let mut picked: Vec<usize> = ranked.into_iter().take(sentences.max(1)).map(|(index, _)| index).collect();
+    picked.sort_unstable();
+    let mut summary = String::new();
+    for index in picked {
+        //Sentences ending in a full width stop are written without a space after them
+        if !summary.is_empty() && !summary.ends_with(['。', '！', '？']) {
+            summary.push(' ');
+        }
+        summary.push_str(&all[index]);
+    }
+    Some(summary)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_stopwords_of_the_language_and_pairs_cjk_characters() {
+        let terms = sentence_terms("Die Regierung und der Bundestag beraten", stopwords("de"));
+        assert!(terms.contains("regierung") && terms.contains("bundestag") && !terms.contains("und") && !terms.contains("der"));
+        let terms = sentence_terms("the government meets", stopwords("en"));
+        assert!(!terms.contains("the"));
+        let terms = sentence_terms("东京下雨", stopwords("en"));
+        let expected: HashSet<String> = vec!["东京", "京下", "下雨"].into_iter().map(String::from).collect();
+        assert_eq!(terms, expected);
+    }
+
+    #[test]
+    fn ranks_the_most_connected_sentence_highest() {
+        let terms: Vec<HashSet<String>> = vec![
+            vec!["rain", "storm", "city"],
+            vec!["rain", "storm", "city", "flood", "roads"],
+            vec!["flood", "roads", "closed"],
+            vec!["music", "festival", "tickets"],
+        ]
+        .into_iter()
+        .map(|words| words.into_iter().map(String::from).collect())
+        .collect();
+        let scores = rank(&terms);
+        assert!(scores[1] > scores[0] && scores[1] > scores[2]);
+        assert!(scores[3] < scores[0]);
+    }
+
+    #[test]
+    fn summaries_keep_document_order() {
+        let text = "Heavy rain flooded the city centre on Monday morning. \
+                    Music fans bought festival tickets online this week. \
+                    The flooded city centre roads stayed closed after heavy rain. \
+                    Officials said heavy rain and flooded roads could return on Friday.";
+        //The third sentence ranks highest but the first still comes before it
+        assert_eq!(summarize(text, 1).unwrap(), "The flooded city centre roads stayed closed after heavy rain.");
+        assert_eq!(
+            summarize(text, 2).unwrap(),
+            "Heavy rain flooded the city centre on Monday morning. The flooded city centre roads stayed closed after heavy rain."
+        );
+        assert_eq!(summarize("Too short. Also short.", 3), None);
+    }
+
+    #[test]
+    fn joins_full_width_sentences_without_spaces() {
+        let text = "今天东京下了大雨，很多道路被淹没。东京的道路今天被大雨淹没了。音乐节的门票已经卖完了。";
+        let summary = summarize(text, 2).unwrap();
+        assert!(!summary.contains(' '));
+        assert!(summary.starts_with("今天东京下了大雨"));
+    }
+}
\ No newline at end of file
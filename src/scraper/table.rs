@@ -0,0 +1,258 @@
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Html, Node};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::scraper::recipe::{Match, NodeSelector, Record};
+use crate::scraper::transform::collapse_whitespace;
+use crate::scraper::xpath::Evaluator;
+
+//Spans are capped so a hostile rowspan cannot blow up the grid
+const MAX_SPAN: usize = 100;
+
+#[derive(Serialize, Debug)]
+pub struct Table {
+    //Position among the tables that were extracted
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    pub headers: Vec<String>,
+    pub rows: Vec<Record>,
+}
+
+struct Cell {
+    text: String,
+    header: bool,
+}
+
+fn is_element(node: NodeRef<Node>, names: &[&str]) -> bool {
+    matches!(node.value(), Node::Element(element) if names.contains(&element.name()))
+}
+
+//Text of a cell, leaving out tables nested inside it since those are extracted on their own
+fn cell_text(cell: ElementRef) -> String {
+    let mut text = String::new();
+    let mut stack: Vec<NodeRef<Node>> = cell.children().collect();
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        match node.value() {
+            Node::Text(value) => {
+                text.push_str(value);
+                text.push(' ');
+            }
+            Node::Element(element) if element.name() == "table" => {}
+            Node::Element(element) if element.name() == "br" => text.push(' '),
+            Node::Element(_) => {
+                let mut children: Vec<NodeRef<Node>> = node.children().collect();
+                children.reverse();
+                stack.extend(children);
+            }
+            _ => {}
+        }
+    }
+    collapse_whitespace(&text)
+}
+
+//Rows of this table in order, skipping the rows of nested tables. `thead` rows are flagged as header rows
+fn table_rows(table: ElementRef) -> Vec<(ElementRef, bool)> {
+    let mut rows = vec![];
+    for child in table.children() {
+        if is_element(child, &["tr"]) {
+            rows.extend(ElementRef::wrap(child).map(|row| (row, false)));
+        } else if is_element(child, &["thead", "tbody", "tfoot"]) {
+            let in_head = is_element(child, &["thead"]);
+            for row in child.children().filter(|row| is_element(*row, &["tr"])) {
+                rows.extend(ElementRef::wrap(row).map(|row| (row, in_head)));
+            }
+        }
+    }
+    rows
+}
+
+fn span(cell: ElementRef, attr: &str) -> usize {
+    cell.value()
+        .attr(attr)
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(1)
+        .clamp(1, MAX_SPAN)
+}
+
+//Lays the cells out on a grid, copying spanning cells into every slot they cover
+fn build_grid(rows: &[(ElementRef, bool)]) -> Vec<Vec<Option<Cell>>> {
+    let mut grid: Vec<Vec<Option<Cell>>> = (0..rows.len()).map(|_| vec![]).collect();
+    for (row_index, (row, _)) in rows.iter().enumerate() {
+        let mut column = 0;
+        for cell in row.children().filter(|cell| is_element(*cell, &["td", "th"])).filter_map(ElementRef::wrap) {
+            //Skip slots already taken by a rowspan from above
+            while grid[row_index].get(column).is_some_and(|slot| slot.is_some()) {
+                column += 1;
+            }
+            let text = cell_text(cell);
+            let header = cell.value().name() == "th";
+            let last_row = (row_index + span(cell, "rowspan")).min(rows.len());
+            for grid_row in grid.iter_mut().take(last_row).skip(row_index) {
+                for offset in 0..span(cell, "colspan") {
+                    if grid_row.len() <= column + offset {
+                        grid_row.resize_with(column + offset + 1, || None);
+                    }
+                    grid_row[column + offset] = Some(Cell { text: text.clone(), header });
+                }
+            }
+            column += span(cell, "colspan");
+        }
+    }
+    grid
+}
+
+//Header rows are the `thead` rows, or the first row when it only has `th` cells
+fn header_row_count(rows: &[(ElementRef, bool)], grid: &[Vec<Option<Cell>>]) -> usize {
+    let head = rows.iter().take_while(|(_, in_head)| *in_head).count();
+    if head > 0 {
+        return head;
+    }
+    match grid.first() {
+        Some(first) if !first.is_empty() && first.iter().all(|cell| cell.as_ref().is_some_and(|cell| cell.header)) => 1,
+        _ => 0,
+    }
+}
+
+//Combines stacked header rows per column ("Sales / Q1") and makes the names unique
+fn column_names(header_rows: &[Vec<Option<Cell>>], width: usize) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for column in 0..width {
+        let mut parts: Vec<&str> = vec![];
+        for row in header_rows {
+            if let Some(Some(cell)) = row.get(column) {
+                if !cell.text.is_empty() && parts.last() != Some(&cell.text.as_str()) {
+                    parts.push(&cell.text);
+                }
+            }
+        }
+        let mut name = match parts.is_empty() {
+            true => format!("column_{}", column + 1),
+            false => parts.join(" / "),
+        };
+        if names.contains(&name) {
+            let mut suffix = 2;
+            while names.contains(&format!("{}_{}", name, suffix)) {
+                suffix += 1;
+            }
+            name = format!("{}_{}", name, suffix);
+        }
+        names.push(name);
+    }
+    names
+}
+
+pub fn parse_table(table: ElementRef, index: usize) -> Table {
+    let rows = table_rows(table);
+    let grid = build_grid(&rows);
+    let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+    let header_rows = header_row_count(&rows, &grid);
+    let headers = column_names(&grid[..header_rows], width);
+    let records = grid[header_rows..]
+        .iter()
+        .filter(|row| row.iter().any(|cell| cell.as_ref().is_some_and(|cell| !cell.text.is_empty())))
+        .map(|row| {
+            let mut record = Record::new();
+            for (column, header) in headers.iter().enumerate() {
+                let value = match row.get(column) {
+                    Some(Some(cell)) => Value::String(cell.text.clone()),
+                    _ => Value::Null,
+                };
+                record.insert(header.clone(), value);
+            }
+            record
+        })
+        .collect();
+    let caption = table
+        .children()
+        .find(|child| is_element(*child, &["caption"]))
+        .and_then(ElementRef::wrap)
+        .map(cell_text);
+    Table { index, caption, headers, rows: records }
+}
+
+//Extracts the tables matched by `selector`, or every table in the document without one
+pub fn extract_tables(document: &Html, selector: Option<&NodeSelector>) -> Result<Vec<Table>, String> {
+    let tables: Vec<ElementRef> = match selector {
+        Some(selector) => {
+            let evaluator = Evaluator::new(document);
+            selector
+                .select_document(&evaluator, document)?
+                .into_iter()
+                .filter_map(|found| match found {
+                    Match::Element(element) if element.value().name() == "table" => Some(element),
+                    _ => None,
+                })
+                .collect()
+        }
+        None => document.tree.root().descendants().filter(|node| is_element(*node, &["table"])).filter_map(ElementRef::wrap).collect(),
+    };
+    Ok(tables.into_iter().enumerate().map(|(index, table)| parse_table(table, index)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tables(html: &str, selector: Option<&str>) -> Vec<Table> {
+        let document = Html::parse_document(html);
+        let selector = selector.map(|selector| NodeSelector::parse(selector).unwrap());
+        extract_tables(&document, selector.as_ref()).unwrap()
+    }
+
+    fn row(table: &Table, index: usize) -> Value {
+        Value::Object(table.rows[index].clone())
+    }
+
+    #[test]
+    fn expands_row_and_column_spans() {
+        let html = r#"<table>
+            <tr><th>Region</th><th>City</th><th>Sales</th></tr>
+            <tr><td rowspan="2">North</td><td>Oslo</td><td>10</td></tr>
+            <tr><td>Bergen</td><td>20</td></tr>
+            <tr><td colspan="2">Total</td><td>30</td></tr>
+        </table>"#;
+        let table = &tables(html, None)[0];
+        assert_eq!(table.headers, vec!["Region", "City", "Sales"]);
+        assert_eq!(row(table, 1), json!({ "Region": "North", "City": "Bergen", "Sales": "20" }));
+        assert_eq!(row(table, 2), json!({ "Region": "Total", "City": "Total", "Sales": "30" }));
+    }
+
+    #[test]
+    fn combines_stacked_headers() {
+        let html = r#"<table>
+            <caption> Quarterly  sales </caption>
+            <thead>
+                <tr><th rowspan="2">Name</th><th colspan="2">Sales</th><th>Name</th></tr>
+                <tr><th>Q1</th><th>Q2</th><th></th></tr>
+            </thead>
+            <tbody><tr><td>Ann</td><td>1</td><td>2</td></tr></tbody>
+        </table>"#;
+        let table = &tables(html, None)[0];
+        assert_eq!(table.caption.as_deref(), Some("Quarterly sales"));
+        assert_eq!(table.headers, vec!["Name", "Sales / Q1", "Sales / Q2", "Name_2"]);
+        assert_eq!(row(table, 0), json!({ "Name": "Ann", "Sales / Q1": "1", "Sales / Q2": "2", "Name_2": null }));
+    }
+
+    #[test]
+    fn names_columns_without_headers_and_caps_spans() {
+        let html = r#"<table><tr><td rowspan="100000">a</td><td>b</td></tr><tr><td>c</td></tr></table>"#;
+        let table = &tables(html, None)[0];
+        assert_eq!(table.headers, vec!["column_1", "column_2"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(row(table, 1), json!({ "column_1": "a", "column_2": "c" }));
+    }
+
+    #[test]
+    fn keeps_nested_tables_apart() {
+        let html = r#"<div><table id="outer"><tr><th>Cell</th></tr><tr><td>Text <table><tr><td>inner</td></tr></table></td></tr></table></div>"#;
+        assert_eq!(tables(html, None).len(), 2);
+        let outer = tables(html, Some("#outer"));
+        assert_eq!(outer.len(), 1);
+        assert_eq!(row(&outer[0], 0), json!({ "Cell": "Text" }));
+    }
+}
@@ -0,0 +1,199 @@
+//Common English words that carry no meaning on their own
+const STOPWORDS: [&str; 124] = [
+    "a", "about", "above", "after", "again", "against", "all", "am", "an", "and", "any", "are", "as", "at", "be", "because",
+    "been", "before", "being", "below", "between", "both", "but", "by", "can", "could", "did", "do", "does", "doing", "down",
+    "during", "each", "few", "for", "from", "further", "had", "has", "have", "having", "he", "her", "here", "hers", "him",
+    "his", "how", "i", "if", "in", "into", "is", "it", "its", "itself", "just", "me", "more", "most", "my", "no", "nor",
+    "not", "now", "of", "off", "on", "once", "only", "or", "other", "our", "ours", "out", "over", "own", "said", "same",
+    "says", "she", "should", "so", "some", "such", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "those", "through", "to", "too", "under", "until", "up", "very", "was", "we", "were", "what", "when", "where",
+    "which", "while", "who", "whom", "why", "will", "with", "would", "you", "your", "yours", "also", "may", "amid",
+    "via",
+];
+
+//Stopwords of other languages for summaries, short lists of their most frequent function words
+const FOREIGN_STOPWORDS: [(&str, &[&str]); 6] = [
+    ("de", &["der", "die", "das", "und", "in", "ist", "nicht", "ein", "eine", "einen", "zu", "den", "dem", "des", "mit", "sich", "auf", "für", "von", "im", "auch", "als", "an", "es", "sie", "er", "wir", "ich", "wird", "werden", "hat", "haben", "bei", "nach", "aus", "noch", "wie", "über", "oder", "aber", "vor", "nur", "sind", "war", "dass"]),
+    ("es", &["el", "la", "los", "las", "de", "del", "y", "en", "que", "un", "una", "por", "con", "para", "no", "se", "su", "sus", "al", "lo", "como", "más", "pero", "es", "son", "ha", "han", "fue", "este", "esta", "entre", "sobre", "también", "sin", "ya", "muy", "o", "le", "les"]),
+    ("fr", &["le", "la", "les", "de", "des", "du", "et", "en", "un", "une", "que", "qui", "dans", "pour", "pas", "par", "sur", "au", "aux", "avec", "ce", "cette", "il", "elle", "ils", "se", "sa", "son", "ses", "est", "sont", "ont", "a", "été", "plus", "ne", "mais", "ou", "comme", "leur"]),
+    ("it", &["il", "lo", "la", "i", "gli", "le", "di", "del", "della", "e", "in", "un", "una", "che", "per", "con", "non", "su", "da", "dal", "al", "alla", "come", "più", "ma", "sono", "è", "ha", "hanno", "anche", "si", "questo", "questa", "tra", "nel", "nella"]),
+    ("nl", &["de", "het", "een", "en", "van", "in", "is", "op", "te", "dat", "die", "met", "voor", "niet", "aan", "er", "zijn", "als", "maar", "om", "ook", "bij", "of", "naar", "uit", "dan", "nog", "wordt", "worden", "heeft", "hebben", "door", "over", "wel"]),
+    ("pt", &["o", "a", "os", "as", "de", "do", "da", "dos", "das", "e", "em", "no", "na", "um", "uma", "que", "para", "com", "não", "por", "se", "sua", "seu", "mais", "como", "mas", "foi", "são", "ao", "aos", "ele", "ela", "também", "entre", "sobre", "já"]),
+];
+
+//Honorifics and abbreviations that end in a dot without ending the sentence
+const ABBREVIATIONS: [&str; 24] = [
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "etc", "inc", "ltd", "co", "corp", "gen", "gov", "sen", "rep",
+    "no", "u.s", "u.k", "e.g", "i.e", "approx",
+];
+
+pub fn is_stopword(word: &str) -> bool {
+    STOPWORDS.contains(&word)
+}
+
+pub fn stopwords(language: &str) -> &'static [&'static str] {
+    FOREIGN_STOPWORDS.iter().find(|(code, _)| *code == language).map_or(&STOPWORDS[..], |(_, words)| words)
+}
+
+//Chinese, Japanese and Korean characters, which are not written with spaces between words
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x3040..=0x30ff | 0x3400..=0x4dbf | 0x4e00..=0x9fff | 0xac00..=0xd7af)
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '。' | '！' | '？' | '।' | '؟')
+}
+
+//Full width and Indic terminators end a sentence even without a following space
+fn ends_without_space(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '।')
+}
+
+//Whether the dot after `word` belongs to an abbreviation or an initial rather than ending the sentence
+fn is_abbreviation(word: &str) -> bool {
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+    //Single letters and dotted initials such as "D.C"
+    let initials = word.split('.').all(|part| part.chars().count() == 1 && part.chars().all(char::is_alphabetic));
+    ABBREVIATIONS.contains(&word.as_str()) || initials
+}
+
+//Splits text into sentences at terminal punctuation followed by a space and at blank lines, keeping closing quotes and
+//brackets with the sentence they close
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    let mut current = String::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        current.push(c);
+        index += 1;
+        let boundary = if c == '\n' {
+            chars.get(index) == Some(&'\n')
+        } else if is_sentence_end(c) {
+            while index < chars.len() && matches!(chars[index], '"' | '\'' | '”' | '’' | ')' | '»' | '」' | '』') {
+                current.push(chars[index]);
+                index += 1;
+            }
+            let last_word = current.trim_end_matches(|c: char| !c.is_alphanumeric()).rsplit(char::is_whitespace).next().unwrap_or("");
+            let spaced = chars.get(index).is_none_or(|next| next.is_whitespace());
+            //A sentence does not go on in lower case, e.g. `"Is it done?" she asked`
+            let continues = chars[index..].iter().find(|next| !next.is_whitespace()).is_some_and(|next| next.is_lowercase());
+            ends_without_space(c) || (spaced && !continues && !(c == '.' && is_abbreviation(last_word)))
+        } else {
+            false
+        };
+        if boundary {
+            push_sentence(&mut sentences, &current);
+            current.clear();
+        }
+    }
+    push_sentence(&mut sentences, &current);
+    sentences
+}
+
+fn push_sentence(sentences: &mut Vec<String>, text: &str) {
+    let sentence = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+}
+
+//A word of a text with what separated it from the word before
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    //Punctuation came before the word, so no phrase runs across it
+    pub phrase_start: bool,
+    //The word starts a sentence, where capitals say nothing about names
+    pub sentence_start: bool,
+}
+
+impl Token {
+    pub fn lower(&self) -> String {
+        self.text.to_lowercase()
+    }
+
+    pub fn is_capitalized(&self) -> bool {
+        self.text.chars().next().is_some_and(|c| c.is_uppercase())
+    }
+}
+
+//Apostrophes, hyphens and dots are part of a word when a letter or digit follows them, e.g. "don't", "covid-19", "U.S"
+fn joins_word(c: char, next: Option<char>) -> bool {
+    matches!(c, '\'' | '’' | '-' | '.') && next.is_some_and(|next| next.is_alphanumeric())
+}
+
+//Splits a text into words, dropping punctuation but remembering where it was
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let (mut phrase_start, mut sentence_start) = (true, true);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || (!word.is_empty() && joins_word(c, chars.peek().copied())) {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(Token { text: std::mem::take(&mut word), phrase_start, sentence_start });
+            phrase_start = false;
+            sentence_start = false;
+        }
+        if !c.is_whitespace() {
+            phrase_start = true;
+        }
+        if matches!(c, '.' | '!' | '?' | '。' | '！' | '？') {
+            sentence_start = true;
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(Token { text: word, phrase_start, sentence_start });
+    }
+    tokens
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_abbreviations_and_initials_inside_sentences() {
+        assert_eq!(
+            split_sentences("Dr. Smith met U.S. officials in Washington, D.C. on Monday. They agreed on a plan."),
+            vec!["Dr. Smith met U.S. officials in Washington, D.C. on Monday.", "They agreed on a plan."]
+        );
+        assert_eq!(split_sentences("It costs approx. 5 dollars. Cheap!"), vec!["It costs approx. 5 dollars.", "Cheap!"]);
+    }
+
+    #[test]
+    fn splits_at_quotes_blank_lines_and_full_width_stops() {
+        assert_eq!(split_sentences("\"Is it done?\" she asked. \"Yes.\" He left."), vec!["\"Is it done?\" she asked.", "\"Yes.\"", "He left."]);
+        assert_eq!(split_sentences("A heading\n\nThe text"), vec!["A heading", "The text"]);
+        assert_eq!(split_sentences("今天下雨。明天是晴天！后天呢？"), vec!["今天下雨。", "明天是晴天！", "后天呢？"]);
+        assert_eq!(split_sentences("यह पहला है। यह दूसरा है।"), vec!["यह पहला है।", "यह दूसरा है।"]);
+    }
+
+    #[test]
+    fn tokenizes_words_and_marks_breaks() {
+        let tokens = tokenize("Don't panic: covid-19 hit the U.S. Then it eased");
+        let words: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+        assert_eq!(words, vec!["Don't", "panic", "covid-19", "hit", "the", "U.S", "Then", "it", "eased"]);
+        assert!(tokens[0].phrase_start && tokens[0].sentence_start);
+        assert!(!tokens[1].phrase_start);
+        assert!(tokens[2].phrase_start && !tokens[2].sentence_start);
+        assert!(tokens[6].sentence_start);
+        assert!(tokens[0].is_capitalized() && !tokens[1].is_capitalized());
+    }
+
+    #[test]
+    fn picks_stopwords_by_language() {
+        assert!(stopwords("de").contains(&"und"));
+        assert!(stopwords("fr").contains(&"les"));
+        assert!(!stopwords("de").contains(&"the"));
+        //Unknown languages fall back to English
+        assert!(stopwords("sv").contains(&"the"));
+        assert!(is_stopword("the") && !is_stopword("und"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,383 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Number, Value};
+
+//Formats tried, in order, when a date transform has no explicit format
+const DATE_TIME_FORMATS: [&str; 4] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%d/%m/%Y %H:%M"];
+const DATE_FORMATS: [&str; 7] = ["%Y-%m-%d", "%B %d, %Y", "%b %d, %Y", "%d %B %Y", "%d %b %Y", "%Y/%m/%d", "%d.%m.%Y"];
+
+//One step of a field's transform chain, e.g. `{ "op": "regex", "pattern": "(\\d+)" }`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Transform {
+    //Trims the value and collapses runs of whitespace into single spaces
+    Trim,
+    Lowercase,
+    //Keeps a capture group of the first match, the first group by default, or null when nothing matches
+    Regex {
+        pattern: String,
+        #[serde(default)]
+        group: Option<usize>,
+    },
+    //Replaces every match, `with` may refer to groups as `$1`
+    Replace {
+        pattern: String,
+        #[serde(default)]
+        with: String,
+    },
+    //Resolves a relative link against the page it was found on
+    AbsoluteUrl,
+    //Parses the first number in the value, `decimal` is the decimal separator ("." by default)
+    Number {
+        #[serde(default)]
+        decimal: Option<String>,
+    },
+    //Like number but returns `{ "amount": ..., "currency": ... }` with an ISO code when one can be recognised
+    Currency {
+        #[serde(default)]
+        decimal: Option<String>,
+    },
+    //Parses a date into RFC 3339 UTC, with a chrono `format` or by trying common formats
+    Date {
+        #[serde(default)]
+        format: Option<String>,
+    },
+    //Converts the element's HTML into markdown
+    Markdown,
+    //Replaces a null or empty value
+    Default { value: Value },
+}
+
+enum Step {
+    Trim,
+    Lowercase,
+    Regex(Regex, Option<usize>),
+    Replace(Regex, String),
+    AbsoluteUrl,
+    Number(char),
+    Currency(char),
+    Date(Option<String>),
+    Markdown,
+    Default(Value),
+}
+
+//A field's transforms with their patterns compiled, built once per extraction
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))
+}
+
+fn decimal_separator(decimal: &Option<String>) -> Result<char, String> {
+    match decimal.as_deref() {
+        None | Some(".") => Ok('.'),
+        Some(",") => Ok(','),
+        Some(other) => Err(format!("Unsupported decimal separator '{}'", other)),
+    }
+}
+
+impl Pipeline {
+    pub fn compile(transforms: &[Transform]) -> Result<Self, String> {
+        let mut steps = vec![];
+        for transform in transforms {
+            steps.push(match transform {
+                Transform::Trim => Step::Trim,
+                Transform::Lowercase => Step::Lowercase,
+                Transform::Regex { pattern, group } => Step::Regex(compile_regex(pattern)?, *group),
+                Transform::Replace { pattern, with } => Step::Replace(compile_regex(pattern)?, with.clone()),
+                Transform::AbsoluteUrl => Step::AbsoluteUrl,
+                Transform::Number { decimal } => Step::Number(decimal_separator(decimal)?),
+                Transform::Currency { decimal } => Step::Currency(decimal_separator(decimal)?),
+                Transform::Date { format } => Step::Date(format.clone()),
+                Transform::Markdown => Step::Markdown,
+                Transform::Default { value } => Step::Default(value.clone()),
+            });
+        }
+        Ok(Pipeline { steps })
+    }
+
+    //Markdown needs the element's HTML rather than its text
+    pub fn wants_html(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step, Step::Markdown))
+    }
+
+    pub fn apply(&self, mut value: Value, page_url: &Url) -> Value {
+        for step in &self.steps {
+            value = match (step, value) {
+                (Step::Default(default), Value::Null) => default.clone(),
+                (Step::Default(default), Value::String(text)) if text.is_empty() => default.clone(),
+                (_, Value::Null) => Value::Null,
+                (step, Value::String(text)) => apply_step(step, &text, page_url),
+                //Steps after a number or currency only see non text values, which pass through
+                (_, other) => other,
+            };
+        }
+        value
+    }
+}
+
+fn apply_step(step: &Step, text: &str, page_url: &Url) -> Value {
+    let result = match step {
+        Step::Trim => Some(Value::String(collapse_whitespace(text))),
+        Step::Lowercase => Some(Value::String(text.to_lowercase())),
+        Step::Regex(pattern, group) => pattern.captures(text).and_then(|captures| {
+            let group = group.unwrap_or(if captures.len() > 1 { 1 } else { 0 });
+            captures.get(group).map(|found| Value::String(found.as_str().to_string()))
+        }),
+        Step::Replace(pattern, with) => Some(Value::String(pattern.replace_all(text, with.as_str()).into_owned())),
+        Step::AbsoluteUrl => page_url.join(text.trim()).ok().map(|url| Value::String(url.to_string())),
+        Step::Number(decimal) => parse_number(text, *decimal).and_then(Number::from_f64).map(Value::Number),
+        Step::Currency(decimal) => parse_number(text, *decimal).map(|amount| json!({ "amount": amount, "currency": currency_code(text) })),
+        Step::Date(format) => parse_date(text, format.as_deref()).map(Value::String),
+        Step::Markdown => Some(Value::String(html_to_markdown(text, page_url))),
+        Step::Default(_) => Some(Value::String(text.to_string())),
+    };
+    result.unwrap_or(Value::Null)
+}
+
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+//Finds the first number, dropping thousands separators ("1,234.50", "1.234,50", "1 234")
+fn parse_number(text: &str, decimal: char) -> Option<f64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let negative = text[..start].trim_end().ends_with('-');
+    let mut number = String::new();
+    for c in text[start..].chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            c if c == decimal => number.push('.'),
+            ',' | '.' | '\'' | ' ' | '\u{a0}' | '\u{202f}' => {}
+            _ => break,
+        }
+    }
+    let number = number.trim_end_matches('.');
+    let value: f64 = number.parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+fn currency_code(text: &str) -> Option<String> {
+    let symbols = [("US$", "USD"), ("$", "USD"), ("€", "EUR"), ("£", "GBP"), ("¥", "JPY"), ("₹", "INR"), ("₩", "KRW"), ("₽", "RUB")];
+    for (symbol, code) in symbols {
+        if text.contains(symbol) {
+            return Some(code.to_string());
+        }
+    }
+    text.split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()))
+        .map(|code| code.to_string())
+}
+
+//Normalises a date to RFC 3339 in UTC, `format` is a chrono format string when the date is not in a common one
+pub fn parse_date(text: &str, format: Option<&str>) -> Option<String> {
+    let text = text.trim();
+    let to_rfc3339 = |date: DateTime<Utc>| date.to_rfc3339_opts(SecondsFormat::Secs, true);
+    if let Some(format) = format {
+        if let Ok(date) = DateTime::parse_from_str(text, format) {
+            return Some(to_rfc3339(date.with_timezone(&Utc)));
+        }
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(to_rfc3339(date.and_utc()));
+        }
+        return NaiveDate::parse_from_str(text, format).ok().and_then(|date| date.and_hms_opt(0, 0, 0)).map(|date| to_rfc3339(date.and_utc()));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(to_rfc3339(date.with_timezone(&Utc)));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(text) {
+        return Some(to_rfc3339(date.with_timezone(&Utc)));
+    }
+    for format in DATE_TIME_FORMATS {
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(to_rfc3339(date.and_utc()));
+        }
+    }
+    for format in DATE_FORMATS {
+        if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+            return date.and_hms_opt(0, 0, 0).map(|date| to_rfc3339(date.and_utc()));
+        }
+    }
+    //Unix timestamps in seconds or milliseconds since 1973. Shorter numbers, like a bare year, are not dates
+    if text.bytes().all(|b| b.is_ascii_digit()) && matches!(text.len(), 9 | 10 | 12 | 13) {
+        let timestamp: i64 = text.parse().ok()?;
+        let date = if text.len() > 10 { DateTime::from_timestamp_millis(timestamp) } else { DateTime::from_timestamp(timestamp, 0) };
+        return date.map(to_rfc3339);
+    }
+    None
+}
+
+pub fn html_to_markdown(html: &str, page_url: &Url) -> String {
+    let fragment = Html::parse_fragment(html);
+    let mut markdown = String::new();
+    write_markdown(fragment.root_element(), page_url, &mut markdown);
+    //Block elements each add blank lines, keep at most one between paragraphs
+    let mut cleaned = String::new();
+    let mut newlines = 0;
+    for c in markdown.trim().chars() {
+        if c == '\n' {
+            newlines += 1;
+            if newlines > 2 {
+                continue;
+            }
+        } else {
+            newlines = 0;
+        }
+        cleaned.push(c);
+    }
+    cleaned
+}
+
+fn write_children(element: ElementRef, page_url: &Url, out: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                let collapsed = collapse_whitespace(text);
+                if collapsed.is_empty() {
+                    //Whitespace between inline elements still separates words
+                    if !text.is_empty() && !out.is_empty() && !out.ends_with(char::is_whitespace) {
+                        out.push(' ');
+                    }
+                    continue;
+                }
+                if text.starts_with(char::is_whitespace) && !out.ends_with(char::is_whitespace) && !out.is_empty() {
+                    out.push(' ');
+                }
+                out.push_str(&collapsed);
+                if text.ends_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+            }
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    write_markdown(child, page_url, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn inline_markdown(element: ElementRef, page_url: &Url) -> String {
+    let mut inner = String::new();
+    write_children(element, page_url, &mut inner);
+    inner.trim().to_string()
+}
+
+fn resolve(link: &str, page_url: &Url) -> String {
+    page_url.join(link).map(|url| url.to_string()).unwrap_or_else(|_| link.to_string())
+}
+
+fn write_markdown(element: ElementRef, page_url: &Url, out: &mut String) {
+    let name = element.value().name();
+    match name {
+        "script" | "style" | "noscript" | "template" => {}
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            out.push_str(&format!("\n\n{} {}\n\n", "#".repeat(level), inline_markdown(element, page_url)));
+        }
+        "p" | "div" | "section" | "article" | "header" | "footer" | "figure" => {
+            out.push_str("\n\n");
+            write_children(element, page_url, out);
+            out.push_str("\n\n");
+        }
+        "br" => out.push('\n'),
+        "hr" => out.push_str("\n\n---\n\n"),
+        "strong" | "b" => out.push_str(&format!("**{}**", inline_markdown(element, page_url))),
+        "em" | "i" => out.push_str(&format!("*{}*", inline_markdown(element, page_url))),
+        "code" => out.push_str(&format!("`{}`", element.text().collect::<String>())),
+        "pre" => out.push_str(&format!("\n\n```\n{}\n```\n\n", element.text().collect::<String>().trim_end())),
+        "a" => {
+            let text = inline_markdown(element, page_url);
+            match element.value().attr("href") {
+                Some(href) => out.push_str(&format!("[{}]({})", text, resolve(href, page_url))),
+                None => out.push_str(&text),
+            }
+        }
+        "img" => {
+            if let Some(src) = element.value().attr("src") {
+                out.push_str(&format!("![{}]({})", element.value().attr("alt").unwrap_or(""), resolve(src, page_url)));
+            }
+        }
+        "ul" | "ol" => {
+            out.push_str("\n\n");
+            let items = element.children().filter_map(ElementRef::wrap).filter(|child| child.value().name() == "li");
+            for (index, item) in items.enumerate() {
+                let marker = if name == "ol" { format!("{}.", index + 1) } else { "-".to_string() };
+                out.push_str(&format!("{} {}\n", marker, inline_markdown(item, page_url)));
+            }
+            out.push('\n');
+        }
+        "blockquote" => {
+            let quoted = html_to_markdown(&element.inner_html(), page_url);
+            out.push_str("\n\n");
+            for line in quoted.lines() {
+                out.push_str(&format!("> {}\n", line));
+            }
+            out.push('\n');
+        }
+        _ => write_children(element, page_url, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(transforms: Value, value: Value) -> Value {
+        let transforms: Vec<Transform> = serde_json::from_value(transforms).unwrap();
+        let page_url = Url::parse("https://example.com/shop/item").unwrap();
+        Pipeline::compile(&transforms).unwrap().apply(value, &page_url)
+    }
+
+    #[test]
+    fn chains_text_steps() {
+        let transforms = json!([{ "op": "trim" }, { "op": "lowercase" }, { "op": "replace", "pattern": "(\\w+) (\\w+)", "with": "$2 $1" }]);
+        assert_eq!(run(transforms, json!("  Hello \n World ")), json!("world hello"));
+        assert_eq!(run(json!([{ "op": "regex", "pattern": "SKU-(\\d+)" }]), json!("Item SKU-042 in stock")), json!("042"));
+        assert_eq!(run(json!([{ "op": "regex", "pattern": "\\d+", "group": 0 }]), json!("no digits")), Value::Null);
+        assert_eq!(run(json!([{ "op": "absolute_url" }]), json!("../cart?id=1")), json!("https://example.com/cart?id=1"));
+    }
+
+    #[test]
+    fn parses_numbers_and_currencies() {
+        assert_eq!(run(json!([{ "op": "number" }]), json!("Price: 1,234.50")), json!(1234.5));
+        assert_eq!(run(json!([{ "op": "number", "decimal": "," }]), json!("1.234,50 €")), json!(1234.5));
+        assert_eq!(run(json!([{ "op": "number" }]), json!("- 7 items")), json!(-7.0));
+        assert_eq!(run(json!([{ "op": "currency" }]), json!("£12.99")), json!({ "amount": 12.99, "currency": "GBP" }));
+        assert_eq!(run(json!([{ "op": "currency" }]), json!("12.99 CHF")), json!({ "amount": 12.99, "currency": "CHF" }));
+        assert!(Pipeline::compile(&[Transform::Number { decimal: Some(";".to_string()) }]).is_err());
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("2024-03-05T10:00:00+02:00", None).as_deref(), Some("2024-03-05T08:00:00Z"));
+        assert_eq!(parse_date("March 5, 2024", None).as_deref(), Some("2024-03-05T00:00:00Z"));
+        assert_eq!(parse_date("05.03.2024", None).as_deref(), Some("2024-03-05T00:00:00Z"));
+        assert_eq!(parse_date("1709632800", None).as_deref(), Some("2024-03-05T10:00:00Z"));
+        assert_eq!(parse_date("1709632800000", None).as_deref(), Some("2024-03-05T10:00:00Z"));
+        assert_eq!(parse_date("05/03/24", Some("%d/%m/%y")).as_deref(), Some("2024-03-05T00:00:00Z"));
+        assert_eq!(parse_date("2024", None), None);
+        assert_eq!(parse_date("not a date", None), None);
+    }
+
+    #[test]
+    fn defaults_replace_missing_values() {
+        let transforms = json!([{ "op": "regex", "pattern": "(\\d+)" }, { "op": "default", "value": 0 }]);
+        assert_eq!(run(transforms.clone(), json!("none")), json!(0));
+        assert_eq!(run(transforms, json!("12 left")), json!("12"));
+        assert_eq!(run(json!([{ "op": "trim" }, { "op": "default", "value": "n/a" }]), json!("   ")), json!("n/a"));
+    }
+
+    #[test]
+    fn converts_html_to_markdown() {
+        let page_url = Url::parse("https://example.com/blog/").unwrap();
+        let html = "<h2>Title</h2><p>Some <strong>bold</strong> and <a href=\"post\">a link</a>.</p><ul><li>One</li><li>Two</li></ul>";
+        assert_eq!(html_to_markdown(html, &page_url), "## Title\n\nSome **bold** and [a link](https://example.com/blog/post).\n\n- One\n- Two");
+    }
+}
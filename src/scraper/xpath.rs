@@ -0,0 +1,1163 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Html, Node};
+
+//XPath 1.0 over the tree built by `scraper`. Element and attribute names are matched case-insensitively since
+//HTML parsing lowercases them, namespace prefixes are ignored and variables are not supported.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    ColonColon,
+    Slash,
+    DoubleSlash,
+    Pipe,
+    Plus,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Star,
+    Literal(String),
+    Number(f64),
+    //A name test or keyword, with its namespace prefix removed
+    Name(String),
+    //`prefix:*`
+    PrefixStar,
+    Variable(String),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, length) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::LeftParen, 1),
+            ')' => (Token::RightParen, 1),
+            '[' => (Token::LeftBracket, 1),
+            ']' => (Token::RightBracket, 1),
+            '@' => (Token::At, 1),
+            ',' => (Token::Comma, 1),
+            '|' => (Token::Pipe, 1),
+            '+' => (Token::Plus, 1),
+            '-' => (Token::Minus, 1),
+            '=' => (Token::Equal, 1),
+            '*' => (Token::Star, 1),
+            ':' if next == Some(':') => (Token::ColonColon, 2),
+            '/' if next == Some('/') => (Token::DoubleSlash, 2),
+            '/' => (Token::Slash, 1),
+            '!' if next == Some('=') => (Token::NotEqual, 2),
+            '<' if next == Some('=') => (Token::LessEqual, 2),
+            '<' => (Token::Less, 1),
+            '>' if next == Some('=') => (Token::GreaterEqual, 2),
+            '>' => (Token::Greater, 1),
+            '.' if next == Some('.') => (Token::DotDot, 2),
+            '.' if !next.is_some_and(|n| n.is_ascii_digit()) => (Token::Dot, 1),
+            '"' | '\'' => {
+                let end = chars[i + 1..].iter().position(|&d| d == c).ok_or_else(|| "Unterminated string literal".to_string())?;
+                (Token::Literal(chars[i + 1..i + 1 + end].iter().collect()), end + 2)
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = i;
+                while end < chars.len() && (chars[end].is_ascii_digit() || chars[end] == '.') {
+                    end += 1;
+                }
+                let text: String = chars[i..end].iter().collect();
+                let number = text.parse::<f64>().map_err(|_| format!("Invalid number '{}'", text))?;
+                (Token::Number(number), end - i)
+            }
+            '$' => {
+                let end = name_end(&chars, i + 1);
+                (Token::Variable(chars[i + 1..end].iter().collect()), end - i)
+            }
+            c if is_name_start(c) => {
+                let mut end = name_end(&chars, i);
+                let mut name: String = chars[i..end].iter().collect();
+                //A single colon joins a prefix to a local name or `*`
+                if chars.get(end) == Some(&':') && chars.get(end + 1) != Some(&':') {
+                    if chars.get(end + 1) == Some(&'*') {
+                        tokens.push(Token::PrefixStar);
+                        i = end + 2;
+                        continue;
+                    }
+                    let local_end = name_end(&chars, end + 1);
+                    if local_end == end + 1 {
+                        return Err(format!("Invalid name '{}:'", name));
+                    }
+                    name = chars[end + 1..local_end].iter().collect();
+                    end = local_end;
+                }
+                (Token::Name(name), end - i)
+            }
+            other => return Err(format!("Unexpected character '{}'", other)),
+        };
+        tokens.push(token);
+        i += length;
+    }
+    Ok(tokens)
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn name_end(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() && (chars[end].is_alphanumeric() || matches!(chars[end], '_' | '-' | '.')) {
+        end += 1;
+    }
+    end
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Namespace,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    SelfNode,
+}
+
+impl Axis {
+    fn from_name(name: &str) -> Option<Axis> {
+        Some(match name {
+            "ancestor" => Axis::Ancestor,
+            "ancestor-or-self" => Axis::AncestorOrSelf,
+            "attribute" => Axis::Attribute,
+            "child" => Axis::Child,
+            "descendant" => Axis::Descendant,
+            "descendant-or-self" => Axis::DescendantOrSelf,
+            "following" => Axis::Following,
+            "following-sibling" => Axis::FollowingSibling,
+            "namespace" => Axis::Namespace,
+            "parent" => Axis::Parent,
+            "preceding" => Axis::Preceding,
+            "preceding-sibling" => Axis::PrecedingSibling,
+            "self" => Axis::SelfNode,
+            _ => return None,
+        })
+    }
+
+    //Reverse axes number their nodes from the context node outwards
+    fn is_reverse(self) -> bool {
+        matches!(self, Axis::Ancestor | Axis::AncestorOrSelf | Axis::Preceding | Axis::PrecedingSibling)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NodeTest {
+    //Any node of the axis' principal type, `*` or `prefix:*`
+    Any,
+    Name(String),
+    Node,
+    Text,
+    Comment,
+    ProcessingInstruction(Option<String>),
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Union,
+}
+
+#[derive(Debug, Clone)]
+enum PathStart {
+    Root,
+    Context,
+    Filter(Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Literal(String),
+    Number(f64),
+    Function(String, Vec<Expr>),
+    Filter(Box<Expr>, Vec<Expr>),
+    Path(PathStart, Vec<Step>),
+}
+
+//Name, minimum and maximum number of arguments
+const FUNCTIONS: [(&str, usize, usize); 26] = [
+    ("last", 0, 0),
+    ("position", 0, 0),
+    ("count", 1, 1),
+    ("id", 1, 1),
+    ("local-name", 0, 1),
+    ("name", 0, 1),
+    ("string", 0, 1),
+    ("concat", 2, usize::MAX),
+    ("starts-with", 2, 2),
+    ("contains", 2, 2),
+    ("substring-before", 2, 2),
+    ("substring-after", 2, 2),
+    ("substring", 2, 3),
+    ("string-length", 0, 1),
+    ("normalize-space", 0, 1),
+    ("translate", 3, 3),
+    ("boolean", 1, 1),
+    ("not", 1, 1),
+    ("true", 0, 0),
+    ("false", 0, 0),
+    ("lang", 1, 1),
+    ("number", 0, 1),
+    ("sum", 1, 1),
+    ("floor", 1, 1),
+    ("ceiling", 1, 1),
+    ("round", 1, 1),
+];
+
+const NODE_TYPES: [&str; 4] = ["node", "text", "comment", "processing-instruction"];
+
+//Parsing and evaluation recurse through the expression, so deep nesting and long expressions are refused before
+//they can exhaust the stack
+const MAX_DEPTH: usize = 32;
+const MAX_TOKENS: usize = 1000;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    //Expressions currently being parsed inside each other, through brackets, predicates and arguments
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("Expected {:?} but found {:?}", expected, token)),
+            None => Err(format!("Expected {:?} but the expression ended", expected)),
+        }
+    }
+
+    //Operator names are only keywords where an operator is expected
+    fn peek_operator_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(found)) if found == name)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("Expressions can be nested at most {} deep", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let expr = self.parse_or();
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while self.peek_operator_name("or") {
+            self.advance();
+            left = Expr::Binary(Operator::Or, Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_equality()?;
+        while self.peek_operator_name("and") {
+            self.advance();
+            left = Expr::Binary(Operator::And, Box::new(left), Box::new(self.parse_equality()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_equality(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_relational()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Equal) => Operator::Equal,
+                Some(Token::NotEqual) => Operator::NotEqual,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.parse_relational()?));
+        }
+    }
+
+    fn parse_relational(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_additive()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Less) => Operator::Less,
+                Some(Token::LessEqual) => Operator::LessEqual,
+                Some(Token::Greater) => Operator::Greater,
+                Some(Token::GreaterEqual) => Operator::GreaterEqual,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.parse_additive()?));
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => Operator::Add,
+                Some(Token::Minus) => Operator::Subtract,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.parse_multiplicative()?));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Star) => Operator::Multiply,
+                Some(Token::Name(name)) if name == "div" => Operator::Divide,
+                Some(Token::Name(name)) if name == "mod" => Operator::Modulo,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        let mut negations = 0;
+        while self.peek() == Some(&Token::Minus) {
+            self.advance();
+            negations += 1;
+        }
+        let mut expr = self.parse_union()?;
+        for _ in 0..negations {
+            expr = Expr::Negate(Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn parse_union(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_path()?;
+        while self.peek() == Some(&Token::Pipe) {
+            self.advance();
+            left = Expr::Binary(Operator::Union, Box::new(left), Box::new(self.parse_path()?));
+        }
+        Ok(left)
+    }
+
+    fn starts_filter(&self) -> bool {
+        match self.peek() {
+            Some(Token::Literal(_)) | Some(Token::Number(_)) | Some(Token::LeftParen) | Some(Token::Variable(_)) => true,
+            Some(Token::Name(name)) => self.peek_at(1) == Some(&Token::LeftParen) && !NODE_TYPES.contains(&name.as_str()),
+            _ => false,
+        }
+    }
+
+    fn parse_path(&mut self) -> Result<Expr, String> {
+        if self.starts_filter() {
+            let primary = self.parse_primary()?;
+            let predicates = self.parse_predicates()?;
+            let filter = if predicates.is_empty() { primary } else { Expr::Filter(Box::new(primary), predicates) };
+            let mut steps = vec![];
+            match self.peek() {
+                Some(Token::Slash) => {
+                    self.advance();
+                    self.parse_relative_path(&mut steps)?;
+                }
+                Some(Token::DoubleSlash) => {
+                    self.advance();
+                    steps.push(descendant_or_self());
+                    self.parse_relative_path(&mut steps)?;
+                }
+                _ => return Ok(filter),
+            }
+            return Ok(Expr::Path(PathStart::Filter(Box::new(filter)), steps));
+        }
+        let mut steps = vec![];
+        match self.peek() {
+            Some(Token::Slash) => {
+                self.advance();
+                //A lone `/` selects the root
+                if self.starts_step() {
+                    self.parse_relative_path(&mut steps)?;
+                }
+                Ok(Expr::Path(PathStart::Root, steps))
+            }
+            Some(Token::DoubleSlash) => {
+                self.advance();
+                steps.push(descendant_or_self());
+                self.parse_relative_path(&mut steps)?;
+                Ok(Expr::Path(PathStart::Root, steps))
+            }
+            _ => {
+                self.parse_relative_path(&mut steps)?;
+                Ok(Expr::Path(PathStart::Context, steps))
+            }
+        }
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Dot) | Some(Token::DotDot) | Some(Token::At) | Some(Token::Star) | Some(Token::PrefixStar) | Some(Token::Name(_))
+        )
+    }
+
+    fn parse_relative_path(&mut self, steps: &mut Vec<Step>) -> Result<(), String> {
+        steps.push(self.parse_step()?);
+        loop {
+            match self.peek() {
+                Some(Token::Slash) => {
+                    self.advance();
+                }
+                Some(Token::DoubleSlash) => {
+                    self.advance();
+                    steps.push(descendant_or_self());
+                }
+                _ => return Ok(()),
+            }
+            steps.push(self.parse_step()?);
+        }
+    }
+
+    fn parse_step(&mut self) -> Result<Step, String> {
+        match self.peek() {
+            Some(Token::Dot) => {
+                self.advance();
+                return Ok(Step { axis: Axis::SelfNode, test: NodeTest::Node, predicates: vec![] });
+            }
+            Some(Token::DotDot) => {
+                self.advance();
+                return Ok(Step { axis: Axis::Parent, test: NodeTest::Node, predicates: vec![] });
+            }
+            _ => {}
+        }
+        let axis = match (self.peek(), self.peek_at(1)) {
+            (Some(Token::At), _) => {
+                self.advance();
+                Axis::Attribute
+            }
+            (Some(Token::Name(name)), Some(Token::ColonColon)) => {
+                let axis = Axis::from_name(name).ok_or_else(|| format!("Unknown axis '{}'", name))?;
+                self.advance();
+                self.advance();
+                axis
+            }
+            _ => Axis::Child,
+        };
+        let test = match self.advance() {
+            Some(Token::Star) | Some(Token::PrefixStar) => NodeTest::Any,
+            Some(Token::Name(name)) if self.peek() == Some(&Token::LeftParen) && NODE_TYPES.contains(&name.as_str()) => {
+                self.advance();
+                let test = match name.as_str() {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    "comment" => NodeTest::Comment,
+                    _ => match self.peek() {
+                        Some(Token::Literal(target)) => {
+                            let target = target.clone();
+                            self.advance();
+                            NodeTest::ProcessingInstruction(Some(target))
+                        }
+                        _ => NodeTest::ProcessingInstruction(None),
+                    },
+                };
+                self.expect(Token::RightParen)?;
+                test
+            }
+            Some(Token::Name(name)) => NodeTest::Name(name.to_lowercase()),
+            Some(token) => return Err(format!("Expected a node test but found {:?}", token)),
+            None => return Err("Expected a node test but the expression ended".to_string()),
+        };
+        Ok(Step { axis, test, predicates: self.parse_predicates()? })
+    }
+
+    fn parse_predicates(&mut self) -> Result<Vec<Expr>, String> {
+        let mut predicates = vec![];
+        while self.peek() == Some(&Token::LeftBracket) {
+            self.advance();
+            predicates.push(self.parse_expr()?);
+            self.expect(Token::RightBracket)?;
+        }
+        Ok(predicates)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Literal(text)) => Ok(Expr::Literal(text)),
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::LeftParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(Token::RightParen)?;
+                Ok(expr)
+            }
+            Some(Token::Variable(name)) => Err(format!("Variables are not supported: ${}", name)),
+            Some(Token::Name(name)) => {
+                self.expect(Token::LeftParen)?;
+                let mut args = vec![];
+                if self.peek() != Some(&Token::RightParen) {
+                    args.push(self.parse_expr()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.advance();
+                        args.push(self.parse_expr()?);
+                    }
+                }
+                self.expect(Token::RightParen)?;
+                let (_, min, max) = FUNCTIONS
+                    .iter()
+                    .find(|(function, _, _)| *function == name)
+                    .ok_or_else(|| format!("Unknown function '{}'", name))?;
+                if args.len() < *min || args.len() > *max {
+                    return Err(format!("Wrong number of arguments for {}()", name));
+                }
+                Ok(Expr::Function(name, args))
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn descendant_or_self() -> Step {
+    Step { axis: Axis::DescendantOrSelf, test: NodeTest::Node, predicates: vec![] }
+}
+
+//A node of the document, attributes are addressed by their element and position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XNode {
+    Node(NodeId),
+    Attribute(NodeId, usize),
+}
+
+#[derive(Debug, Clone)]
+pub enum XValue {
+    Nodes(Vec<XNode>),
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct XPath {
+    expr: Expr,
+}
+
+impl XPath {
+    pub fn parse(source: &str) -> Result<XPath, String> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0, depth: 0 };
+        if parser.tokens.is_empty() {
+            return Err("Empty XPath expression".to_string());
+        }
+        if parser.tokens.len() > MAX_TOKENS {
+            return Err(format!("XPath expressions can have at most {} tokens", MAX_TOKENS));
+        }
+        let expr = parser.parse_expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {:?}", token));
+        }
+        Ok(XPath { expr })
+    }
+}
+
+//Evaluates expressions against one document, the document order of its nodes is computed on first use
+pub struct Evaluator<'a> {
+    html: &'a Html,
+    order: OnceCell<HashMap<NodeId, usize>>,
+}
+
+struct Context {
+    node: XNode,
+    position: usize,
+    size: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(html: &'a Html) -> Self {
+        Evaluator { html, order: OnceCell::new() }
+    }
+
+    pub fn evaluate(&self, xpath: &XPath, context: NodeId) -> Result<XValue, String> {
+        self.eval(&xpath.expr, &Context { node: XNode::Node(context), position: 1, size: 1 })
+    }
+
+    fn node(&self, id: NodeId) -> NodeRef<'a, Node> {
+        self.html.tree.get(id).expect("node ids come from the same tree")
+    }
+
+    fn order_key(&self, node: &XNode) -> (usize, usize) {
+        let order = self.order.get_or_init(|| self.html.tree.root().descendants().enumerate().map(|(index, node)| (node.id(), index)).collect());
+        match node {
+            XNode::Node(id) => (order.get(id).copied().unwrap_or(usize::MAX), 0),
+            //Attributes come after their element and before its children
+            XNode::Attribute(id, index) => (order.get(id).copied().unwrap_or(usize::MAX), index + 1),
+        }
+    }
+
+    fn sort_nodes(&self, nodes: &mut Vec<XNode>) {
+        nodes.sort_by_key(|node| self.order_key(node));
+        nodes.dedup();
+    }
+
+    fn attribute(&self, id: NodeId, index: usize) -> Option<(&'a str, &'a str)> {
+        match self.node(id).value() {
+            Node::Element(element) => element.attrs().nth(index),
+            _ => None,
+        }
+    }
+
+    pub fn element(&self, node: &XNode) -> Option<ElementRef<'a>> {
+        match node {
+            XNode::Node(id) => ElementRef::wrap(self.node(*id)),
+            XNode::Attribute(_, _) => None,
+        }
+    }
+
+    pub fn string_value(&self, node: &XNode) -> String {
+        match node {
+            XNode::Attribute(id, index) => self.attribute(*id, *index).map(|(_, value)| value.to_string()).unwrap_or_default(),
+            XNode::Node(id) => {
+                let node = self.node(*id);
+                match node.value() {
+                    Node::Text(text) => text.text.to_string(),
+                    Node::Comment(comment) => comment.comment.to_string(),
+                    Node::ProcessingInstruction(instruction) => instruction.data.to_string(),
+                    _ => node
+                        .descendants()
+                        .filter_map(|descendant| match descendant.value() {
+                            Node::Text(text) => Some(&*text.text),
+                            _ => None,
+                        })
+                        .collect(),
+                }
+            }
+        }
+    }
+
+    fn local_name(&self, node: &XNode) -> String {
+        match node {
+            XNode::Attribute(id, index) => self.attribute(*id, *index).map(|(name, _)| name.to_string()).unwrap_or_default(),
+            XNode::Node(id) => match self.node(*id).value() {
+                Node::Element(element) => element.name().to_string(),
+                Node::ProcessingInstruction(instruction) => instruction.target.to_string(),
+                _ => String::new(),
+            },
+        }
+    }
+
+    fn matches_test(&self, node: &XNode, axis: Axis, test: &NodeTest) -> bool {
+        match node {
+            XNode::Attribute(_, _) => match test {
+                NodeTest::Any | NodeTest::Node => true,
+                NodeTest::Name(name) => self.local_name(node).eq_ignore_ascii_case(name),
+                _ => false,
+            },
+            XNode::Node(id) => {
+                let value = self.node(*id);
+                let value = value.value();
+                match test {
+                    NodeTest::Node => true,
+                    NodeTest::Text => matches!(value, Node::Text(_)),
+                    NodeTest::Comment => matches!(value, Node::Comment(_)),
+                    NodeTest::ProcessingInstruction(target) => match value {
+                        Node::ProcessingInstruction(instruction) => target.as_ref().is_none_or(|target| *target == *instruction.target),
+                        _ => false,
+                    },
+                    //Only elements are named on every axis but attribute
+                    NodeTest::Any => axis != Axis::Attribute && matches!(value, Node::Element(_)),
+                    NodeTest::Name(name) => axis != Axis::Attribute && matches!(value, Node::Element(element) if element.name().eq_ignore_ascii_case(name)),
+                }
+            }
+        }
+    }
+
+    //Nodes along an axis, in proximity order
+    fn axis_nodes(&self, node: &XNode, axis: Axis) -> Vec<XNode> {
+        let id = match node {
+            XNode::Node(id) => *id,
+            XNode::Attribute(owner, _) => {
+                return match axis {
+                    Axis::SelfNode => vec![*node],
+                    Axis::Parent => vec![XNode::Node(*owner)],
+                    Axis::Ancestor | Axis::AncestorOrSelf => {
+                        let mut nodes = if axis == Axis::AncestorOrSelf { vec![*node] } else { vec![] };
+                        nodes.push(XNode::Node(*owner));
+                        nodes.extend(self.node(*owner).ancestors().map(|ancestor| XNode::Node(ancestor.id())));
+                        nodes
+                    }
+                    //An attribute is followed by its element's content
+                    Axis::Following => {
+                        let mut nodes: Vec<XNode> = self.node(*owner).descendants().skip(1).map(|descendant| XNode::Node(descendant.id())).collect();
+                        nodes.extend(self.axis_nodes(&XNode::Node(*owner), Axis::Following));
+                        nodes
+                    }
+                    Axis::Preceding => self.axis_nodes(&XNode::Node(*owner), Axis::Preceding),
+                    _ => vec![],
+                };
+            }
+        };
+        let node = self.node(id);
+        let wrap = |node: NodeRef<Node>| XNode::Node(node.id());
+        match axis {
+            Axis::SelfNode => vec![XNode::Node(id)],
+            Axis::Child => node.children().map(wrap).collect(),
+            Axis::Descendant => node.descendants().skip(1).map(wrap).collect(),
+            Axis::DescendantOrSelf => node.descendants().map(wrap).collect(),
+            Axis::Parent => node.parent().map(wrap).into_iter().collect(),
+            Axis::Ancestor => node.ancestors().map(wrap).collect(),
+            Axis::AncestorOrSelf => std::iter::once(node).chain(node.ancestors()).map(wrap).collect(),
+            Axis::FollowingSibling => node.next_siblings().map(wrap).collect(),
+            Axis::PrecedingSibling => node.prev_siblings().map(wrap).collect(),
+            Axis::Following => std::iter::once(node)
+                .chain(node.ancestors())
+                .flat_map(|ancestor| ancestor.next_siblings())
+                .flat_map(|sibling| sibling.descendants())
+                .map(wrap)
+                .collect(),
+            Axis::Preceding => std::iter::once(node)
+                .chain(node.ancestors())
+                .flat_map(|ancestor| ancestor.prev_siblings())
+                .flat_map(|sibling| sibling.descendants().collect::<Vec<_>>().into_iter().rev())
+                .map(wrap)
+                .collect(),
+            Axis::Attribute => match node.value() {
+                Node::Element(element) => (0..element.attrs().count()).map(|index| XNode::Attribute(id, index)).collect(),
+                _ => vec![],
+            },
+            Axis::Namespace => vec![],
+        }
+    }
+
+    //Keeps the nodes for which every predicate holds, positions are counted in the order given
+    fn filter(&self, mut nodes: Vec<XNode>, predicates: &[Expr]) -> Result<Vec<XNode>, String> {
+        for predicate in predicates {
+            let size = nodes.len();
+            let mut kept = vec![];
+            for (index, node) in nodes.into_iter().enumerate() {
+                let context = Context { node, position: index + 1, size };
+                let keep = match self.eval(predicate, &context)? {
+                    XValue::Number(number) => number == (index + 1) as f64,
+                    other => self.boolean(&other),
+                };
+                if keep {
+                    kept.push(node);
+                }
+            }
+            nodes = kept;
+        }
+        Ok(nodes)
+    }
+
+    fn eval_steps(&self, mut nodes: Vec<XNode>, steps: &[Step]) -> Result<Vec<XNode>, String> {
+        for step in steps {
+            let single_context = nodes.len() == 1;
+            let mut result = vec![];
+            for node in &nodes {
+                let candidates: Vec<XNode> = self.axis_nodes(node, step.axis).into_iter().filter(|candidate| self.matches_test(candidate, step.axis, &step.test)).collect();
+                result.extend(self.filter(candidates, &step.predicates)?);
+            }
+            //One context node on a forward axis already yields distinct nodes in document order
+            if !single_context || step.axis.is_reverse() {
+                self.sort_nodes(&mut result);
+            }
+            nodes = result;
+        }
+        Ok(nodes)
+    }
+
+    fn eval_nodes(&self, expr: &Expr, context: &Context) -> Result<Vec<XNode>, String> {
+        match self.eval(expr, context)? {
+            XValue::Nodes(nodes) => Ok(nodes),
+            _ => Err("Expression does not evaluate to a node-set".to_string()),
+        }
+    }
+
+    fn eval(&self, expr: &Expr, context: &Context) -> Result<XValue, String> {
+        Ok(match expr {
+            Expr::Literal(text) => XValue::String(text.clone()),
+            Expr::Number(number) => XValue::Number(*number),
+            Expr::Negate(inner) => XValue::Number(-self.number(&self.eval(inner, context)?)),
+            Expr::Path(start, steps) => {
+                let start = match start {
+                    PathStart::Root => vec![XNode::Node(self.html.tree.root().id())],
+                    PathStart::Context => vec![context.node],
+                    PathStart::Filter(filter) => self.eval_nodes(filter, context)?,
+                };
+                XValue::Nodes(self.eval_steps(start, steps)?)
+            }
+            Expr::Filter(primary, predicates) => {
+                let mut nodes = self.eval_nodes(primary, context)?;
+                self.sort_nodes(&mut nodes);
+                XValue::Nodes(self.filter(nodes, predicates)?)
+            }
+            Expr::Function(name, args) => self.call(name, args, context)?,
+            Expr::Binary(operator, left, right) => match operator {
+                Operator::Or => XValue::Boolean(self.boolean(&self.eval(left, context)?) || self.boolean(&self.eval(right, context)?)),
+                Operator::And => XValue::Boolean(self.boolean(&self.eval(left, context)?) && self.boolean(&self.eval(right, context)?)),
+                Operator::Union => {
+                    let mut nodes = self.eval_nodes(left, context)?;
+                    nodes.extend(self.eval_nodes(right, context)?);
+                    self.sort_nodes(&mut nodes);
+                    XValue::Nodes(nodes)
+                }
+                Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide | Operator::Modulo => {
+                    let left = self.number(&self.eval(left, context)?);
+                    let right = self.number(&self.eval(right, context)?);
+                    XValue::Number(match operator {
+                        Operator::Add => left + right,
+                        Operator::Subtract => left - right,
+                        Operator::Multiply => left * right,
+                        Operator::Divide => left / right,
+                        _ => left % right,
+                    })
+                }
+                _ => XValue::Boolean(self.compare(*operator, &self.eval(left, context)?, &self.eval(right, context)?)),
+            },
+        })
+    }
+
+    pub fn string(&self, value: &XValue) -> String {
+        match value {
+            XValue::Nodes(nodes) => nodes.first().map(|node| self.string_value(node)).unwrap_or_default(),
+            XValue::Boolean(boolean) => boolean.to_string(),
+            XValue::Number(number) => number_to_string(*number),
+            XValue::String(text) => text.clone(),
+        }
+    }
+
+    fn number(&self, value: &XValue) -> f64 {
+        match value {
+            XValue::Number(number) => *number,
+            XValue::Boolean(boolean) => if *boolean { 1.0 } else { 0.0 },
+            other => string_to_number(&self.string(other)),
+        }
+    }
+
+    fn boolean(&self, value: &XValue) -> bool {
+        match value {
+            XValue::Nodes(nodes) => !nodes.is_empty(),
+            XValue::Boolean(boolean) => *boolean,
+            XValue::Number(number) => *number != 0.0 && !number.is_nan(),
+            XValue::String(text) => !text.is_empty(),
+        }
+    }
+
+    //Comparisons involving node-sets hold when they hold for any node in the set
+    fn compare(&self, operator: Operator, left: &XValue, right: &XValue) -> bool {
+        match (left, right) {
+            (XValue::Nodes(nodes), XValue::Boolean(_)) => self.compare(operator, &XValue::Boolean(!nodes.is_empty()), right),
+            (XValue::Boolean(_), XValue::Nodes(nodes)) => self.compare(operator, left, &XValue::Boolean(!nodes.is_empty())),
+            (XValue::Nodes(nodes), _) => nodes.iter().any(|node| self.compare(operator, &XValue::String(self.string_value(node)), right)),
+            (_, XValue::Nodes(nodes)) => nodes.iter().any(|node| self.compare(operator, left, &XValue::String(self.string_value(node)))),
+            _ => match operator {
+                Operator::Equal | Operator::NotEqual => {
+                    let equal = match (left, right) {
+                        (XValue::Boolean(_), _) | (_, XValue::Boolean(_)) => self.boolean(left) == self.boolean(right),
+                        (XValue::Number(_), _) | (_, XValue::Number(_)) => self.number(left) == self.number(right),
+                        _ => self.string(left) == self.string(right),
+                    };
+                    equal == (operator == Operator::Equal)
+                }
+                _ => {
+                    let (left, right) = (self.number(left), self.number(right));
+                    match operator {
+                        Operator::Less => left < right,
+                        Operator::LessEqual => left <= right,
+                        Operator::Greater => left > right,
+                        _ => left >= right,
+                    }
+                }
+            },
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Expr], context: &Context) -> Result<XValue, String> {
+        let arg = |index: usize| self.eval(&args[index], context);
+        let string_arg = |index: usize| -> Result<String, String> {
+            match args.get(index) {
+                Some(expr) => Ok(self.string(&self.eval(expr, context)?)),
+                None => Ok(self.string_value(&context.node)),
+            }
+        };
+        //Functions taking an optional node-set use its first node, or the context node
+        let node_arg = || -> Result<Option<XNode>, String> {
+            match args.first() {
+                Some(expr) => {
+                    let mut nodes = self.eval_nodes(expr, context)?;
+                    self.sort_nodes(&mut nodes);
+                    Ok(nodes.first().copied())
+                }
+                None => Ok(Some(context.node)),
+            }
+        };
+        Ok(match name {
+            "last" => XValue::Number(context.size as f64),
+            "position" => XValue::Number(context.position as f64),
+            "count" => XValue::Number(self.eval_nodes(&args[0], context)?.len() as f64),
+            "id" => {
+                let ids: Vec<String> = match arg(0)? {
+                    XValue::Nodes(nodes) => nodes.iter().flat_map(|node| self.string_value(node).split_whitespace().map(str::to_string).collect::<Vec<_>>()).collect(),
+                    other => self.string(&other).split_whitespace().map(str::to_string).collect(),
+                };
+                let nodes = self
+                    .html
+                    .tree
+                    .root()
+                    .descendants()
+                    .filter(|node| matches!(node.value(), Node::Element(element) if element.attr("id").is_some_and(|id| ids.iter().any(|wanted| wanted == id))))
+                    .map(|node| XNode::Node(node.id()))
+                    .collect();
+                XValue::Nodes(nodes)
+            }
+            "local-name" | "name" => XValue::String(node_arg()?.map(|node| self.local_name(&node)).unwrap_or_default()),
+            "string" => XValue::String(string_arg(0)?),
+            "concat" => {
+                let mut text = String::new();
+                for index in 0..args.len() {
+                    text.push_str(&string_arg(index)?);
+                }
+                XValue::String(text)
+            }
+            "starts-with" => XValue::Boolean(string_arg(0)?.starts_with(&string_arg(1)?)),
+            "contains" => XValue::Boolean(string_arg(0)?.contains(&string_arg(1)?)),
+            "substring-before" => {
+                let text = string_arg(0)?;
+                let pattern = string_arg(1)?;
+                XValue::String(text.find(&pattern).map(|index| text[..index].to_string()).unwrap_or_default())
+            }
+            "substring-after" => {
+                let text = string_arg(0)?;
+                let pattern = string_arg(1)?;
+                XValue::String(text.find(&pattern).map(|index| text[index + pattern.len()..].to_string()).unwrap_or_default())
+            }
+            "substring" => {
+                let text = string_arg(0)?;
+                let start = round(self.number(&arg(1)?));
+                let end = match args.get(2) {
+                    Some(length) => start + round(self.number(&self.eval(length, context)?)),
+                    None => f64::INFINITY,
+                };
+                //Characters are numbered from 1 and kept when start <= position < start + length
+                XValue::String(
+                    text.chars()
+                        .enumerate()
+                        .filter(|(index, _)| {
+                            let position = (*index + 1) as f64;
+                            position >= start && position < end
+                        })
+                        .map(|(_, c)| c)
+                        .collect(),
+                )
+            }
+            "string-length" => XValue::Number(string_arg(0)?.chars().count() as f64),
+            "normalize-space" => XValue::String(string_arg(0)?.split_whitespace().collect::<Vec<_>>().join(" ")),
+            "translate" => {
+                let from: Vec<char> = string_arg(1)?.chars().collect();
+                let to: Vec<char> = string_arg(2)?.chars().collect();
+                XValue::String(
+                    string_arg(0)?
+                        .chars()
+                        .filter_map(|c| match from.iter().position(|&f| f == c) {
+                            Some(index) => to.get(index).copied(),
+                            None => Some(c),
+                        })
+                        .collect(),
+                )
+            }
+            "boolean" => XValue::Boolean(self.boolean(&arg(0)?)),
+            "not" => XValue::Boolean(!self.boolean(&arg(0)?)),
+            "true" => XValue::Boolean(true),
+            "false" => XValue::Boolean(false),
+            "lang" => {
+                let wanted = string_arg(0)?.to_lowercase();
+                let start = match context.node {
+                    XNode::Node(id) | XNode::Attribute(id, _) => self.node(id),
+                };
+                let lang = std::iter::once(start).chain(start.ancestors()).find_map(|node| match node.value() {
+                    Node::Element(element) => element.attr("lang").or_else(|| element.attr("xml:lang")),
+                    _ => None,
+                });
+                XValue::Boolean(lang.is_some_and(|lang| {
+                    let lang = lang.to_lowercase();
+                    lang == wanted || lang.starts_with(&format!("{}-", wanted))
+                }))
+            }
+            "number" => XValue::Number(match args.first() {
+                Some(expr) => self.number(&self.eval(expr, context)?),
+                None => string_to_number(&self.string_value(&context.node)),
+            }),
+            "sum" => XValue::Number(self.eval_nodes(&args[0], context)?.iter().map(|node| string_to_number(&self.string_value(node))).sum()),
+            "floor" => XValue::Number(self.number(&arg(0)?).floor()),
+            "ceiling" => XValue::Number(self.number(&arg(0)?).ceil()),
+            "round" => XValue::Number(round(self.number(&arg(0)?))),
+            other => return Err(format!("Unknown function '{}'", other)),
+        })
+    }
+}
+
+//XPath rounds halves up, towards positive infinity
+fn round(number: f64) -> f64 {
+    if number.is_nan() || number.is_infinite() {
+        return number;
+    }
+    (number + 0.5).floor()
+}
+
+fn string_to_number(text: &str) -> f64 {
+    let text = text.trim();
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit() || c == '.') || digits.matches('.').count() > 1 || digits == "." {
+        return f64::NAN;
+    }
+    text.parse().unwrap_or(f64::NAN)
+}
+
+fn number_to_string(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "Infinity".to_string() } else { "-Infinity".to_string() }
+    } else if number == 0.0 {
+        //Also turns -0 into 0
+        "0".to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <div id="list">
+            <a class="item" href="/one">One</a>
+            <a class="item special" href="/two">Two</a>
+            <span>  spaced   text </span>
+            <a href="/three">Three</a>
+        </div>
+    </body></html>"#;
+
+    fn evaluate(expression: &str) -> (Html, Result<XValue, String>) {
+        let html = Html::parse_document(PAGE);
+        let xpath = XPath::parse(expression).unwrap();
+        let value = Evaluator::new(&html).evaluate(&xpath, html.tree.root().id());
+        (html, value)
+    }
+
+    fn string(expression: &str) -> String {
+        let (html, value) = evaluate(expression);
+        Evaluator::new(&html).string(&value.unwrap())
+    }
+
+    fn count(expression: &str) -> usize {
+        match evaluate(expression).1.unwrap() {
+            XValue::Nodes(nodes) => nodes.len(),
+            value => panic!("Expected nodes, got {:?}", value),
+        }
+    }
+
+    #[test]
+    fn selects_nodes_with_predicates() {
+        assert_eq!(count("//a"), 3);
+        assert_eq!(count("//a[@class]"), 2);
+        assert_eq!(count("//a[contains(@class, 'special')]"), 1);
+        assert_eq!(string("//a[2]"), "Two");
+        assert_eq!(string("//a[last()]/@href"), "/three");
+        assert_eq!(string("(//a)[position() = 1]"), "One");
+        assert_eq!(count("//div[@id='list']/*"), 4);
+    }
+
+    #[test]
+    fn follows_axes() {
+        assert_eq!(string("//span/preceding-sibling::a[1]"), "Two");
+        assert_eq!(string("//span/following-sibling::a"), "Three");
+        assert_eq!(string("name(//a[1]/parent::*)"), "div");
+        assert_eq!(count("//a[1]/ancestor::*"), 3);
+    }
+
+    #[test]
+    fn evaluates_functions_and_operators() {
+        assert_eq!(string("normalize-space(//span)"), "spaced text");
+        assert_eq!(string("count(//a) * 2 + 1"), "7");
+        assert_eq!(string("concat(//a[1], '-', //a[2])"), "One-Two");
+        assert_eq!(string("substring-after(//a[2]/@href, '/')"), "two");
+        assert_eq!(string("translate('abc', 'abc', 'ABC')"), "ABC");
+        assert_eq!(string("--3"), "3");
+        assert_eq!(string("10 div 4"), "2.5");
+        assert_eq!(string("count(//a) = 3 and not(//table)"), "true");
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(XPath::parse("").is_err());
+        assert!(XPath::parse("//a[").is_err());
+        assert!(XPath::parse("unknown()").is_err());
+        assert!(XPath::parse("$var").is_err());
+    }
+
+    #[test]
+    fn limits_nesting_and_length() {
+        assert!(XPath::parse(&format!("{}1{}", "(".repeat(20), ")".repeat(20))).is_ok());
+        assert!(XPath::parse(&format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000))).is_err());
+        assert!(XPath::parse(&format!("//a{}", "[1]".repeat(100))).is_ok());
+        assert!(XPath::parse(&format!("{}1{}", "//a[".repeat(40), "]".repeat(40))).is_err());
+        assert!(XPath::parse(&vec!["1"; 1000].join("+")).is_err());
+    }
+}
@@ -1,4 +1,7 @@
 use cfg_if::cfg_if;
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
 
 cfg_if! {
     // https://github.com/rustwasm/console_error_panic_hook#readme
@@ -16,4 +19,68 @@ cfg_if! {
 pub struct RouteContext2<T> {
     pub state: T,
     pub cookies: Vec<String>,
-}
\ No newline at end of file
+}
+
+//Broken down UTC time, computed from a unix timestamp in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UtcTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    //0 = Sunday
+    pub weekday: u32,
+}
+
+impl UtcTime {
+    pub fn from_millis(millis: u64) -> Self {
+        let secs = millis / 1000;
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+        let (year, month, day) = civil_from_days(days);
+        UtcTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: ((rem % 3600) / 60) as u32,
+            second: (rem % 60) as u32,
+            //1970-01-01 was a Thursday
+            weekday: ((days + 4).rem_euclid(7)) as u32,
+        }
+    }
+}
+
+//Days since 1970-01-01 to (year, month, day), see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+//Resolves after `ms` milliseconds using the runtime's setTimeout
+pub async fn sleep(ms: u64) {
+    let promise = Promise::new(&mut |resolve, _reject| {
+        let set_timeout = Reflect::get(&js_sys::global(), &JsValue::from_str("setTimeout"))
+            .ok()
+            .and_then(|set_timeout| set_timeout.dyn_into::<Function>().ok());
+        match set_timeout {
+            Some(set_timeout) => {
+                let _ = set_timeout.call2(&JsValue::NULL, &resolve, &JsValue::from_f64(ms as f64));
+            }
+            None => {
+                let _ = resolve.call0(&JsValue::NULL);
+            }
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}